@@ -0,0 +1,57 @@
+//! The terminal bell, rung for the events below depending on the shell options: `nobeep` keeps it
+//! quiet, `visualbell` flashes the screen instead, and `beepfinish` and `beepjobs` ring it for
+//! finished commands and jobs.
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::{options::ShellOption, OPTIONS};
+
+/// How long a command has to run for its end to ring the bell with `beepfinish`.
+pub const FINISH_AFTER: Duration = Duration::from_secs(10);
+
+/// How long the screen stays inverted for a visual bell.
+const FLASH: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Nothing completes the word before the cursor.
+    NoCompletion,
+    /// A command ran for at least [`FINISH_AFTER`].
+    Finished,
+    /// A background job is done.
+    JobDone,
+}
+
+/// Rings the bell for `event` if the options ask for it and stdout is a terminal.
+pub fn ring(event: Event) {
+    let Ok(options) = OPTIONS.try_lock() else {
+        return;
+    };
+
+    let wanted = match event {
+        Event::NoCompletion => true,
+        Event::Finished => options.is_set(ShellOption::BeepFinish),
+        Event::JobDone => options.is_set(ShellOption::BeepJobs),
+    };
+    if !wanted || options.is_set(ShellOption::NoBeep) || !termion::is_tty(&io::stdout()) {
+        return;
+    }
+
+    let visual = options.is_set(ShellOption::VisualBell);
+    drop(options);
+
+    let mut stdout = io::stdout();
+    let _ = if visual {
+        // reverse video, then back
+        write!(stdout, "\x1b[?5h")
+            .and_then(|()| stdout.flush())
+            .map(|()| std::thread::sleep(FLASH))
+            .and_then(|()| write!(stdout, "\x1b[?5l"))
+    } else {
+        write!(stdout, "\x07")
+    }
+    .and_then(|()| stdout.flush());
+}
@@ -0,0 +1,69 @@
+//! Hooks run whenever `cd` changes the working directory, added with the `chpwd` builtin, and
+//! `ls` before them with `set -o autols`.
+//!
+//! A hook changing the directory itself doesn't run the hooks again.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, MutexGuard, PoisonError,
+};
+
+use async_recursion::async_recursion;
+
+use crate::{error, options::ShellOption, Command, OPTIONS};
+
+static HOOKS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Whether the hooks are running, for a `cd` in one of them not to run them again.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn hooks() -> MutexGuard<'static, Vec<String>> {
+    HOOKS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Adds `command` to the end of the hooks, unless it is already one of them.
+pub fn add(command: String) {
+    let mut hooks = hooks();
+    if !hooks.contains(&command) {
+        hooks.push(command);
+    }
+}
+
+/// Removes the hook `command`, returning whether it was one.
+#[must_use]
+pub fn remove(command: &str) -> bool {
+    let mut hooks = hooks();
+    let len = hooks.len();
+    hooks.retain(|hook| hook != command);
+    hooks.len() != len
+}
+
+/// Removes every hook.
+pub fn clear() {
+    hooks().clear();
+}
+
+/// Returns the hooks, in the order they run.
+#[must_use]
+pub fn list() -> Vec<String> {
+    hooks().clone()
+}
+
+/// Runs `ls` with `set -o autols`, then the hooks, after the working directory changed.
+#[async_recursion]
+pub(crate) async fn run() {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let autols = OPTIONS.lock().await.is_set(ShellOption::AutoLs);
+    let commands = autols.then(|| String::from("ls")).into_iter().chain(list());
+
+    for command in commands {
+        if let (Err(error), _) = Command::run(&command).await {
+            error!("chpwd: {error}");
+        }
+    }
+
+    RUNNING.store(false, Ordering::SeqCst);
+}
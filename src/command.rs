@@ -1,20 +1,69 @@
-use tokio::{io, process};
-
-use std::time::Duration;
+use std::{
+    fmt::Display,
+    io,
+    os::{
+        fd::{OwnedFd, RawFd},
+        unix::process::{CommandExt, ExitStatusExt},
+    },
+    process::{self, ExitStatus},
+};
 
 use crate::{
-    error,
+    condition, errexit, error, glob, guard, job,
     lang::{
         builtin::Builtin,
-        parser::{self, Parser},
+        parser::{self, error::ErrorKind, Parser},
         scanner::Scanner,
     },
+    list::List,
+    log,
+    options::ShellOption,
+    priority::{self, Priority},
+    prompt,
+    redirect::{Plan, Redirect},
+    rusage::{self, ExecutionReport},
+    subshell, variables,
+    word::Word,
+    OPTIONS,
 };
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Command {
-    pub(crate) keyword: String,
-    pub(crate) args: Vec<String>,
+    /// The `NAME=VALUE`s before the keyword, set in the environment of the command only, or as
+    /// shell variables without a keyword.
+    pub(crate) assignments: Vec<(String, Word)>,
+    /// The keyword followed by the arguments.
+    pub(crate) words: Vec<Word>,
+    pub(crate) redirects: Vec<Redirect>,
+    /// The list run instead of a keyword, like a builtin.
+    pub(crate) compound: Option<Compound>,
+}
+
+/// A command list run as a single command, with the redirections of the command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Compound {
+    /// `( LIST )`, run in a subshell.
+    Subshell(Box<List>),
+    /// `{ LIST; }`, run in the shell itself.
+    Group(Box<List>),
+    /// `if LIST; then LIST; [elif LIST; then LIST;]... [else LIST;] fi`, running the list after
+    /// the first condition that succeeds, or the one after `else` if none does.
+    If {
+        /// The conditions, each with the list it runs.
+        branches: Vec<(List, List)>,
+        otherwise: Option<Box<List>>,
+    },
+    /// `case WORD in PATTERN[|PATTERN]...) [LIST] ;; ... esac`, running the list of the first
+    /// arm with a glob pattern matching `WORD`.
+    Case {
+        word: Word,
+        /// The patterns of each arm, with the list it runs.
+        arms: Vec<(Vec<Word>, Option<List>)>,
+    },
+    /// `[[ EXPRESSION ]]`, testing the expression like `test` with its words expanded without
+    /// splitting them, `&&` and `||` joining expressions, and glob patterns after `==` and
+    /// `!=`.
+    Test(Vec<Word>),
 }
 
 impl Command {
@@ -25,13 +74,10 @@ impl Command {
     /// # Errors
     /// This function also uses the [`error!`] macro to report errors to stdout.
     ///
-    /// # Panics
-    ///
-    /// Panics if the spawned command process' exit code could not be extracted.
-    ///
     /// # Returns
     ///
-    /// This function returns the exit code of the process being executed and the time it took.
+    /// This function returns the exit code of the process being executed, or 128 plus the
+    /// number of the signal that killed it.
     ///
     /// It returns an exit code of 1 if waiting for the process to finish failed.
     ///
@@ -40,49 +86,387 @@ impl Command {
     ///
     /// # Command aliases
     ///
-    /// If the command is a key inside of the `rshell::ALIASES`. It executes the aliased command.
-    async fn interpret(&self) -> i32 {
-        let mut args = self.args.clone();
-        args.insert(0, self.keyword.clone());
-
-        match Builtin::run(&args).await {
-            Ok(code) => code,
-            Err(command) => {
-                let command = command.to_string();
-
-                if command.is_empty() {
-                    0
+    /// Aliases were already replaced by their values when the line was scanned.
+    pub(crate) async fn interpret(&self) -> i32 {
+        let command = match self.expand().await {
+            Ok(command) => command,
+            Err(error) => {
+                error!("{error}");
+                return 1;
+            }
+        };
+
+        if !command.allowed().await {
+            return 1;
+        }
+        command.trace().await;
+
+        if command.is_empty() {
+            for (name, value) in &command.assignments {
+                log!(Debug, "variables", "{name}={}", value.text());
+                variables::set(name, &value.text());
+            }
+            return 0;
+        }
+
+        // `exec` redirects the shell itself, other builtins only while they run
+        if command.keyword() == "exec" {
+            if let Err(error) = Plan::new(&command.redirects).and_then(Plan::apply_to_shell) {
+                error!("{error}");
+                return 1;
+            }
+            return command.builtin().await;
+        }
+
+        if command.is_builtin() {
+            return command.run_builtin(Vec::new()).await;
+        }
+
+        match command.spawn(Vec::new()) {
+            Ok(process) => command.wait(process).await,
+            Err(code) => code,
+        }
+    }
+
+    /// Returns the command with its words expanded, as it runs at this point, or the message of
+    /// a `${NAME:?WORD}` parameter that isn't set.
+    pub(crate) async fn expand(&self) -> Result<Self, String> {
+        let mut assignments = Vec::with_capacity(self.assignments.len());
+        for (name, value) in &self.assignments {
+            assignments.push((name.clone(), Word::from(value.expand().await?)));
+        }
+
+        let mut words = Vec::with_capacity(self.words.len());
+        for word in &self.words {
+            words.extend(word.fields().await?.into_iter().map(Word::from));
+        }
+
+        let mut redirects = Vec::with_capacity(self.redirects.len());
+        for redirect in &self.redirects {
+            redirects.push(redirect.expand().await?);
+        }
+
+        Ok(Self {
+            assignments,
+            words,
+            redirects,
+            compound: self.compound.clone(),
+        })
+    }
+
+    /// Prints the expanded command to stderr after `$PS4`, `+ ` by default, with `set -x`.
+    /// Compound commands aren't printed, the commands in them are.
+    pub(crate) async fn trace(&self) {
+        if self.compound.is_some()
+            || (self.words.is_empty() && self.assignments.is_empty())
+            || !OPTIONS.lock().await.is_set(ShellOption::XTrace)
+        {
+            return;
+        }
+
+        let prompt = prompt::trace().await;
+        crate::errln!("{prompt}{self}");
+    }
+
+    /// Returns whether the guards let the command run, see [`guard::allows`].
+    pub(crate) async fn allowed(&self) -> bool {
+        self.words.is_empty() || guard::allows(&self.texts()).await
+    }
+
+    /// Returns whether the command runs in the shell, as a builtin or a compound command.
+    pub(crate) fn is_builtin(&self) -> bool {
+        self.compound.is_some() || self.keyword().parse::<Builtin>().is_ok()
+    }
+
+    /// Returns whether there is nothing to run, like for a line with only redirections.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.compound.is_none()
+    }
+
+    fn keyword(&self) -> String {
+        self.words.first().map(Word::text).unwrap_or_default()
+    }
+
+    /// Returns the text of the keyword followed by the arguments.
+    fn texts(&self) -> Vec<String> {
+        self.words.iter().map(Word::text).collect()
+    }
+
+    /// Runs the command as a builtin, with `pipes` and its redirections in place of the shell's
+    /// descriptors while it runs.
+    pub(crate) async fn run_builtin(&self, pipes: Vec<(RawFd, OwnedFd)>) -> i32 {
+        if pipes.is_empty() && self.redirects.is_empty() {
+            return self.builtin().await;
+        }
+
+        match Plan::piped(pipes, &self.redirects) {
+            Ok(plan) => plan.scope(self.builtin()).await,
+            Err(error) => {
+                error!("{error}");
+                1
+            }
+        }
+    }
+
+    async fn builtin(&self) -> i32 {
+        match &self.compound {
+            Some(Compound::Subshell(list)) => return subshell::run(list).await,
+            Some(Compound::Group(list)) => return list.interpret().await,
+            Some(Compound::If {
+                branches,
+                otherwise,
+            }) => return Self::conditional(branches, otherwise.as_deref()).await,
+            Some(Compound::Case { word, arms }) => return Self::case(word, arms).await,
+            Some(Compound::Test(words)) => return Self::test(words).await,
+            None => {}
+        }
+
+        // the environment of a builtin is the shell's, until it returns
+        let saved = self
+            .assignments
+            .iter()
+            .map(|(name, value)| {
+                let saved = std::env::var_os(name);
+                std::env::set_var(name, value.text());
+                (name, saved)
+            })
+            .collect::<Vec<_>>();
+
+        let code = match Builtin::run(&self.texts()).await {
+            Ok(code) => {
+                log!(Debug, "builtin", "{} exited with {code}", self.keyword());
+                code
+            }
+            Err(error) => {
+                error!("{error}");
+                1
+            }
+        };
+
+        for (name, value) in saved {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+
+        code
+    }
+
+    /// Spawns the command as a process, with `pipes` and its redirections installed.
+    ///
+    /// Returns the exit code of the command if it couldn't be spawned.
+    pub(crate) fn spawn(&self, pipes: Vec<(RawFd, OwnedFd)>) -> Result<process::Child, i32> {
+        // descriptors opened with `exec` are inherited, then redirected
+        let plan = match Plan::piped(pipes, &self.redirects) {
+            Ok(plan) => plan,
+            Err(error) => {
+                error!("{error}");
+                return Err(1);
+            }
+        };
+
+        let words = self.texts();
+        let mut process = process::Command::new(&words[0]);
+        process.args(&words[1..]);
+        process.envs(
+            self.assignments
+                .iter()
+                .map(|(name, value)| (name, value.text())),
+        );
+        let priority = priority::current();
+        // SAFETY: the installers only call async-signal-safe functions.
+        unsafe {
+            process.pre_exec(plan.installer());
+            if priority != Priority::default() {
+                process.pre_exec(priority.installer());
+            }
+        }
+        let process = process.spawn();
+        drop(plan);
+
+        match process {
+            Ok(process) => {
+                log!(
+                    Debug,
+                    "spawn",
+                    "spawned {} {:?} with pid {}",
+                    words[0],
+                    &words[1..],
+                    process.id()
+                );
+                Ok(process)
+            }
+            Err(error) => {
+                if let io::ErrorKind::NotFound = error.kind() {
+                    error!("command not found: {}", words[0]);
                 } else {
-                    let process = process::Command::new(command.clone())
-                        .args(self.args.clone())
-                        .spawn();
-
-                    match process {
-                        Ok(mut process) => match process.wait().await {
-                            Ok(process) => process.code().unwrap(),
-                            Err(error) => {
-                                error!("{error}");
-                                1
-                            }
-                        },
-                        Err(error) => {
-                            let kind = error.kind();
-                            if let io::ErrorKind::NotFound = kind {
-                                error!("command not found: {command}");
-                            } else {
-                                error!("{error}");
-                            }
-                            2
-                        }
-                    }
+                    error!("{error}");
                 }
+                Err(2)
+            }
+        }
+    }
+
+    /// Waits for `process`, spawned by [`Command::spawn`], and returns its exit code. With
+    /// `set -o cmdstats`, the usage of a foreground process is recorded.
+    ///
+    /// Processes are waited for with `wait4` on a blocking thread rather than through the async
+    /// runtime, which learns that children exited from a `SIGCHLD` pipe that forked subshells
+    /// share with the shell, so either could miss the other's signals.
+    pub(crate) async fn wait(&self, process: process::Child) -> i32 {
+        let measure = !job::in_background() && OPTIONS.lock().await.is_set(ShellOption::CmdStats);
+
+        match wait(process.id()).await {
+            Ok((status, usage)) => {
+                if measure {
+                    rusage::record(usage);
+                }
+                log!(Debug, "spawn", "{} exited with {status}", self.keyword());
+                exit_code(status)
+            }
+            Err(error) => {
+                error!("{error}");
+                1
             }
         }
     }
 
     #[must_use]
     pub fn new(keyword: String, args: Vec<String>) -> Self {
-        Self { keyword, args }
+        Self {
+            words: std::iter::once(keyword)
+                .chain(args)
+                .map(Word::from)
+                .collect(),
+            assignments: Vec::new(),
+            redirects: Vec::new(),
+            compound: None,
+        }
+    }
+
+    /// Returns the `NAME=VALUE`s before the keyword.
+    #[must_use]
+    pub fn assignments(&self) -> &[(String, Word)] {
+        &self.assignments
+    }
+
+    /// Returns the keyword followed by the arguments, before they are expanded.
+    #[must_use]
+    pub fn words(&self) -> &[Word] {
+        &self.words
+    }
+
+    /// Returns the redirections of the command, in the order they are applied.
+    #[must_use]
+    pub fn redirects(&self) -> &[Redirect] {
+        &self.redirects
+    }
+
+    /// Returns the compound command run instead of a keyword, if any.
+    #[must_use]
+    pub const fn compound(&self) -> Option<&Compound> {
+        self.compound.as_ref()
+    }
+
+    /// Runs the list after the first of the conditions of `branches` that succeeds, or
+    /// `otherwise` if none does, returning its exit code, or 0 if no list ran.
+    async fn conditional(branches: &[(List, List)], otherwise: Option<&List>) -> i32 {
+        for (condition, list) in branches {
+            let code = errexit::tested(condition.interpret()).await;
+            if subshell::is_exiting() {
+                return code;
+            }
+            if code == 0 {
+                return list.interpret().await;
+            }
+        }
+
+        match otherwise {
+            Some(list) => list.interpret().await,
+            None => 0,
+        }
+    }
+
+    /// Runs the list of the first of `arms` with a pattern matching `word`, returning its exit
+    /// code, or 0 if no list ran.
+    async fn case(word: &Word, arms: &[(Vec<Word>, Option<List>)]) -> i32 {
+        let word = match word.expand().await {
+            Ok(word) => word,
+            Err(error) => {
+                error!("{error}");
+                return 1;
+            }
+        };
+
+        for (patterns, list) in arms {
+            for pattern in patterns {
+                let pattern = match pattern.expand().await {
+                    Ok(pattern) => pattern,
+                    Err(error) => {
+                        error!("{error}");
+                        return 1;
+                    }
+                };
+
+                if glob::matches(&pattern, &word, false) {
+                    return match list {
+                        Some(list) => list.interpret().await,
+                        None => 0,
+                    };
+                }
+            }
+        }
+
+        0
+    }
+
+    /// Evaluates the expression of a `[[ ]]` made of `words`, returning 0 if it is true, 1 if it
+    /// is false and 2 if it is invalid. Only the words written without anything to expand can
+    /// be operators, so `[[ $NAME ]]` tests a value like `-f` as a string.
+    async fn test(words: &[Word]) -> i32 {
+        let mut texts = Vec::with_capacity(words.len());
+        for word in words {
+            match word.expand().await {
+                Ok(text) => texts.push(text),
+                Err(error) => {
+                    error!("{error}");
+                    return 1;
+                }
+            }
+        }
+
+        let args = words
+            .iter()
+            .zip(&texts)
+            .map(|(word, text)| condition::Arg {
+                text,
+                operator: word.is_text(),
+            })
+            .collect::<Vec<_>>();
+
+        match condition::eval(&args, true) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(error) => {
+                error!("[[: {error}");
+                2
+            }
+        }
+    }
+
+    /// Returns whether `command` needs more lines to be run, as it ends inside a quote or after
+    /// a `\\` or an operator like `&&`, or for the bodies of its here-documents.
+    pub async fn is_incomplete(command: &str) -> bool {
+        let mut scanner = Scanner::new(command);
+        let tokens = scanner.scan_tokens().await;
+        if scanner.is_incomplete() {
+            return true;
+        }
+
+        Parser::new(tokens)
+            .parse_tokens()
+            .is_err_and(|error| error.kinds().any(ErrorKind::is_incomplete))
     }
 
     /// Runs a command from a string.
@@ -90,27 +474,115 @@ impl Command {
     /// # Errors
     ///
     /// This function will return an error if parsing throws an error.
-    pub async fn run(command: &str) -> (Result<i32, parser::error::Error>, Duration) {
-        let mut scanner = Scanner::new(command);
-        let tokens = scanner.scan_tokens().await;
-
-        let mut parser = Parser::new(tokens);
-        let commands = match parser.parse_tokens() {
-            Ok(commands) => commands,
+    pub async fn run(command: &str) -> (Result<i32, parser::error::Error>, ExecutionReport) {
+        let before = rusage::start();
+        let list = match parser::parse(command).await {
+            Ok(Some(list)) => list,
+            Ok(None) => return (Ok(0), ExecutionReport::unrun(before)),
             Err(error) => {
-                return (Err(error), Duration::default());
+                log!(Debug, "parser", "{}", error.kind());
+                return (Err(error), ExecutionReport::unrun(before));
             }
         };
 
+        log!(Debug, "parser", "{list:?}");
+
         let start = tokio::time::Instant::now();
-        for command in commands {
-            let exit_code = command.interpret().await;
+        let exit_code = errexit::line(list.interpret()).await;
 
-            if exit_code != 0 {
-                return (Ok(exit_code), start.elapsed());
+        let report = ExecutionReport {
+            duration: start.elapsed(),
+            usage: rusage::finish(before),
+        };
+        (Ok(exit_code), report)
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(Compound::Subshell(list)) = &self.compound {
+            write!(f, "({list})")?;
+        } else if let Some(Compound::Group(list)) = &self.compound {
+            write!(f, "{{ {} }}", terminated(list))?;
+        } else if let Some(Compound::If {
+            branches,
+            otherwise,
+        }) = &self.compound
+        {
+            for (index, (condition, list)) in branches.iter().enumerate() {
+                let keyword = if index == 0 { "if" } else { "elif" };
+                write!(
+                    f,
+                    "{keyword} {} then {} ",
+                    terminated(condition),
+                    terminated(list)
+                )?;
+            }
+            if let Some(list) = otherwise {
+                write!(f, "else {} ", terminated(list))?;
             }
+            f.write_str("fi")?;
+        } else if let Some(Compound::Case { word, arms }) = &self.compound {
+            write!(f, "case {word} in")?;
+            for (patterns, list) in arms {
+                let patterns = patterns.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, " {})", patterns.join("|"))?;
+                if let Some(list) = list {
+                    write!(f, " {list}")?;
+                }
+                f.write_str(" ;;")?;
+            }
+            f.write_str(" esac")?;
+        } else if let Some(Compound::Test(words)) = &self.compound {
+            f.write_str("[[")?;
+            for word in words {
+                write!(f, " {word}")?;
+            }
+            f.write_str(" ]]")?;
+        } else {
+            let words = self
+                .assignments
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .chain(self.words.iter().map(ToString::to_string))
+                .collect::<Vec<_>>();
+            f.write_str(&words.join(" "))?;
+        }
+
+        for redirect in &self.redirects {
+            write!(f, " {redirect}")?;
         }
 
-        (Ok(0), start.elapsed())
+        Ok(())
+    }
+}
+
+/// Waits for the child `pid` with `wait4` on a blocking thread, returning how it exited and what
+/// it used.
+pub(crate) async fn wait(pid: u32) -> io::Result<(ExitStatus, rusage::Usage)> {
+    let pid = libc::pid_t::try_from(pid).map_err(io::Error::other)?;
+
+    match tokio::task::spawn_blocking(move || rusage::wait(pid)).await {
+        Ok(Ok((status, usage))) => Ok((ExitStatus::from_raw(status), usage)),
+        Ok(Err(error)) => Err(error),
+        Err(error) => Err(io::Error::other(error)),
+    }
+}
+
+/// Returns the exit code of a process that exited with `status`, or 128 plus the number of the
+/// signal that killed it.
+pub(crate) fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default())
+}
+
+/// Returns `list` followed by a `;` ending it, unless it ends with `&`.
+fn terminated(list: &List) -> String {
+    let list = list.to_string();
+    if list.ends_with('&') {
+        list
+    } else {
+        format!("{list};")
     }
 }
@@ -0,0 +1,153 @@
+//! Caches of the completion sources that are slow to read: the programs in `PATH`, directory
+//! listings and the output of completer programs.
+//!
+//! Entries are kept for `RSHELL_COMPLETION_TTL` seconds, 60 by default, `0` turning caching off.
+//! The programs and directories are also keyed by the modification times of their directories,
+//! so installing a program or creating a file shows up right away.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::Candidate;
+
+/// How long entries are kept, unless `RSHELL_COMPLETION_TTL` says otherwise.
+const DEFAULT_TTL: Duration = Duration::from_mins(1);
+
+/// A value kept for its key, since `at`.
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    at: Instant,
+}
+
+type Cache<K, V> = Mutex<Vec<Entry<K, V>>>;
+
+/// When a directory was last modified, if it exists.
+type Modified = Option<SystemTime>;
+
+/// The name of a directory entry, with whether it is a directory itself.
+type DirEntry = (String, bool);
+
+/// The programs in `PATH`, keyed by `PATH` and the modification times of its directories.
+static PROGRAMS: Cache<(String, Vec<Modified>), Vec<String>> = Mutex::new(Vec::new());
+
+/// The entries of directories, keyed by the directories and their modification times.
+static DIRS: Cache<(PathBuf, Modified), Vec<DirEntry>> = Mutex::new(Vec::new());
+
+/// The candidates of completer programs, keyed by the completer, the words before the one
+/// completed and that word.
+static COMPLETERS: Cache<(String, Vec<String>, String), Vec<Candidate>> = Mutex::new(Vec::new());
+
+fn lock<T>(cache: &Mutex<T>) -> MutexGuard<'_, T> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns how long entries are kept, from `RSHELL_COMPLETION_TTL`.
+fn ttl() -> Duration {
+    std::env::var("RSHELL_COMPLETION_TTL")
+        .ok()
+        .and_then(|seconds| seconds.trim().parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .unwrap_or(DEFAULT_TTL)
+}
+
+/// Returns the value cached for `key`, or computes it with `compute` and caches it.
+///
+/// The value is computed without holding the lock, so a slow one doesn't hold up the others.
+fn cached<K: PartialEq, V: Clone>(cache: &Cache<K, V>, key: K, compute: impl FnOnce() -> V) -> V {
+    let ttl = ttl();
+    if ttl.is_zero() {
+        return compute();
+    }
+
+    {
+        let mut entries = lock(cache);
+        entries.retain(|entry| entry.at.elapsed() < ttl);
+        if let Some(entry) = entries.iter().find(|entry| entry.key == key) {
+            return entry.value.clone();
+        }
+    }
+
+    let value = compute();
+
+    let mut entries = lock(cache);
+    entries.retain(|entry| entry.key != key);
+    entries.push(Entry {
+        key,
+        value: value.clone(),
+        at: Instant::now(),
+    });
+
+    value
+}
+
+fn modified(path: &Path) -> Modified {
+    path.metadata()
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Returns the names of the executable files in the directories of `PATH`.
+pub(super) fn programs() -> Vec<String> {
+    let path = std::env::var("PATH").unwrap_or_default();
+    let modified = path
+        .split(':')
+        .map(|dir| modified(Path::new(dir)))
+        .collect();
+
+    cached(&PROGRAMS, (path.clone(), modified), || {
+        let mut programs = Vec::new();
+
+        for dir in path.split(':') {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                continue;
+            };
+
+            programs.extend(
+                entries
+                    .filter_map(Result::ok)
+                    .filter(|entry| super::is_executable(&entry.path()))
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned()),
+            );
+        }
+
+        programs
+    })
+}
+
+/// Returns the names of the entries of `dir`, with whether they are directories.
+pub(super) fn entries(dir: &Path) -> Vec<DirEntry> {
+    // relative to the working directory, which may change
+    let key = (
+        std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf()),
+        modified(dir),
+    );
+
+    cached(&DIRS, key, || {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| {
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.path().is_dir(),
+                )
+            })
+            .collect()
+    })
+}
+
+/// Returns the candidates of `completer` for `prefix`, the word being typed after `words`.
+pub(super) fn complete(completer: &str, words: &[String], prefix: &str) -> Vec<Candidate> {
+    let key = (completer.to_string(), words.to_vec(), prefix.to_string());
+
+    cached(&COMPLETERS, key, || {
+        super::external::complete(completer, words, prefix)
+    })
+}
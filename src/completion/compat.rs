@@ -0,0 +1,206 @@
+//! Loads the simple cases of bash and fish completion definitions.
+//!
+//! Only static word lists and external completers are understood: `complete -W "words" cmd` and
+//! `complete -C program cmd` from bash, and the options and plain `-a` arguments of fish's
+//! `complete -c cmd` declarations, with their descriptions. Anything needing a function or a
+//! command substitution to run is skipped.
+
+use std::path::PathBuf;
+
+use super::{Candidate, Completions};
+use crate::user;
+
+/// Looks for bash and fish completion definitions of `command` and registers their words.
+pub(super) fn load(completions: &mut Completions, command: &str) {
+    for path in bash_paths(command) {
+        if let Ok(source) = std::fs::read_to_string(path) {
+            for line in source.lines() {
+                load_bash(completions, &split_words(line));
+            }
+        }
+    }
+
+    for path in fish_paths(command) {
+        if let Ok(source) = std::fs::read_to_string(path) {
+            for line in source.lines() {
+                load_fish(completions, command, &split_words(line));
+            }
+        }
+    }
+}
+
+fn bash_paths(command: &str) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if let Some(home) = user::home_dir() {
+        dirs.push(home.join(".local/share/bash-completion/completions"));
+    }
+    dirs.push(PathBuf::from("/usr/share/bash-completion/completions"));
+    dirs.push(PathBuf::from(
+        "/usr/local/share/bash-completion/completions",
+    ));
+    dirs.push(PathBuf::from("/etc/bash_completion.d"));
+
+    dirs.into_iter().map(|dir| dir.join(command)).collect()
+}
+
+fn fish_paths(command: &str) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if let Some(home) = user::home_dir() {
+        dirs.push(home.join(".config/fish/completions"));
+    }
+    dirs.push(PathBuf::from("/usr/share/fish/vendor_completions.d"));
+    dirs.push(PathBuf::from("/usr/local/share/fish/vendor_completions.d"));
+    dirs.push(PathBuf::from("/usr/share/fish/completions"));
+
+    dirs.into_iter()
+        .map(|dir| dir.join(format!("{command}.fish")))
+        .collect()
+}
+
+/// Registers the words of a bash `complete -W WORDLIST NAME...` declaration, or the program of a
+/// `complete -C PROGRAM NAME...` declaration.
+///
+/// Returns `false` if `words` is not such a declaration.
+pub(crate) fn load_bash(completions: &mut Completions, words: &[String]) -> bool {
+    if words.first().map(String::as_str) != Some("complete") {
+        return false;
+    }
+
+    let mut word_list = None;
+    let mut completer = None;
+    let mut names = Vec::new();
+    let mut words = words[1..].iter();
+
+    while let Some(word) = words.next() {
+        match word.as_str() {
+            "-W" => word_list = words.next(),
+            "-C" => completer = words.next(),
+            // options taking an argument
+            "-A" | "-F" | "-G" | "-P" | "-S" | "-X" | "-o" => {
+                words.next();
+            }
+            flag if flag.starts_with('-') => {}
+            name => names.push(name),
+        }
+    }
+
+    // word lists needing expansion can't be used as-is
+    let word_list = word_list.filter(|list| !list.contains(['$', '`']));
+
+    if word_list.is_none() && completer.is_none() {
+        return false;
+    }
+
+    for name in names {
+        if let Some(word_list) = word_list {
+            completions.add_words(name, word_list.split_whitespace().map(String::from));
+        }
+        if let Some(completer) = completer {
+            completions.set_completer(name, completer.clone());
+        }
+    }
+
+    true
+}
+
+/// Registers the options and static arguments of a fish `complete -c COMMAND` declaration.
+fn load_fish(completions: &mut Completions, command: &str, words: &[String]) {
+    if words.first().map(String::as_str) != Some("complete") {
+        return;
+    }
+
+    let mut is_command = false;
+    let mut condition = None;
+    let mut options = Vec::new();
+    let mut arguments = None;
+    let mut description = None;
+    let mut words = words[1..].iter();
+
+    while let Some(word) = words.next() {
+        let (flag, value) = match word.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (word.as_str(), None),
+        };
+
+        let mut value = || value.clone().or_else(|| words.next().cloned());
+
+        match flag {
+            "-c" | "--command" => is_command = value().as_deref() == Some(command),
+            "-s" | "--short-option" => options.extend(value().map(|option| format!("-{option}"))),
+            "-o" | "--old-option" => options.extend(value().map(|option| format!("-{option}"))),
+            "-l" | "--long-option" => options.extend(value().map(|option| format!("--{option}"))),
+            "-a" | "--arguments" => arguments = value(),
+            "-n" | "--condition" => condition = value(),
+            "-d" | "--description" => description = value(),
+            "-w" | "--wraps" => {
+                value();
+            }
+            _ => {}
+        }
+    }
+
+    if !is_command {
+        return;
+    }
+
+    completions.add_words(
+        command,
+        options.into_iter().map(|option| Candidate {
+            value: option,
+            description: description.clone(),
+        }),
+    );
+
+    // arguments behind a condition usually belong to a subcommand
+    if condition.is_none_or(|condition| condition == "__fish_use_subcommand") {
+        if let Some(arguments) = arguments {
+            let arguments = arguments
+                .split_whitespace()
+                .filter(|argument| !argument.contains(['(', '$']))
+                .map(|argument| match argument.split_once('\t') {
+                    Some((value, description)) => Candidate {
+                        value: value.to_string(),
+                        description: Some(description.to_string()),
+                    },
+                    None => Candidate::from(argument.to_string()),
+                });
+
+            completions.add_words(command, arguments);
+        }
+    }
+}
+
+/// Splits a line into words, removing quotes and stopping at an unquoted `#`.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            '#' if word.is_none() => break,
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    words
+}
@@ -0,0 +1,51 @@
+//! Delegates completion to external programs registered with `complete -C PROGRAM NAME`.
+//!
+//! This follows bash's protocol: the program is called with the command name, the word being
+//! completed and the previous word as arguments, with `COMP_LINE`, `COMP_POINT` and `COMP_CWORD`
+//! describing the command line. The words of the command line are also written to its stdin, one
+//! per line. It prints one candidate per line, optionally followed by a tab and a description,
+//! and is killed if it takes longer than a second so that a hung program can't freeze the editor.
+
+use std::process::Command;
+
+use itertools::Itertools;
+
+use super::{help, Candidate};
+
+/// Runs `completer` to complete `prefix`, the word being typed after `words`.
+///
+/// Returns no candidates if the program couldn't be run or was killed.
+pub(super) fn complete(completer: &str, words: &[String], prefix: &str) -> Vec<Candidate> {
+    let mut completer = completer.split_whitespace();
+    let Some(program) = completer.next() else {
+        return Vec::new();
+    };
+
+    let line = words.iter().map(String::as_str).chain([prefix]).join(" ");
+    let previous = words.last().map_or("", String::as_str);
+
+    let stdin = words.iter().map(String::as_str).chain([prefix]).join("\n");
+    let Some(output) = help::run(
+        Command::new(program)
+            .args(completer)
+            .args([words[0].as_str(), prefix, previous])
+            .env("COMP_LINE", &line)
+            .env("COMP_POINT", line.len().to_string())
+            .env("COMP_CWORD", words.len().to_string()),
+        &stdin,
+    ) else {
+        return Vec::new();
+    };
+
+    output
+        .lines()
+        .map(|line| match line.split_once('\t') {
+            Some((value, description)) => Candidate {
+                value: value.to_string(),
+                description: Some(description.to_string()),
+            },
+            None => Candidate::from(line.to_string()),
+        })
+        .filter(|candidate| !candidate.value.is_empty() && candidate.value.starts_with(prefix))
+        .collect()
+}
@@ -0,0 +1,136 @@
+//! Flag completion generated from a command's `--help` output, or its man page.
+//!
+//! Used for commands without completion definitions when the `helpcomplete` option is set.
+
+use std::{
+    io::{Read, Write},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use super::Candidate;
+
+/// How long a command gets to print its help, or candidates, before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns the flags documented by `command --help`, or by `man command` if that printed none.
+pub(super) fn flags(command: &str) -> Vec<Candidate> {
+    let flags = run(Command::new(command).arg("--help"), "")
+        .map(|help| parse(&help))
+        .unwrap_or_default();
+
+    if !flags.is_empty() {
+        return flags;
+    }
+
+    run(Command::new("man").arg(command).env("MANPAGER", "cat"), "")
+        .map(|man| parse(&man))
+        .unwrap_or_default()
+}
+
+/// Parses the lines of `help` that start with flags, like `-a, --all  do not ignore entries`.
+fn parse(help: &str) -> Vec<Candidate> {
+    let lines = help.lines().map(strip_overstrike).collect::<Vec<_>>();
+    let mut flags = Vec::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        let line = line.trim_start();
+        if !line.starts_with('-') {
+            continue;
+        }
+
+        // options and description are separated by at least two spaces
+        let (options, description) = match line.find("  ") {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+
+        // or the description is on the next line
+        let description = if description.is_empty() {
+            lines
+                .get(index + 1)
+                .map(|next| next.trim())
+                .filter(|next| !next.starts_with('-'))
+                .unwrap_or_default()
+        } else {
+            description
+        };
+
+        for option in options.split([',', ' ']) {
+            let option = option.split(['=', '[']).next().unwrap_or_default();
+
+            if option.len() > 1
+                && option.starts_with('-')
+                && option
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+            {
+                flags.push(Candidate {
+                    value: option.to_string(),
+                    description: (!description.is_empty()).then(|| description.to_string()),
+                });
+            }
+        }
+    }
+
+    flags
+}
+
+/// Runs `command` with a [`TIMEOUT`], writing `input` to its stdin, and returns what it printed
+/// on stdout.
+pub(super) fn run(command: &mut Command, input: &str) -> Option<String> {
+    let mut child = command
+        .stdin(if input.is_empty() {
+            Stdio::null()
+        } else {
+            Stdio::piped()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // the command doesn't have to read it, so it's written without waiting
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = input.to_string();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+
+    // read on another thread so that a full pipe can't block the command
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+
+    let start = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if start.elapsed() > TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let output = reader.join().ok()?;
+    Some(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Removes the backspace sequences man pages use for bold and underlined text.
+fn strip_overstrike(line: &str) -> String {
+    let mut stripped = String::new();
+
+    for c in line.chars() {
+        if c == '\u{8}' {
+            stripped.pop();
+        } else {
+            stripped.push(c);
+        }
+    }
+
+    stripped
+}
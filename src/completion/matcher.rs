@@ -0,0 +1,67 @@
+//! How candidates are matched against the word being completed, set with the `nocasecomplete`,
+//! `substringcomplete` and `fuzzycomplete` options.
+//!
+//! Candidates starting with the word are tried first. Candidates containing it, then candidates
+//! containing its characters in order, are only tried when the previous matchers found nothing.
+
+use crate::{options::ShellOption, OPTIONS};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Kind {
+    Prefix,
+    Substring,
+    Fuzzy,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Matcher {
+    pub(super) kind: Kind,
+    ignore_case: bool,
+}
+
+impl Matcher {
+    /// Returns the matchers enabled by the options, in the order they are tried.
+    pub(super) fn enabled() -> Vec<Self> {
+        let Ok(options) = OPTIONS.try_lock() else {
+            return vec![Self {
+                kind: Kind::Prefix,
+                ignore_case: false,
+            }];
+        };
+
+        let ignore_case = options.is_set(ShellOption::NoCaseComplete);
+
+        [
+            (Kind::Prefix, true),
+            (
+                Kind::Substring,
+                options.is_set(ShellOption::SubstringComplete),
+            ),
+            (Kind::Fuzzy, options.is_set(ShellOption::FuzzyComplete)),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(kind, _)| Self { kind, ignore_case })
+        .collect()
+    }
+
+    /// Returns whether `candidate` matches `word`, the word being completed.
+    pub(super) fn matches(self, word: &str, candidate: &str) -> bool {
+        if self.ignore_case {
+            let matcher = Self {
+                ignore_case: false,
+                ..self
+            };
+            return matcher.matches(&word.to_lowercase(), &candidate.to_lowercase());
+        }
+
+        match self.kind {
+            Kind::Prefix => candidate.starts_with(word),
+            Kind::Substring => candidate.contains(word),
+            Kind::Fuzzy => {
+                let mut chars = candidate.chars();
+                word.chars().all(|c| chars.any(|candidate| candidate == c))
+            }
+        }
+    }
+}
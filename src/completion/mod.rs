@@ -0,0 +1,313 @@
+//! Tab completion for the line editor.
+//!
+//! Commands are completed from builtins, aliases and `PATH`. Arguments are completed from the
+//! words registered for the command, either with the `complete` builtin or loaded from bash and
+//! fish completion definitions, or by an external completer program, falling back to file names.
+//! `cd`, `pushd` and `groot` are completed with directories, from the root of the git repository
+//! for `groot`. With the `helpcomplete` option, flags of other commands are completed from their
+//! `--help`.
+//! The slow sources are [`cache`]d, and warmed up as the shell starts.
+
+mod cache;
+mod compat;
+mod external;
+mod help;
+mod matcher;
+
+pub(crate) use compat::load_bash;
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use crate::{
+    git, lang::builtin::Builtin, options::ShellOption, user, ALIASES, COMPLETIONS, OPTIONS,
+};
+use matcher::{Kind, Matcher};
+
+/// A completion candidate.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Candidate {
+    pub value: String,
+    pub description: Option<String>,
+}
+
+impl From<String> for Candidate {
+    fn from(value: String) -> Self {
+        Self {
+            value,
+            description: None,
+        }
+    }
+}
+
+/// How a command's arguments are completed.
+#[derive(Clone, Debug, Default)]
+pub struct Spec {
+    pub words: Vec<Candidate>,
+    /// The program asked for candidates, see [`external`].
+    pub completer: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Completions {
+    specs: HashMap<String, Spec>,
+    /// Commands whose bash/fish definitions were already looked for.
+    loaded: HashSet<String>,
+    /// Flags parsed from the `--help` output of commands.
+    help: HashMap<String, Vec<Candidate>>,
+}
+
+impl Completions {
+    /// Adds `words` to the completions of `command`.
+    pub fn add_words(
+        &mut self,
+        command: &str,
+        words: impl IntoIterator<Item = impl Into<Candidate>>,
+    ) {
+        self.specs
+            .entry(command.to_string())
+            .or_default()
+            .words
+            .extend(words.into_iter().map(Into::into));
+    }
+
+    /// Makes `completer` complete the arguments of `command`.
+    pub fn set_completer(&mut self, command: &str, completer: String) {
+        self.specs.entry(command.to_string()).or_default().completer = Some(completer);
+    }
+
+    #[must_use]
+    pub fn get(&self, command: &str) -> Option<&Spec> {
+        self.specs.get(command)
+    }
+
+    pub fn remove(&mut self, command: &str) -> Option<Spec> {
+        self.specs.remove(command)
+    }
+
+    /// Returns the registered specs, sorted by command name.
+    #[must_use]
+    pub fn specs(&self) -> Vec<(&String, &Spec)> {
+        let mut specs = self.specs.iter().collect::<Vec<_>>();
+        specs.sort_by_key(|(command, _)| *command);
+        specs
+    }
+
+    /// Returns the spec of `command`, loading it from bash/fish definitions the first time.
+    fn spec(&mut self, command: &str) -> Option<&Spec> {
+        if !self.specs.contains_key(command) && self.loaded.insert(command.to_string()) {
+            compat::load(self, command);
+        }
+
+        self.specs.get(command)
+    }
+}
+
+/// Returns the candidates completing `prefix`, the word being typed after `words`.
+///
+/// Candidates are found by the first of the enabled [`matcher`]s finding any, then sorted and
+/// deduplicated, keeping the ones with a description. Directories end with a `/`.
+#[must_use]
+pub fn candidates(words: &[String], prefix: &str) -> Vec<Candidate> {
+    let mut candidates = Matcher::enabled()
+        .into_iter()
+        .map(|matcher| {
+            if words.is_empty() {
+                return commands(prefix, matcher);
+            }
+
+            let arguments = arguments(words, prefix, matcher);
+            if !arguments.is_empty() {
+                return arguments;
+            }
+
+            directories(&words[0], prefix, matcher)
+                .unwrap_or_else(|| files(prefix, matcher))
+                .into_iter()
+                .map(Candidate::from)
+                .collect()
+        })
+        .find(|candidates| !candidates.is_empty())
+        .unwrap_or_default();
+
+    candidates.sort_by(|a, b| {
+        a.value
+            .cmp(&b.value)
+            .then(a.description.is_none().cmp(&b.description.is_none()))
+    });
+    candidates.dedup_by(|a, b| a.value == b.value);
+    candidates
+}
+
+fn arguments(words: &[String], prefix: &str, matcher: Matcher) -> Vec<Candidate> {
+    // the editor runs outside of the async tasks, and nothing else holds the lock while editing
+    let Ok(mut completions) = COMPLETIONS.try_lock() else {
+        return Vec::new();
+    };
+
+    let Some(spec) = completions.spec(&words[0]).cloned() else {
+        if !prefix.starts_with('-')
+            || !OPTIONS
+                .try_lock()
+                .is_ok_and(|options| options.is_set(ShellOption::HelpComplete))
+        {
+            return Vec::new();
+        }
+
+        return completions
+            .help
+            .entry(words[0].clone())
+            .or_insert_with(|| help::flags(&words[0]))
+            .iter()
+            .filter(|flag| matcher.matches(prefix, &flag.value))
+            .cloned()
+            .collect();
+    };
+    drop(completions);
+
+    let mut candidates = spec
+        .words
+        .into_iter()
+        .filter(|word| matcher.matches(prefix, &word.value))
+        .collect::<Vec<_>>();
+
+    // completers match candidates themselves
+    if let (Some(completer), Kind::Prefix) = (spec.completer, matcher.kind) {
+        candidates.extend(cache::complete(&completer, words, prefix));
+    }
+
+    candidates
+}
+
+/// Returns the builtins, aliases described by their value, and programs completing `prefix`.
+fn commands(prefix: &str, matcher: Matcher) -> Vec<Candidate> {
+    // paths are completed as files
+    if prefix.contains('/') {
+        return files(prefix, matcher)
+            .into_iter()
+            .map(Candidate::from)
+            .collect();
+    }
+
+    let mut commands = Builtin::NAMES
+        .iter()
+        .filter(|name| matcher.matches(prefix, name))
+        .map(|name| Candidate::from(name.to_string()))
+        .collect::<Vec<_>>();
+
+    if let Ok(aliases) = ALIASES.try_lock() {
+        commands.extend(
+            aliases
+                .names()
+                .filter(|name| matcher.matches(prefix, name))
+                .map(|name| Candidate {
+                    value: name.clone(),
+                    description: aliases.get(name).cloned(),
+                }),
+        );
+    }
+
+    commands.extend(
+        cache::programs()
+            .into_iter()
+            .filter(|name| matcher.matches(prefix, name))
+            .map(Candidate::from),
+    );
+
+    commands
+}
+
+/// Returns the directories completing `prefix` for the directory commands, or `None` for other
+/// commands.
+///
+/// Inside a git repository, `groot` completes the directories of its root, and `cd` and `pushd`
+/// the ones of the root as paths relative to the working directory, like `../../tests/`, when no
+/// directory of the working directory completes `prefix`.
+fn directories(command: &str, prefix: &str, matcher: Matcher) -> Option<Vec<String>> {
+    let only_directories = |files: Vec<String>| {
+        files
+            .into_iter()
+            .filter(|file| file.ends_with('/'))
+            .collect::<Vec<_>>()
+    };
+    let root = std::env::current_dir()
+        .ok()
+        .and_then(|current_dir| Some((git::discover(&current_dir)?, current_dir)));
+
+    match command {
+        "cd" | "pushd" => {
+            let local = only_directories(files(prefix, matcher));
+            let Some((root, current_dir)) = root.filter(|_| local.is_empty()) else {
+                return Some(local);
+            };
+
+            let depth = current_dir.strip_prefix(&root).ok()?.components().count();
+            if depth == 0 {
+                return Some(local);
+            }
+            Some(only_directories(files(
+                &format!("{}{prefix}", "../".repeat(depth)),
+                matcher,
+            )))
+        }
+        "groot" => {
+            let (root, _) = root?;
+            let root = format!("{}/", root.display());
+
+            Some(
+                only_directories(files(&format!("{root}{prefix}"), matcher))
+                    .into_iter()
+                    .filter_map(|directory| directory.strip_prefix(&root).map(String::from))
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Returns the files and directories completing `prefix`, which may start with `~/`.
+fn files(prefix: &str, matcher: Matcher) -> Vec<String> {
+    let (dir, name) = match prefix.rfind('/') {
+        Some(index) => prefix.split_at(index + 1),
+        None => ("", prefix),
+    };
+
+    let read_from = match dir.strip_prefix("~/") {
+        Some(rest) => format!("{}/{rest}", user::home_dir().unwrap_or_default().display()),
+        None if dir.is_empty() => String::from("."),
+        None => dir.to_string(),
+    };
+
+    cache::entries(Path::new(&read_from))
+        .into_iter()
+        .filter_map(|(file_name, is_dir)| {
+            // hidden files are only completed when asked for
+            if !matcher.matches(name, &file_name)
+                || (file_name.starts_with('.') && !name.starts_with('.'))
+            {
+                return None;
+            }
+
+            let suffix = if is_dir { "/" } else { "" };
+            Some(format!("{dir}{file_name}{suffix}"))
+        })
+        .collect()
+}
+
+/// Reads the programs in `PATH` and the working directory into the completion cache in the
+/// background, so the first `TAB` doesn't wait for them.
+pub fn warm_up() {
+    tokio::task::spawn_blocking(|| {
+        cache::programs();
+        cache::entries(Path::new("."));
+    });
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
@@ -0,0 +1,327 @@
+//! Conditional expressions, as evaluated by the `test` and `[` builtins and `[[ ]]` commands.
+//!
+//! Expressions test files, like `-f PATH` or `PATH -nt PATH`, strings, like `-z STRING` or
+//! `STRING != STRING`, and integers, like `NUMBER -lt NUMBER`. They are negated with `!`,
+//! grouped with `(` and `)`, and joined with `-a` and `-o`, or `&&` and `||` inside `[[ ]]`,
+//! where `==` and `!=` also match glob patterns.
+//!
+//! Like POSIX `test`, a binary operator between two arguments is read as one before anything
+//! else, so `[ -f = -f ]` compares strings.
+
+use std::{
+    ffi::CString,
+    fs::Metadata,
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt, fs::MetadataExt, fs::PermissionsExt},
+    path::Path,
+};
+
+use crate::glob;
+
+/// An argument of an expression.
+#[derive(Clone, Copy, Debug)]
+pub struct Arg<'a> {
+    pub text: &'a str,
+    /// Whether the argument may be an operator, which the words of `[[ ]]` that parameters or
+    /// substitutions expanded to can't be.
+    pub operator: bool,
+}
+
+impl<'a> Arg<'a> {
+    /// Returns an argument that may be an operator, like all those of `test`.
+    #[must_use]
+    pub const fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            operator: true,
+        }
+    }
+}
+
+/// Evaluates the expression of `test` made of `args`, or of `[[ ]]` if `extended` is set.
+///
+/// # Errors
+///
+/// This function will return an error describing the problem if the expression is invalid, like
+/// `-lt` between strings that aren't integers.
+pub fn eval(args: &[Arg], extended: bool) -> Result<bool, String> {
+    // no argument is false, a single one is whether it isn't empty
+    match args {
+        [] => return Ok(false),
+        [arg] if !extended => return Ok(!arg.text.is_empty()),
+        _ => {}
+    }
+
+    let mut parser = Parser {
+        args,
+        current: 0,
+        extended,
+    };
+    let value = parser.or()?;
+
+    match parser.peek() {
+        None => Ok(value),
+        Some(arg) => Err(format!("{}: unexpected argument", arg.text)),
+    }
+}
+
+struct Parser<'a> {
+    args: &'a [Arg<'a>],
+    current: usize,
+    /// Whether the expression is the one of `[[ ]]`.
+    extended: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Arg<'a>> {
+        self.args.get(self.current).copied()
+    }
+
+    /// Returns whether the argument `offset` places ahead is the operator `operators`.
+    fn is_operator(&self, offset: usize, operators: &[&str]) -> bool {
+        self.args
+            .get(self.current + offset)
+            .is_some_and(|arg| arg.operator && operators.contains(&arg.text))
+    }
+
+    fn next(&mut self) -> Result<Arg<'a>, String> {
+        let arg = self
+            .peek()
+            .ok_or_else(|| String::from("argument expected"))?;
+        self.current += 1;
+        Ok(arg)
+    }
+
+    /// The operators joining expressions, `-o` and `-a`, or `||` and `&&` inside `[[ ]]`.
+    const fn joins(&self) -> (&'static str, &'static str) {
+        if self.extended {
+            ("||", "&&")
+        } else {
+            ("-o", "-a")
+        }
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut value = self.and()?;
+        while self.is_operator(0, &[self.joins().0]) {
+            self.current += 1;
+            let right = self.and()?;
+            value = value || right;
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut value = self.not()?;
+        while self.is_operator(0, &[self.joins().1]) {
+            self.current += 1;
+            let right = self.not()?;
+            value = value && right;
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        // `! = !` compares strings
+        if self.is_operator(0, &["!"]) && !self.is_binary(1) {
+            self.current += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    /// Returns whether the argument `offset` places ahead is a binary operator with an operand
+    /// on both sides.
+    fn is_binary(&self, offset: usize) -> bool {
+        self.current + offset + 1 < self.args.len() && self.is_operator(offset, BINARY)
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        if self.is_binary(1) {
+            let left = self.next()?.text;
+            let operator = self.next()?.text;
+            let right = self.next()?.text;
+            return self.binary(left, operator, right);
+        }
+
+        if self.is_operator(0, UNARY) && self.current + 1 < self.args.len() {
+            let operator = self.next()?.text;
+            let operand = self.next()?.text;
+            return unary(operator, operand);
+        }
+
+        if self.is_operator(0, &["("]) {
+            self.current += 1;
+            let value = self.or()?;
+            return match self.next() {
+                Ok(arg) if arg.operator && arg.text == ")" => Ok(value),
+                _ => Err(String::from("')' expected")),
+            };
+        }
+
+        let arg = self.next()?;
+        if arg.operator && self.extended && (UNARY.contains(&arg.text) || arg.text == "(") {
+            return Err(format!("{}: argument expected", arg.text));
+        }
+        Ok(!arg.text.is_empty())
+    }
+
+    fn binary(&self, left: &str, operator: &str, right: &str) -> Result<bool, String> {
+        Ok(match operator {
+            "=" | "==" if self.extended => glob::matches(right, left, false),
+            "!=" if self.extended => !glob::matches(right, left, false),
+            "=" | "==" => left == right,
+            "!=" => left != right,
+            "<" => left < right,
+            ">" => left > right,
+            "-eq" => integer(left)? == integer(right)?,
+            "-ne" => integer(left)? != integer(right)?,
+            "-lt" => integer(left)? < integer(right)?,
+            "-le" => integer(left)? <= integer(right)?,
+            "-gt" => integer(left)? > integer(right)?,
+            "-ge" => integer(left)? >= integer(right)?,
+            "-nt" => match (modified(left), modified(right)) {
+                (Some(left), Some(right)) => left > right,
+                (left, right) => left.is_some() && right.is_none(),
+            },
+            "-ot" => match (modified(left), modified(right)) {
+                (Some(left), Some(right)) => left < right,
+                (left, right) => left.is_none() && right.is_some(),
+            },
+            "-ef" => match (std::fs::metadata(left), std::fs::metadata(right)) {
+                (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+                _ => false,
+            },
+            _ => unreachable!("only binary operators are evaluated"),
+        })
+    }
+}
+
+/// The operators between two operands.
+const BINARY: &[&str] = &[
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// The operators before an operand.
+const UNARY: &[&str] = &[
+    "-b", "-c", "-d", "-e", "-f", "-g", "-G", "-h", "-k", "-L", "-n", "-O", "-p", "-r", "-s", "-S",
+    "-t", "-u", "-v", "-w", "-x", "-z",
+];
+
+fn unary(operator: &str, operand: &str) -> Result<bool, String> {
+    let path = Path::new(operand);
+    let metadata = std::fs::metadata(path);
+    let has = |test: fn(&Metadata) -> bool| metadata.as_ref().is_ok_and(test);
+
+    Ok(match operator {
+        "-n" => !operand.is_empty(),
+        "-z" => operand.is_empty(),
+        "-v" => crate::variables::get(operand).is_some(),
+        "-e" => metadata.is_ok(),
+        "-f" => has(Metadata::is_file),
+        "-d" => has(Metadata::is_dir),
+        "-b" => has(|metadata| metadata.file_type().is_block_device()),
+        "-c" => has(|metadata| metadata.file_type().is_char_device()),
+        "-p" => has(|metadata| metadata.file_type().is_fifo()),
+        "-S" => has(|metadata| metadata.file_type().is_socket()),
+        "-s" => has(|metadata| metadata.len() > 0),
+        "-g" => has(|metadata| metadata.permissions().mode() & 0o2000 != 0),
+        "-u" => has(|metadata| metadata.permissions().mode() & 0o4000 != 0),
+        "-k" => has(|metadata| metadata.permissions().mode() & 0o1000 != 0),
+        // SAFETY: geteuid and getegid always succeed.
+        "-O" => has(|metadata| metadata.uid() == unsafe { libc::geteuid() }),
+        "-G" => has(|metadata| metadata.gid() == unsafe { libc::getegid() }),
+        "-h" | "-L" => path.is_symlink(),
+        "-r" => access(path, libc::R_OK),
+        "-w" => access(path, libc::W_OK),
+        "-x" => access(path, libc::X_OK),
+        "-t" => {
+            let fd = integer(operand)?;
+            // SAFETY: isatty only looks at the descriptor, which may not be open.
+            i32::try_from(fd).is_ok_and(|fd| unsafe { libc::isatty(fd) } == 1)
+        }
+        _ => unreachable!("only unary operators are evaluated"),
+    })
+}
+
+/// Returns whether the shell may access `path` in `mode`, like `R_OK`.
+fn access(path: &Path, mode: libc::c_int) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `path` is null-terminated.
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+fn modified(path: &str) -> Option<std::time::SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn integer(text: &str) -> Result<i64, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{text}: integer expected"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(args: &[&str]) -> Result<bool, String> {
+        eval(
+            &args.iter().copied().map(Arg::new).collect::<Vec<_>>(),
+            false,
+        )
+    }
+
+    fn extended(args: &[&str]) -> Result<bool, String> {
+        eval(
+            &args.iter().copied().map(Arg::new).collect::<Vec<_>>(),
+            true,
+        )
+    }
+
+    #[test]
+    fn strings_and_integers() {
+        assert_eq!(test(&[]), Ok(false));
+        assert_eq!(test(&[""]), Ok(false));
+        assert_eq!(test(&["-z", ""]), Ok(true));
+        assert_eq!(test(&["a", "!=", "b"]), Ok(true));
+        assert_eq!(test(&["-f", "=", "-f"]), Ok(true));
+        assert_eq!(test(&["10", "-gt", "9"]), Ok(true));
+        assert!(test(&["a", "-lt", "1"]).is_err());
+    }
+
+    #[test]
+    fn joins_and_groups() {
+        assert_eq!(test(&["!", "a", "-o", "b", "-a", ""]), Ok(false));
+        assert_eq!(test(&["(", "a", "-o", "", ")", "-a", "b"]), Ok(true));
+        assert_eq!(extended(&["-n", "a", "&&", "!", "-z", "b"]), Ok(true));
+        assert!(test(&["(", "a"]).is_err());
+        assert!(test(&["a", "b", "c"]).is_err());
+    }
+
+    #[test]
+    fn patterns_only_match_in_brackets() {
+        assert_eq!(extended(&["main.rs", "==", "*.rs"]), Ok(true));
+        assert_eq!(test(&["main.rs", "=", "*.rs"]), Ok(false));
+
+        let quoted = [
+            Arg::new("main.rs"),
+            Arg::new("=="),
+            Arg {
+                text: "-n",
+                operator: false,
+            },
+        ];
+        assert_eq!(eval(&quoted, true), Ok(false));
+    }
+
+    #[test]
+    fn files() {
+        assert_eq!(test(&["-d", "/"]), Ok(true));
+        assert_eq!(test(&["-f", "/"]), Ok(false));
+        assert_eq!(test(&["-e", "/nonexistent/path"]), Ok(false));
+    }
+}
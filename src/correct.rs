@@ -0,0 +1,67 @@
+//! Spelling correction of paths, offered by `cd` when the `correct` option is set.
+//!
+//! Every missing component of a path is replaced by the directory next to it with the closest
+//! name, at most [`MAX_DISTANCE`] edits away.
+
+use std::path::{Component, Path, PathBuf};
+
+/// The largest number of inserted, deleted or substituted characters a correction may have.
+pub const MAX_DISTANCE: usize = 2;
+
+/// Returns `path` with its missing components corrected to existing directories, if they all
+/// could be.
+#[must_use]
+pub fn directory(path: &Path) -> Option<PathBuf> {
+    let mut corrected = PathBuf::new();
+
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            corrected.push(component);
+            continue;
+        };
+
+        if corrected.join(name).is_dir() {
+            corrected.push(name);
+            continue;
+        }
+
+        let name = name.to_string_lossy();
+        let parent = if corrected.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            corrected.as_path()
+        };
+
+        let closest = std::fs::read_dir(parent)
+            .ok()?
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .map(|candidate| (distance(&name, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= MAX_DISTANCE)
+            .min()?;
+
+        corrected.push(closest.1);
+    }
+
+    (corrected != path).then_some(corrected)
+}
+
+/// Returns the Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
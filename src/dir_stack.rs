@@ -0,0 +1,37 @@
+//! The directory stack of `pushd`, `popd` and `dirs`, which `cd` pushes to as well with
+//! `set -o autopushd`.
+//!
+//! The working directory is the top of the stack as `dirs` shows it, but isn't kept here.
+
+use std::{
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+static DIR_STACK: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn dir_stack() -> MutexGuard<'static, Vec<PathBuf>> {
+    DIR_STACK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Pushes `dir`, usually the directory just left.
+pub fn push(dir: PathBuf) {
+    dir_stack().push(dir);
+}
+
+/// Removes the directory pushed last and returns it.
+#[must_use]
+pub fn pop() -> Option<PathBuf> {
+    dir_stack().pop()
+}
+
+/// Removes every directory.
+pub fn clear() {
+    dir_stack().clear();
+}
+
+/// Returns the directories, the one pushed last first.
+#[must_use]
+pub fn all() -> Vec<PathBuf> {
+    dir_stack().iter().rev().cloned().collect()
+}
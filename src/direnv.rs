@@ -0,0 +1,160 @@
+//! Integration with [direnv](https://direnv.net), enabled with `set -o direnv`.
+
+use std::{
+    io,
+    iter::Peekable,
+    process::{Command, Stdio},
+    str::Chars,
+};
+
+use crate::{error, options::ShellOption, OPTIONS};
+
+/// Runs `direnv export json` and applies the returned diff to the environment.
+///
+/// Does nothing unless the `direnv` option is set. Problems are reported with [`error!`].
+pub async fn export() {
+    if !OPTIONS.lock().await.is_set(ShellOption::Direnv) {
+        return;
+    }
+
+    // direnv reports what it loads on stderr, so only stdout is captured, and it is waited for
+    // on a blocking thread like the commands the shell runs
+    let output = tokio::task::spawn_blocking(|| {
+        Command::new("direnv")
+            .args(["export", "json"])
+            .stdout(Stdio::piped())
+            .output()
+    })
+    .await
+    .unwrap_or_else(|error| Err(io::Error::other(error)));
+
+    let output = match output {
+        Ok(output) => output,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            error!("direnv: command not found");
+            return;
+        }
+        Err(error) => {
+            error!("direnv: {error}");
+            return;
+        }
+    };
+
+    let diff = String::from_utf8_lossy(&output.stdout);
+    if diff.trim().is_empty() {
+        return;
+    }
+
+    let Some(diff) = parse(&diff) else {
+        error!("direnv: could not parse exported environment");
+        return;
+    };
+
+    for (key, value) in diff {
+        match value {
+            Some(value) => std::env::set_var(key, value),
+            None => std::env::remove_var(key),
+        }
+    }
+}
+
+/// Parses the flat JSON object printed by `direnv export json`, whose values are either
+/// strings or `null` for variables that should be unset.
+fn parse(json: &str) -> Option<Vec<(String, Option<String>)>> {
+    let mut chars = json.chars().peekable();
+    let mut diff = Vec::new();
+
+    skip_whitespace(&mut chars);
+    if chars.next()? != '{' {
+        return None;
+    }
+
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        return Some(diff);
+    }
+
+    loop {
+        skip_whitespace(&mut chars);
+        let key = parse_string(&mut chars)?;
+
+        skip_whitespace(&mut chars);
+        if chars.next()? != ':' {
+            return None;
+        }
+
+        skip_whitespace(&mut chars);
+        let value = if chars.peek() == Some(&'n') {
+            for expected in "null".chars() {
+                if chars.next()? != expected {
+                    return None;
+                }
+            }
+            None
+        } else {
+            Some(parse_string(&mut chars)?)
+        };
+
+        diff.push((key, value));
+
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => {}
+            '}' => return Some(diff),
+            _ => return None,
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+
+    let mut string = String::new();
+
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => match chars.next()? {
+                'b' => string.push('\u{8}'),
+                'f' => string.push('\u{c}'),
+                'n' => string.push('\n'),
+                'r' => string.push('\r'),
+                't' => string.push('\t'),
+                'u' => {
+                    let mut code = parse_hex(chars)?;
+
+                    // surrogate pair
+                    if (0xD800..0xDC00).contains(&code) {
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let low = parse_hex(chars)?;
+                        code = 0x10000 + ((code - 0xD800) << 10) + (low.checked_sub(0xDC00)?);
+                    }
+
+                    string.push(char::from_u32(code)?);
+                }
+                c => string.push(c),
+            },
+            c => string.push(c),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let mut code = 0;
+
+    for _ in 0..4 {
+        code = code * 16 + chars.next()?.to_digit(16)?;
+    }
+
+    Some(code)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
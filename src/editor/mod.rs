@@ -0,0 +1,818 @@
+//! Interactive line editor used by the REPL.
+//!
+//! When stdin is a terminal, lines are edited in raw mode so that keys like the arrows,
+//! CTRL+C and the widget keybindings work. Otherwise lines are read as-is.
+
+mod picker;
+
+pub use picker::pick_to_file;
+
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, Stdin, Stdout, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use termion::{
+    event::Key,
+    input::{Keys, TermRead},
+    raw::{IntoRawMode, RawTerminal},
+};
+
+use crate::{
+    bell::{self, Event},
+    completion::{self, Candidate},
+    lang::scanner::Scanner,
+    options::ShellOption,
+    pager, snippet, OPTIONS, RED_FG_COLOR,
+};
+
+/// How the newlines of a command spanning several lines are shown on the edited line.
+const NEWLINE: char = '↵';
+
+/// The result of reading a line from the user.
+pub enum ReadLine {
+    /// A complete line, without its trailing newline.
+    Line(String),
+    /// The line was abandoned with CTRL+C.
+    Interrupted,
+    /// End of input was reached (CTRL+D on an empty line).
+    Eof,
+}
+
+/// Completion candidates cycled through with TAB once they were listed.
+struct Menu {
+    candidates: Vec<Candidate>,
+    /// The inserted candidate, if any.
+    selected: Option<usize>,
+    /// Where the completed word starts in the buffer.
+    start: usize,
+    /// Whether the candidates are drawn below the line, see [`ShellOption::MenuSelect`].
+    grid: bool,
+}
+
+#[derive(Default)]
+pub struct Editor {
+    buffer: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    history_index: usize,
+    draft: Vec<char>,
+    /// The length of the snippet placeholder selected at the cursor, typed over by the next
+    /// character.
+    selection: usize,
+    menu: Option<Menu>,
+    /// The lines put aside with `ALT+Q`, with their cursors, the last one coming back first.
+    pushed: Vec<(Vec<char>, usize)>,
+}
+
+impl Editor {
+    /// Completes the word before the cursor.
+    ///
+    /// A single candidate is inserted, otherwise the candidates' common prefix is inserted, and
+    /// if that doesn't add anything they are listed below the prompt, or in the pager if they
+    /// don't fit. Completing again then cycles through them.
+    fn complete(&mut self, menu: Option<Menu>, stdout: &mut RawTerminal<Stdout>) -> io::Result<()> {
+        if let Some(menu) = menu {
+            let next = menu
+                .selected
+                .map_or(0, |selected| (selected + 1) % menu.candidates.len());
+            self.select(menu, next);
+            return Ok(());
+        }
+
+        let before = self.buffer[..self.cursor].iter().collect::<String>();
+        let start = before
+            .rfind(char::is_whitespace)
+            .map_or(0, |index| index + 1);
+        let prefix = &before[start..];
+
+        // only the words of the command being typed matter
+        let words = before[..start]
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let words = match words
+            .iter()
+            .rposition(|word| ["&&", "||", "|", "&", ";"].contains(&word.as_str()))
+        {
+            Some(index) => &words[index + 1..],
+            None => &words[..],
+        };
+
+        let candidates = completion::candidates(words, prefix);
+
+        match candidates.as_slice() {
+            [] => bell::ring(Event::NoCompletion),
+            [candidate] => {
+                let suffix = if candidate.value.ends_with('/') {
+                    ""
+                } else {
+                    " "
+                };
+                self.replace_before_cursor(
+                    prefix.chars().count(),
+                    &format!("{}{suffix}", quote(&candidate.value)),
+                );
+            }
+            candidates => {
+                let common = common_prefix(candidates);
+
+                if common.len() > prefix.len() {
+                    self.replace_before_cursor(prefix.chars().count(), &common);
+                } else {
+                    let grid = OPTIONS
+                        .try_lock()
+                        .is_ok_and(|options| options.is_set(ShellOption::MenuSelect));
+                    if !grid {
+                        let mut listing = Vec::new();
+                        list(&mut listing, candidates)?;
+                        let listing = String::from_utf8_lossy(&listing);
+
+                        if pager::is_needed(listing.matches("\r\n").count()) {
+                            write!(stdout, "\r\n")?;
+                            stdout.suspend_raw_mode()?;
+                            let paged = pager::page(
+                                &listing
+                                    .trim_start_matches(['\r', '\n'])
+                                    .replace("\r\n", "\n"),
+                            );
+                            stdout.activate_raw_mode()?;
+                            paged?;
+                        } else {
+                            write!(stdout, "{listing}")?;
+                        }
+                    }
+
+                    self.menu = Some(Menu {
+                        candidates: candidates.to_vec(),
+                        selected: None,
+                        start: self.cursor - prefix.chars().count(),
+                        grid,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Inserts the candidate at `index` of `menu` in place of the completed word.
+    fn select(&mut self, mut menu: Menu, index: usize) {
+        let value = quote(&menu.candidates[index].value);
+        self.buffer.splice(menu.start..self.cursor, value.chars());
+        self.cursor = menu.start + value.chars().count();
+
+        menu.selected = Some(index);
+        self.menu = Some(menu);
+    }
+
+    /// Moves the selection of a grid `menu` with the arrow `key`.
+    fn navigate(&mut self, menu: Menu, key: Key) {
+        let len = menu.candidates.len();
+        let (_, columns) = layout(&menu.candidates);
+
+        let index = match (menu.selected, key) {
+            (None, _) => 0,
+            (Some(index), Key::Left) => (index + len - 1) % len,
+            (Some(index), Key::Right) => (index + 1) % len,
+            (Some(index), Key::Down) if index + columns < len => index + columns,
+            (Some(index), Key::Down) => index % columns,
+            (Some(index), Key::Up) if index >= columns => index - columns,
+            // wrap to the last row of the column
+            (Some(index), _) => {
+                let last = index + (len.div_ceil(columns) - 1) * columns;
+                if last < len {
+                    last
+                } else {
+                    last - columns
+                }
+            }
+        };
+
+        self.select(menu, index);
+    }
+
+    /// Adds a line to the history that can be navigated with the arrow keys.
+    pub fn add_history(&mut self, line: &str) {
+        if !line.trim().is_empty() && self.history.last().map(String::as_str) != Some(line) {
+            self.history.push(line.to_string());
+        }
+    }
+
+    fn delete_backward(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.buffer.remove(self.cursor);
+        }
+    }
+
+    fn delete_word_backward(&mut self) {
+        let mut start = self.cursor;
+
+        while start > 0 && self.buffer[start - 1] == ' ' {
+            start -= 1;
+        }
+        while start > 0 && self.buffer[start - 1] != ' ' {
+            start -= 1;
+        }
+
+        self.buffer.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    fn history_next(&mut self) {
+        if self.history_index >= self.history.len() {
+            return;
+        }
+
+        self.history_index = (self.history_index + 1..self.history.len())
+            .find(|&index| self.history_matches(index))
+            .unwrap_or(self.history.len());
+
+        self.buffer = if self.history_index == self.history.len() {
+            std::mem::take(&mut self.draft)
+        } else {
+            self.history[self.history_index].chars().collect()
+        };
+        self.cursor = self.buffer.len();
+    }
+
+    fn history_previous(&mut self) {
+        if self.history_index == self.history.len() {
+            self.draft = self.buffer.clone();
+        }
+
+        let Some(index) = (0..self.history_index)
+            .rev()
+            .find(|&index| self.history_matches(index))
+        else {
+            return;
+        };
+
+        self.history_index = index;
+        self.buffer = self.history[self.history_index].chars().collect();
+        self.cursor = self.buffer.len();
+    }
+
+    /// Returns whether the history entry at `index` is one the arrow keys step to: any entry, or
+    /// with [`ShellOption::HistSubstring`] and a typed line, the ones containing it other than
+    /// the entry shown.
+    fn history_matches(&self, index: usize) -> bool {
+        let search = OPTIONS
+            .try_lock()
+            .is_ok_and(|options| options.is_set(ShellOption::HistSubstring));
+        if !search || self.draft.is_empty() {
+            return true;
+        }
+
+        let entry = &self.history[index];
+        entry.contains(&self.draft.iter().collect::<String>())
+            && !entry.chars().eq(self.buffer.iter().copied())
+    }
+
+    fn insert_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.buffer.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    /// Replaces the `count` characters before the cursor with `text`.
+    fn replace_before_cursor(&mut self, count: usize, text: &str) {
+        self.buffer.drain(self.cursor - count..self.cursor);
+        self.cursor -= count;
+        self.insert_str(text);
+    }
+
+    #[must_use]
+    pub fn new(history: Vec<String>) -> Self {
+        let history_index = history.len();
+
+        Self {
+            history,
+            history_index,
+            ..Self::default()
+        }
+    }
+
+    /// Opens the line in `$VISUAL` or `$EDITOR`, falling back to `vi`, and replaces it with the
+    /// edited file once the editor exits successfully, keeping its lines apart.
+    fn edit_externally(&mut self, stdout: &mut RawTerminal<Stdout>) -> io::Result<()> {
+        let editor = ["VISUAL", "EDITOR"]
+            .into_iter()
+            .filter_map(std::env::var_os)
+            .find(|editor| !editor.is_empty())
+            .map_or_else(
+                || String::from("vi"),
+                |editor| editor.to_string_lossy().into_owned(),
+            );
+        // like `code --wait`
+        let mut words = editor.split_whitespace();
+        let Some(program) = words.next() else {
+            return Ok(());
+        };
+
+        let (path, mut file) = create_temporary()?;
+        let written = writeln!(file, "{}", self.buffer.iter().collect::<String>());
+        drop(file);
+        if let Err(error) = written {
+            let _ = std::fs::remove_file(&path);
+            return Err(error);
+        }
+
+        write!(stdout, "\r\n")?;
+        stdout.suspend_raw_mode()?;
+        let status = std::process::Command::new(program)
+            .args(words)
+            .arg(&path)
+            .status();
+        stdout.activate_raw_mode()?;
+
+        let edited = std::fs::read_to_string(&path);
+        let _ = std::fs::remove_file(&path);
+
+        match status {
+            Ok(status) if status.success() => {
+                let edited = edited?;
+                self.buffer = edited.trim_end_matches('\n').chars().collect();
+                self.cursor = self.buffer.len();
+            }
+            Ok(_) => {}
+            Err(error) => write!(stdout, "rshell: {program}: {error}\r\n")?,
+        }
+
+        Ok(())
+    }
+
+    /// Opens the file picker and inserts the selected path at the cursor.
+    fn pick_file(&mut self, keys: &mut Keys<Stdin>) -> io::Result<()> {
+        let files = picker::files(&std::env::current_dir()?);
+
+        if let Some(path) = picker::pick(&files, keys)? {
+            if self.cursor > 0 && self.buffer[self.cursor - 1] != ' ' {
+                self.insert_str(" ");
+            }
+            self.insert_str(&quote(&path));
+        }
+
+        Ok(())
+    }
+
+    /// Opens the history picker and replaces the line with the selected command.
+    fn pick_history(&mut self, keys: &mut Keys<Stdin>) -> io::Result<()> {
+        // most recent first, without duplicates
+        let mut seen = HashSet::new();
+        let history = self
+            .history
+            .iter()
+            .rev()
+            .filter(|line| seen.insert(*line))
+            .collect::<Vec<_>>();
+        // one entry per line
+        let entries = history
+            .iter()
+            .map(|line| line.replace('\n', &NEWLINE.to_string()))
+            .collect::<Vec<_>>();
+
+        if let Some(picked) = picker::pick(&entries, keys)? {
+            if let Some(index) = entries.iter().position(|entry| *entry == picked) {
+                self.buffer = history[index].chars().collect();
+                self.cursor = self.buffer.len();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Selects the next placeholder of the line after the current `selection`, or if there is
+    /// none, opens the snippet picker and inserts the selected snippet at the cursor.
+    fn pick_snippet(&mut self, selection: usize, keys: &mut Keys<Stdin>) -> io::Result<()> {
+        if self.select_placeholder(self.cursor + selection) || self.select_placeholder(0) {
+            return Ok(());
+        }
+
+        let snippets = snippet::load().unwrap_or_default();
+        let entries = snippets
+            .iter()
+            .map(|snippet| format!("{}: {}", snippet.name, snippet.command))
+            .collect::<Vec<_>>();
+
+        if let Some(picked) = picker::pick(&entries, keys)? {
+            if let Some(index) = entries.iter().position(|entry| *entry == picked) {
+                let start = self.cursor;
+                self.insert_str(&snippets[index].command);
+                self.select_placeholder(start);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Selects the first placeholder at or after `from`, returning whether there was one.
+    fn select_placeholder(&mut self, from: usize) -> bool {
+        match snippet::next_placeholder(&self.buffer, from) {
+            Some((start, end)) => {
+                self.cursor = start;
+                self.selection = end - start;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reads a line from stdin after printing `prompt`, starting from the last line put aside
+    /// with `ALT+Q` if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading from stdin or writing to stdout failed.
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<ReadLine> {
+        self.read(prompt, true)
+    }
+
+    /// Reads a line continuing a command from stdin after printing `prompt`, leaving the lines put
+    /// aside with `ALT+Q` for the next command.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading from stdin or writing to stdout failed.
+    pub fn read_continuation(&mut self, prompt: &str) -> io::Result<ReadLine> {
+        self.read(prompt, false)
+    }
+
+    fn read(&mut self, prompt: &str, restore: bool) -> io::Result<ReadLine> {
+        if termion::is_tty(&io::stdin()) {
+            return self.read_line_raw(prompt, restore);
+        }
+
+        print!("{prompt}");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if BufRead::read_line(&mut io::stdin().lock(), &mut line)? == 0 {
+            return Ok(ReadLine::Eof);
+        }
+
+        Ok(ReadLine::Line(
+            line.trim_end_matches(['\n', '\r']).to_string(),
+        ))
+    }
+
+    fn read_line_raw(&mut self, prompt: &str, restore: bool) -> io::Result<ReadLine> {
+        let mut stdout = io::stdout().into_raw_mode()?;
+        let mut keys = io::stdin().keys();
+
+        (self.buffer, self.cursor) = restore
+            .then(|| self.pushed.pop())
+            .flatten()
+            .unwrap_or_default();
+        self.selection = 0;
+        self.menu = None;
+        self.history_index = self.history.len();
+
+        self.render(&mut stdout, prompt)?;
+
+        while let Some(key) = keys.next() {
+            let key = match key {
+                Ok(key) => key,
+                // unrecognized escape sequences
+                Err(error) if error.kind() == io::ErrorKind::Other => continue,
+                Err(error) => return Err(error),
+            };
+
+            // a selected placeholder is replaced by the next character, or deleted
+            let selection = std::mem::take(&mut self.selection);
+            // any other key than TAB accepts the inserted candidate
+            let menu = self.menu.take();
+
+            match key {
+                Key::Left | Key::Right | Key::Up | Key::Down
+                    if menu.as_ref().is_some_and(|menu| menu.grid) =>
+                {
+                    if let Some(menu) = menu {
+                        self.navigate(menu, key);
+                    }
+                }
+                // closes the menu
+                Key::Char('\n') if menu.as_ref().is_some_and(|menu| menu.grid) => {}
+                Key::Char('\n') => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(ReadLine::Line(self.buffer.iter().collect()));
+                }
+                Key::Ctrl('c') => {
+                    write!(stdout, "^C\r\n")?;
+                    return Ok(ReadLine::Interrupted);
+                }
+                Key::Ctrl('d') if self.buffer.is_empty() => return Ok(ReadLine::Eof),
+                Key::Backspace | Key::Ctrl('h' | 'd') | Key::Delete if selection > 0 => {
+                    self.buffer.drain(self.cursor..self.cursor + selection);
+                }
+                Key::Ctrl('d') | Key::Delete if self.cursor < self.buffer.len() => {
+                    self.buffer.remove(self.cursor);
+                }
+                Key::Char('\t') => self.complete(menu, &mut stdout)?,
+                Key::BackTab => {
+                    if let Some(menu) = menu {
+                        let len = menu.candidates.len();
+                        let previous = menu
+                            .selected
+                            .map_or(len - 1, |selected| (selected + len - 1) % len);
+                        self.select(menu, previous);
+                    }
+                }
+                Key::Ctrl('r') => self.pick_history(&mut keys)?,
+                Key::Ctrl('t') => self.pick_file(&mut keys)?,
+                Key::Alt('s') => self.pick_snippet(selection, &mut keys)?,
+                Key::Alt('e') => self.edit_externally(&mut stdout)?,
+                // zsh's push-line, giving back the line at the next prompt
+                Key::Alt('q') if !self.buffer.is_empty() => {
+                    self.pushed
+                        .push((std::mem::take(&mut self.buffer), self.cursor));
+                    self.cursor = 0;
+                }
+                Key::Ctrl('l') => write!(
+                    stdout,
+                    "{}{}",
+                    termion::clear::All,
+                    termion::cursor::Goto(1, 1)
+                )?,
+                Key::Backspace | Key::Ctrl('h') => self.delete_backward(),
+                Key::Ctrl('w') => self.delete_word_backward(),
+                Key::Ctrl('u') => {
+                    self.buffer.drain(..self.cursor);
+                    self.cursor = 0;
+                }
+                Key::Ctrl('k') => self.buffer.truncate(self.cursor),
+                Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+                Key::Right | Key::Ctrl('f') => {
+                    self.cursor = (self.cursor + 1).min(self.buffer.len());
+                }
+                Key::Home | Key::Ctrl('a') => self.cursor = 0,
+                Key::End | Key::Ctrl('e') => self.cursor = self.buffer.len(),
+                Key::Up | Key::Ctrl('p') => self.history_previous(),
+                Key::Down | Key::Ctrl('n') => self.history_next(),
+                Key::Char(c) => {
+                    self.buffer.drain(self.cursor..self.cursor + selection);
+                    self.insert_str(&c.to_string());
+                }
+                _ => {}
+            }
+
+            self.render(&mut stdout, prompt)?;
+        }
+
+        Ok(ReadLine::Eof)
+    }
+
+    /// Returns the buffer with the selection inverted, the bracket pair at or before the cursor
+    /// underlined, and unmatched brackets and unterminated quotes in red.
+    fn highlight(&self) -> String {
+        let brackets = Scanner::brackets(&self.buffer);
+        let pair = [Some(self.cursor), self.cursor.checked_sub(1)]
+            .into_iter()
+            .flatten()
+            .find_map(|index| {
+                brackets
+                    .pairs
+                    .iter()
+                    .find(|&&(start, end)| start == index || end == index)
+            });
+
+        let mut line = String::new();
+        for (index, &c) in self.buffer.iter().enumerate() {
+            let selected = (self.cursor..self.cursor + self.selection).contains(&index);
+            let paired = pair.is_some_and(|&(start, end)| index == start || index == end);
+            let unmatched = brackets.unmatched.contains(&index);
+
+            // lines are shown side by side, the prompt being drawn on a single row
+            let c = if c == '\n' { NEWLINE } else { c };
+
+            if !(selected || paired || unmatched) {
+                line.push(c);
+                continue;
+            }
+
+            if selected {
+                line.push_str(termion::style::Invert.as_ref());
+            }
+            if paired {
+                line.push_str(termion::style::Underline.as_ref());
+            }
+            if unmatched {
+                line.push_str(&RED_FG_COLOR.to_string());
+            }
+            line.push(c);
+            line.push_str(termion::style::Reset.as_ref());
+        }
+
+        line
+    }
+
+    fn render(&self, stdout: &mut impl Write, prompt: &str) -> io::Result<()> {
+        let line = self.highlight();
+
+        write!(stdout, "\r{}{prompt}{line}", termion::clear::AfterCursor)?;
+
+        if let Some(menu) = self.menu.as_ref().filter(|menu| menu.grid) {
+            let rows = draw_menu(stdout, menu)?;
+            write!(stdout, "{}\r{prompt}{line}", termion::cursor::Up(rows))?;
+        }
+
+        let back = self.buffer.len() - self.cursor;
+        if back > 0 {
+            let back = u16::try_from(back).unwrap_or(u16::MAX);
+            write!(stdout, "{}", termion::cursor::Left(back))?;
+        }
+
+        stdout.flush()
+    }
+}
+
+/// Creates a file only readable by the user in the temporary directory, under a name that wasn't
+/// taken, without following a link put in its place.
+fn create_temporary() -> io::Result<(PathBuf, File)> {
+    let pid = std::process::id();
+
+    let mut attempt = 0;
+
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let path = std::env::temp_dir().join(format!("rshell-edit-{pid}-{nanos:x}{attempt}.sh"));
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Returns the longest prefix shared by all `candidates`.
+fn common_prefix(candidates: &[Candidate]) -> String {
+    let mut prefix = candidates[0].value.as_str();
+
+    for candidate in &candidates[1..] {
+        let length = prefix
+            .char_indices()
+            .zip(candidate.value.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(
+                prefix.len().min(candidate.value.len()),
+                |((index, _), _)| index,
+            );
+
+        prefix = &prefix[..length];
+    }
+
+    prefix.to_string()
+}
+
+/// Returns the column width and the number of columns `candidates` are listed in: as many as
+/// fit in the terminal, or one if they have descriptions.
+fn layout(candidates: &[Candidate]) -> (usize, usize) {
+    let (width, _) = termion::terminal_size().unwrap_or((80, 24));
+    let column_width = candidates
+        .iter()
+        .map(|candidate| candidate.value.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+
+    let columns = if candidates
+        .iter()
+        .any(|candidate| candidate.description.is_some())
+    {
+        1
+    } else {
+        (usize::from(width) / column_width).max(1)
+    };
+
+    (column_width, columns)
+}
+
+/// Lists `candidates` below the current line, in columns, or one per line followed by their
+/// descriptions if they have any.
+fn list(stdout: &mut impl Write, candidates: &[Candidate]) -> io::Result<()> {
+    let (column_width, columns) = layout(candidates);
+
+    for (index, candidate) in candidates.iter().enumerate() {
+        if index % columns == 0 {
+            write!(stdout, "\r\n")?;
+        }
+        write_candidate(stdout, candidate, column_width)?;
+    }
+
+    write!(stdout, "\r\n")
+}
+
+/// Draws the candidates of a grid `menu` below the current line, highlighting the selected one,
+/// and scrolled to it if they don't fit in half of the terminal.
+///
+/// Returns the number of rows drawn.
+fn draw_menu(stdout: &mut impl Write, menu: &Menu) -> io::Result<u16> {
+    let (_, height) = termion::terminal_size().unwrap_or((80, 24));
+    let (column_width, columns) = layout(&menu.candidates);
+
+    let rows = menu.candidates.len().div_ceil(columns);
+    let max_rows = (usize::from(height) / 2).max(1);
+    let first = menu.selected.map_or(0, |selected| {
+        (selected / columns).saturating_sub(max_rows - 1)
+    });
+    let last = rows.min(first + max_rows);
+
+    for row in first..last {
+        write!(stdout, "\r\n")?;
+
+        for (index, candidate) in menu
+            .candidates
+            .iter()
+            .enumerate()
+            .skip(row * columns)
+            .take(columns)
+        {
+            if menu.selected == Some(index) {
+                write!(stdout, "{}", termion::style::Invert)?;
+            }
+            write_candidate(stdout, candidate, column_width)?;
+            write!(stdout, "{}", termion::style::Reset)?;
+        }
+    }
+
+    Ok(u16::try_from(last - first).unwrap_or(u16::MAX))
+}
+
+/// Writes `candidate` padded to `column_width`, followed by its description, dimmed and cut to
+/// fit in the terminal.
+fn write_candidate(
+    stdout: &mut impl Write,
+    candidate: &Candidate,
+    column_width: usize,
+) -> io::Result<()> {
+    write!(stdout, "{:<column_width$}", candidate.value)?;
+
+    let Some(description) = &candidate.description else {
+        return Ok(());
+    };
+
+    let (width, _) = termion::terminal_size().unwrap_or((80, 24));
+    let room = match usize::from(width).saturating_sub(column_width + 1) {
+        // the size is unknown
+        0 if width == 0 => usize::MAX,
+        room => room,
+    };
+
+    let description = if description.chars().count() > room {
+        let mut cut = description
+            .chars()
+            .take(room.saturating_sub(1))
+            .collect::<String>();
+        cut.push('…');
+        cut
+    } else {
+        description.clone()
+    };
+
+    write!(
+        stdout,
+        "{}{description}{}",
+        termion::style::Faint,
+        termion::style::NoFaint
+    )
+}
+
+/// Quotes `word` so that the scanner reads it back as a single argument.
+#[must_use]
+pub fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || ['=', '.', '/', '-'].contains(&c))
+    {
+        word.to_string()
+    } else if word.contains('\'') {
+        // inside double quotes, these keep their meaning unless escaped
+        let escaped = word
+            .chars()
+            .flat_map(|c| {
+                let backslash = ['"', '\\', '$', '`'].contains(&c).then_some('\\');
+                backslash.into_iter().chain([c])
+            })
+            .collect::<String>();
+        format!("\"{escaped}\"")
+    } else {
+        format!("'{word}'")
+    }
+}
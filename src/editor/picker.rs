@@ -0,0 +1,247 @@
+//! Fuzzy picker, drawn on the alternate screen or in a tmux popup.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Stdin, Write},
+    path::{Path, PathBuf},
+};
+
+use termion::{
+    clear, cursor,
+    event::Key,
+    input::{Keys, TermRead},
+    raw::IntoRawMode,
+    screen::IntoAlternateScreen,
+    style,
+};
+
+use crate::{editor::quote, tmux};
+
+/// How deep below the root directory entries are collected.
+const MAX_DEPTH: usize = 8;
+
+/// How many entries are collected at most, to keep huge trees responsive.
+const MAX_ENTRIES: usize = 20_000;
+
+/// Lets the user fuzzy-search `entries`, in a tmux popup if possible.
+///
+/// Returns the selected entry, or `None` if the picker was cancelled with CTRL+C, CTRL+G or
+/// Escape.
+///
+/// # Errors
+///
+/// This function will return an error if reading keys or drawing to the terminal failed.
+pub(super) fn pick(entries: &[String], keys: &mut Keys<Stdin>) -> io::Result<Option<String>> {
+    if tmux::has_popups() {
+        if let Some(picked) = pick_in_popup(entries)? {
+            return Ok(Some(picked).filter(|picked| !picked.is_empty()));
+        }
+    }
+
+    pick_inline(entries, keys)
+}
+
+/// Runs the picker in a tmux popup, by running `rshell --pick ENTRIES OUTPUT` in it.
+///
+/// Returns the selected entry, which is empty if the picker was cancelled, or `None` if the
+/// popup couldn't be opened.
+fn pick_in_popup(entries: &[String]) -> io::Result<Option<String>> {
+    let dir = std::env::temp_dir();
+    let entries_path = dir.join(format!("rshell-pick-{}", std::process::id()));
+    let output_path = dir.join(format!("rshell-picked-{}", std::process::id()));
+
+    std::fs::write(&entries_path, entries.join("\n"))?;
+
+    let command = format!(
+        "{} --pick {} {}",
+        quote(&std::env::current_exe()?.display().to_string()),
+        quote(&entries_path.display().to_string()),
+        quote(&output_path.display().to_string()),
+    );
+    let result = tmux::popup(&command);
+
+    let picked = std::fs::read_to_string(&output_path).ok();
+    let _ = std::fs::remove_file(entries_path);
+    let _ = std::fs::remove_file(output_path);
+    result?;
+
+    Ok(picked)
+}
+
+/// Runs the picker on the lines of `entries`, writing the selected one to `output`.
+///
+/// This is what runs inside of tmux popups.
+///
+/// # Errors
+///
+/// This function will return an error if the files couldn't be read or written, or the
+/// terminal couldn't be used.
+pub fn pick_to_file(entries: &Path, output: &Path) -> io::Result<()> {
+    let entries = std::fs::read_to_string(entries)?
+        .lines()
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    let _raw = io::stdout().into_raw_mode()?;
+    let picked = pick_inline(&entries, &mut io::stdin().keys())?;
+
+    std::fs::write(output, picked.unwrap_or_default())
+}
+
+fn pick_inline(entries: &[String], keys: &mut Keys<Stdin>) -> io::Result<Option<String>> {
+    let mut screen = io::stdout().into_alternate_screen()?;
+
+    let mut query = String::new();
+    let mut selected = 0;
+    let mut matches = filter(entries, &query);
+
+    render(&mut screen, &query, &matches, selected, entries.len())?;
+
+    for key in keys.by_ref() {
+        let Ok(key) = key else {
+            continue;
+        };
+
+        match key {
+            Key::Char('\n') => return Ok(matches.get(selected).map(|entry| (*entry).clone())),
+            Key::Esc | Key::Ctrl('c' | 'g') => return Ok(None),
+            Key::Up | Key::Ctrl('p' | 'k') => selected = selected.saturating_sub(1),
+            Key::Down | Key::Ctrl('n' | 'j') => {
+                selected = (selected + 1).min(matches.len().saturating_sub(1));
+            }
+            Key::Backspace | Key::Ctrl('h') => {
+                query.pop();
+                matches = filter(entries, &query);
+                selected = 0;
+            }
+            Key::Ctrl('u') => {
+                query.clear();
+                matches = filter(entries, &query);
+                selected = 0;
+            }
+            Key::Char(c) if !c.is_control() => {
+                query.push(c);
+                matches = filter(entries, &query);
+                selected = 0;
+            }
+            _ => {}
+        }
+
+        render(&mut screen, &query, &matches, selected, entries.len())?;
+    }
+
+    Ok(None)
+}
+
+/// Returns the entries matching `query`, best matches first.
+fn filter<'a>(entries: &'a [String], query: &str) -> Vec<&'a String> {
+    let mut matches = entries
+        .iter()
+        .filter_map(|entry| score(query, entry).map(|score| (score, entry)))
+        .collect::<Vec<_>>();
+
+    // stable, so equally good matches keep the breadth-first order
+    matches.sort_by_key(|(score, _)| *score);
+
+    matches.into_iter().map(|(_, entry)| entry).collect()
+}
+
+fn render(
+    screen: &mut impl Write,
+    query: &str,
+    matches: &[&String],
+    selected: usize,
+    total: usize,
+) -> io::Result<()> {
+    let (_, height) = termion::terminal_size().unwrap_or((80, 24));
+    let rows = usize::from(height).saturating_sub(1);
+    let offset = selected.saturating_sub(rows.saturating_sub(1));
+
+    write!(screen, "{}", clear::All)?;
+
+    for (index, (row, entry)) in (offset..).zip((2..).zip(matches.iter().skip(offset).take(rows))) {
+        write!(screen, "{}", cursor::Goto(1, row))?;
+
+        if index == selected {
+            write!(screen, "{}{entry}{}", style::Invert, style::Reset)?;
+        } else {
+            write!(screen, "{entry}")?;
+        }
+    }
+
+    write!(
+        screen,
+        "{}{}/{} > {query}",
+        cursor::Goto(1, 1),
+        matches.len(),
+        total
+    )?;
+
+    screen.flush()
+}
+
+/// Scores `candidate` as a case-insensitive subsequence match of `query`.
+///
+/// Returns `None` if `candidate` does not match. Lower scores are better: candidates with the
+/// query's characters closer together come first, then shorter candidates.
+fn score(query: &str, candidate: &str) -> Option<usize> {
+    let mut gaps = 0;
+    let mut chars = candidate.chars().map(|c| c.to_ascii_lowercase());
+    let mut started = false;
+
+    for q in query.chars().map(|c| c.to_ascii_lowercase()) {
+        loop {
+            match chars.next() {
+                Some(c) if c == q => break,
+                Some(_) if started => gaps += 1,
+                Some(_) => {}
+                None => return None,
+            }
+        }
+        started = true;
+    }
+
+    Some(gaps * 1000 + candidate.len())
+}
+
+/// Collects the non-hidden files and directories below `root` in breadth-first order.
+///
+/// Paths are relative to `root`, and directories end with a `/`.
+pub(super) fn files(root: &Path) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut queue = VecDeque::from([(PathBuf::new(), 0)]);
+
+    while let Some((dir, depth)) = queue.pop_front() {
+        let Ok(read_dir) = std::fs::read_dir(root.join(&dir)) else {
+            continue;
+        };
+
+        let mut children = read_dir.filter_map(Result::ok).collect::<Vec<_>>();
+        children.sort_by_key(std::fs::DirEntry::file_name);
+
+        for child in children {
+            let name = child.file_name();
+            if name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let path = dir.join(name);
+
+            if child.file_type().is_ok_and(|r#type| r#type.is_dir()) {
+                entries.push(format!("{}/", path.display()));
+
+                if depth + 1 < MAX_DEPTH {
+                    queue.push_back((path, depth + 1));
+                }
+            } else {
+                entries.push(path.display().to_string());
+            }
+
+            if entries.len() >= MAX_ENTRIES {
+                return entries;
+            }
+        }
+    }
+
+    entries
+}
@@ -0,0 +1,53 @@
+//! Snapshots of the variables, taken with `envdiff snap`, which `envdiff` compares the current
+//! variables against, like after running the commands of an `env.sh`.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use crate::variables::{self, Scope};
+
+/// A variable that changed since the snapshot.
+pub enum Change {
+    Added(String, Scope),
+    Removed(String, Scope),
+    /// The old value and scope, then the new ones.
+    Changed((String, Scope), (String, Scope)),
+}
+
+static SNAPSHOT: Mutex<Option<BTreeMap<String, (String, Scope)>>> = Mutex::new(None);
+
+fn snapshot() -> MutexGuard<'static, Option<BTreeMap<String, (String, Scope)>>> {
+    SNAPSHOT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Takes a snapshot of the variables, replacing the previous one.
+pub fn snap() {
+    *snapshot() = Some(variables::all());
+}
+
+/// Returns the variables that changed since the snapshot, by name, or `None` without a
+/// snapshot.
+#[must_use]
+pub fn diff() -> Option<Vec<(String, Change)>> {
+    let before = snapshot().clone()?;
+    let mut after = variables::all();
+    let mut changes = Vec::new();
+
+    for (name, old) in before {
+        match after.remove(&name) {
+            Some(new) if new == old => {}
+            Some(new) => changes.push((name, Change::Changed(old, new))),
+            None => changes.push((name, Change::Removed(old.0, old.1))),
+        }
+    }
+    changes.extend(
+        after
+            .into_iter()
+            .map(|(name, (value, scope))| (name, Change::Added(value, scope))),
+    );
+
+    changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Some(changes)
+}
@@ -0,0 +1,56 @@
+//! `set -e`, stopping at the first command that fails.
+//!
+//! A failing command stops the subshell or job it runs in, like `exit`, a script or a shell
+//! reading commands from a pipe, or the rest of the line at the prompt of an interactive shell.
+//! Commands whose failure is tested don't: the conditions of `if` and `elif`, the commands
+//! before `&&` and `||`, and the commands of a pipeline but the last, only the exit code of the
+//! pipeline being looked at.
+
+use std::{cell::Cell, future::Future};
+
+use crate::{options::ShellOption, subshell, trap, OPTIONS, STATUS};
+
+tokio::task_local! {
+    /// Set while running a line, to whether a failing command stopped it.
+    static STOPPED: Cell<bool>;
+    /// Set while running a command whose failure is tested.
+    static TESTED: ();
+}
+
+/// Runs `future`, a line, which a failing command stops the rest of at the prompt.
+pub(crate) async fn line<F: Future>(future: F) -> F::Output {
+    STOPPED.scope(Cell::new(false), future).await
+}
+
+/// Runs `future`, a command whose failure is tested, so it doesn't stop anything.
+pub(crate) async fn tested<F: Future>(future: F) -> F::Output {
+    TESTED.scope((), future).await
+}
+
+/// Returns whether the command being run is one whose failure is tested.
+pub(crate) fn is_tested() -> bool {
+    TESTED.try_with(|()| ()).is_ok()
+}
+
+/// Returns whether a failing command stopped the line being run.
+pub(crate) fn is_stopped() -> bool {
+    STOPPED.try_with(Cell::get).unwrap_or_default()
+}
+
+/// Stops what is running with `set -e` if a pipeline exited with `code`, a failure that isn't
+/// tested.
+pub(crate) async fn check(code: i32) {
+    if code == 0 || is_tested() || !OPTIONS.lock().await.is_set(ShellOption::ErrExit) {
+        return;
+    }
+
+    if subshell::exit(code) {
+        return;
+    }
+
+    if !STATUS.lock().await.interactive {
+        trap::exit(code).await;
+    }
+
+    let _ = STOPPED.try_with(|stopped| stopped.set(true));
+}
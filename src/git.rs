@@ -0,0 +1,276 @@
+//! Lightweight git repository discovery, and the git status shown in the prompt.
+//!
+//! The status is cached per repository, as `git status` can take a while in large ones. The cache
+//! is invalidated by watching the repository's `HEAD` and index, which commits, checkouts and
+//! staging in other terminals change, and after a command the shell runs if a file of the working
+//! tree was modified since the status was read.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, SystemTime},
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long the prompt waits for `git status` before showing the status it had cached.
+const STATUS_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// How many entries of the working tree are compared with the time the status was read at most,
+/// beyond which it is read again.
+const MAX_ENTRIES: usize = 20_000;
+
+/// Returns the root of the git repository containing `path`, if any.
+///
+/// A directory is a repository root if it contains a `.git` entry, which is a directory for
+/// regular repositories and a file for worktrees and submodules.
+#[must_use]
+pub fn discover(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// The state of a repository shown in the prompt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// The checked out branch, or `HEAD` if it is detached.
+    pub branch: String,
+    /// Whether there are changes that aren't committed, staged or not.
+    pub dirty: bool,
+}
+
+/// The status of the repository last shown.
+struct Cached {
+    root: PathBuf,
+    status: Option<Status>,
+    /// When the status was last read, entries of the working tree modified since then making it
+    /// stale.
+    read_at: SystemTime,
+    /// Set when the status may have changed since it was read.
+    stale: Arc<AtomicBool>,
+    /// Watches the git directory, setting `stale` when `HEAD` or the index changes.
+    _watcher: Option<RecommendedWatcher>,
+}
+
+static CACHE: Mutex<Option<Cached>> = Mutex::new(None);
+
+fn cache() -> MutexGuard<'static, Option<Cached>> {
+    CACHE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns the status of the repository containing `dir`, if any.
+///
+/// The cached status is returned while it is fresh. Otherwise it is read again, falling back to
+/// the cached one if that takes too long, the new one being cached once read.
+pub async fn status(dir: &Path) -> Option<Status> {
+    let root = discover(dir)?;
+
+    let cached = match &*cache() {
+        Some(cached) if cached.root == root => {
+            if !cached.stale.load(Ordering::Relaxed) {
+                return cached.status.clone();
+            }
+            cached.status.clone()
+        }
+        _ => None,
+    };
+
+    let refreshing = tokio::task::spawn_blocking(move || refresh(&root));
+    match tokio::time::timeout(STATUS_TIMEOUT, refreshing).await {
+        Ok(Ok(status)) => status,
+        _ => cached,
+    }
+}
+
+/// Marks the cached status as stale if an entry of the working tree was modified since it was
+/// read, like by the command that just ran, so the next prompt reads it again.
+///
+/// The directories ignored by name in the `.gitignore` of the root, like `target`, aren't looked
+/// into.
+pub fn check_working_tree() {
+    let (root, read_at, stale) = match &*cache() {
+        Some(cached) if !cached.stale.load(Ordering::Relaxed) => (
+            cached.root.clone(),
+            cached.read_at,
+            Arc::clone(&cached.stale),
+        ),
+        _ => return,
+    };
+
+    if modified_since(&root, read_at) {
+        stale.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Returns whether an entry below `root` was modified since `time`, or there are too many to
+/// tell.
+fn modified_since(root: &Path, time: SystemTime) -> bool {
+    let ignored = std::fs::read_to_string(root.join(".gitignore"))
+        .map(|gitignore| ignored_names(&gitignore))
+        .unwrap_or_default();
+
+    let mut queue = VecDeque::from([root.to_path_buf()]);
+    let mut entries = 0;
+
+    while let Some(dir) = queue.pop_front() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for child in read_dir.filter_map(Result::ok) {
+            let name = child.file_name();
+            if name == ".git" || ignored.contains(&name) {
+                continue;
+            }
+
+            entries += 1;
+            if entries > MAX_ENTRIES {
+                return true;
+            }
+
+            let Ok(metadata) = child.metadata() else {
+                continue;
+            };
+            // a directory is modified when an entry is added to it or removed from it
+            if metadata.modified().is_ok_and(|modified| modified >= time) {
+                return true;
+            }
+            if metadata.is_dir() {
+                queue.push_back(child.path());
+            }
+        }
+    }
+
+    false
+}
+
+/// Returns the names of the patterns of a `.gitignore` that are plain names, like `target` or
+/// `/node_modules/`.
+fn ignored_names(gitignore: &str) -> HashSet<OsString> {
+    gitignore
+        .lines()
+        .map(|line| line.trim().trim_start_matches('/').trim_end_matches('/'))
+        .filter(|name| {
+            !name.is_empty()
+                && !name.starts_with(['#', '!'])
+                && !name.contains(['/', '*', '?', '[', '\\'])
+        })
+        .map(OsString::from)
+        .collect()
+}
+
+/// Reads the status of the repository at `root` and caches it, watching the repository if it
+/// isn't the one cached.
+fn refresh(root: &Path) -> Option<Status> {
+    {
+        let mut cache = cache();
+        match &mut *cache {
+            // cleared before reading, so changes made meanwhile mark the new status stale
+            Some(cached) if cached.root == root => {
+                cached.stale.store(false, Ordering::Relaxed);
+                cached.read_at = SystemTime::now();
+            }
+            _ => {
+                let stale = Arc::new(AtomicBool::new(false));
+                *cache = Some(Cached {
+                    _watcher: watch(root, Arc::clone(&stale)),
+                    root: root.to_path_buf(),
+                    status: None,
+                    read_at: SystemTime::now(),
+                    stale,
+                });
+            }
+        }
+    }
+
+    let status = read(root);
+
+    if let Some(cached) = &mut *cache() {
+        if cached.root == root {
+            cached.status.clone_from(&status);
+        }
+    }
+
+    status
+}
+
+/// Watches the git directory of the repository at `root`, setting `stale` when its `HEAD` or
+/// index changes.
+fn watch(root: &Path, stale: Arc<AtomicBool>) -> Option<RecommendedWatcher> {
+    let git_dir = git_dir(root)?;
+
+    // the directory is watched rather than the files, which git replaces instead of writing to
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if event.paths.iter().any(|path| {
+            path.file_name()
+                .is_some_and(|name| name == "HEAD" || name == "index")
+        }) {
+            stale.store(true, Ordering::Relaxed);
+        }
+    })
+    .ok()?;
+    watcher.watch(&git_dir, RecursiveMode::NonRecursive).ok()?;
+
+    Some(watcher)
+}
+
+/// Returns the git directory of the repository at `root`, which the `.git` file of worktrees
+/// and submodules points to.
+fn git_dir(root: &Path) -> Option<PathBuf> {
+    let dot_git = root.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+
+    let contents = std::fs::read_to_string(&dot_git).ok()?;
+    let git_dir = contents.trim().strip_prefix("gitdir:")?.trim();
+    Some(root.join(git_dir))
+}
+
+/// Reads the status of the repository at `root` with `git status`.
+fn read(root: &Path) -> Option<Status> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["status", "--porcelain", "--branch"])
+        // refreshing the index would change it, and so mark the status stale again
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+
+    // like `## main...origin/main [ahead 1]`, `## No commits yet on main` or `## HEAD (no branch)`
+    let header = lines.next()?.strip_prefix("## ")?;
+    let branch = header
+        .strip_prefix("No commits yet on ")
+        .unwrap_or(header)
+        .split("...")
+        .next()?;
+    let branch = if branch.starts_with("HEAD (") {
+        "HEAD"
+    } else {
+        branch
+    };
+
+    Some(Status {
+        branch: branch.to_string(),
+        dirty: lines.next().is_some(),
+    })
+}
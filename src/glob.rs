@@ -0,0 +1,180 @@
+//! Shell pattern matching, as used by `string match`, and pathname expansion, as used by the
+//! `glob` builtin.
+//!
+//! Patterns support `*`, `?`, bracket expressions like `[a-z]` and `[!0-9]`, and backslash
+//! escapes.
+
+use std::path::Path;
+
+/// Returns whether `pattern` matches the whole of `text`.
+#[must_use]
+pub fn matches(pattern: &str, text: &str, ignore_case: bool) -> bool {
+    let fold = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+
+    let pattern = pattern.chars().map(fold).collect::<Vec<_>>();
+    let text = text.chars().map(fold).collect::<Vec<_>>();
+
+    // backtracking to the last `*` is enough, as `*` matches anything in between
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some(_) => {
+                if let Some(length) = match_one(&pattern[p..], text[t]) {
+                    p += length;
+                    t += 1;
+                    continue;
+                }
+            }
+            None => {}
+        }
+
+        match star {
+            Some((star_p, star_t)) => {
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns the paths matching `pattern`, sorted, like `src/*.rs`. Every `/`-separated component
+/// with `*`, `?` or `[` is matched against the entries of the directories matched so far, hidden
+/// entries only by components starting with `.`, and a trailing `/` only matches directories.
+#[must_use]
+pub fn expand(pattern: &str) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec![String::from("/")], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    for component in rest.split('/') {
+        paths = paths
+            .iter()
+            .flat_map(|path| expand_component(path, component))
+            .collect();
+    }
+
+    paths.sort();
+    paths
+}
+
+/// Returns the paths in `path` matching `component`, a component of a pattern.
+fn expand_component(path: &str, component: &str) -> Vec<String> {
+    let join = |name: &str| {
+        if path.is_empty() || path.ends_with('/') {
+            format!("{path}{name}")
+        } else {
+            format!("{path}/{name}")
+        }
+    };
+
+    // `a//b` or a trailing `/`
+    if component.is_empty() {
+        return if Path::new(path).is_dir() {
+            vec![join("")]
+        } else {
+            Vec::new()
+        };
+    }
+
+    if !component.contains(['*', '?', '[']) {
+        let mut literal = String::new();
+        let mut chars = component.chars();
+        while let Some(c) = chars.next() {
+            literal.push(if c == '\\' {
+                chars.next().unwrap_or(c)
+            } else {
+                c
+            });
+        }
+
+        let joined = join(&literal);
+        return if Path::new(&joined).symlink_metadata().is_ok() {
+            vec![joined]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let dir = if path.is_empty() { "." } else { path };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| {
+            (!name.starts_with('.') || component.starts_with('.'))
+                && matches(component, name, false)
+        })
+        .map(|name| join(&name))
+        .collect()
+}
+
+/// Matches `c` against the start of `pattern`, which isn't `*`.
+///
+/// Returns the length of the matched part of the pattern.
+fn match_one(pattern: &[char], c: char) -> Option<usize> {
+    match pattern[0] {
+        '?' => Some(1),
+        '\\' if pattern.len() > 1 => (pattern[1] == c).then_some(2),
+        '[' => match bracket(pattern, c) {
+            Some((matched, length)) => matched.then_some(length),
+            // an unterminated bracket is a literal `[`
+            None => (c == '[').then_some(1),
+        },
+        literal => (literal == c).then_some(1),
+    }
+}
+
+/// Matches `c` against the bracket expression at the start of `pattern`.
+///
+/// Returns whether it matched and the length of the expression, or `None` if it isn't closed.
+fn bracket(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut index = 1;
+    let negated = matches!(pattern.get(index), Some('!' | '^'));
+    if negated {
+        index += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        let start = *pattern.get(index)?;
+
+        // `]` right after the opening bracket is literal
+        if start == ']' && !first {
+            return Some((matched != negated, index + 1));
+        }
+        first = false;
+
+        if pattern.get(index + 1) == Some(&'-')
+            && pattern.get(index + 2).is_some_and(|&end| end != ']')
+        {
+            matched |= (start..=pattern[index + 2]).contains(&c);
+            index += 3;
+        } else {
+            matched |= start == c;
+            index += 1;
+        }
+    }
+}
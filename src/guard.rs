@@ -0,0 +1,78 @@
+//! Guards for dangerous commands, like `rm -rf /*` or `git push --force*`, configured with the
+//! `guard` builtin.
+//!
+//! A command is matched by its words joined with spaces, after expansions, against the guard's
+//! [`glob`] pattern. A guard either asks for confirmation on the terminal, or runs a hook program
+//! with the command's words as arguments, which must succeed for the command to run.
+
+use async_recursion::async_recursion;
+
+use crate::{editor::quote, error, glob, log, user, Command, GUARDS};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Guard {
+    pub pattern: String,
+    /// The program deciding whether a matching command runs, instead of asking.
+    pub hook: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Guards {
+    guards: Vec<Guard>,
+}
+
+impl Guards {
+    /// Adds `guard`, replacing the guard with the same pattern.
+    pub fn add(&mut self, guard: Guard) {
+        self.remove(&guard.pattern);
+        self.guards.push(guard);
+    }
+
+    /// Removes the guard with `pattern`, returning whether there was one.
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let len = self.guards.len();
+        self.guards.retain(|guard| guard.pattern != pattern);
+        len != self.guards.len()
+    }
+
+    /// Returns the guards, in the order they were added.
+    #[must_use]
+    pub fn guards(&self) -> &[Guard] {
+        &self.guards
+    }
+
+    fn matching(&self, line: &str) -> Option<&Guard> {
+        self.guards
+            .iter()
+            .find(|guard| glob::matches(&guard.pattern, line, false))
+    }
+}
+
+/// Returns whether the command made of `words` may run, asking the first guard matching it.
+///
+/// Blocked commands are reported with [`error!`].
+#[must_use]
+#[async_recursion]
+pub async fn allows(words: &[String]) -> bool {
+    let line = words.join(" ");
+
+    let Some(guard) = GUARDS.lock().await.matching(&line).cloned() else {
+        return true;
+    };
+
+    log!(Debug, "guard", "{line:?} matches {:?}", guard.pattern);
+
+    let allowed = match guard.hook {
+        Some(hook) => Command::new(hook, words.to_vec()).interpret().await == 0,
+        None => user::confirm(&format!(
+            "{line}: guarded by {}, run it?",
+            quote(&guard.pattern)
+        )),
+    };
+
+    if !allowed {
+        error!("{line}: blocked by guard {}", quote(&guard.pattern));
+    }
+
+    allowed
+}
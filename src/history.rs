@@ -0,0 +1,186 @@
+//! The history file, `~/.rshistory`, which commands are appended to, one per line, the lines of
+//! a command spanning several ending with a `\` but the last.
+//!
+//! Commands are buffered and written together once [`interval`] has passed since the first one,
+//! while the shell usually waits at the prompt, and when it exits. With `set -o histsync`, every
+//! command is written and synced to disk as soon as it is entered instead. A failed write is
+//! reported and the file is given up on, without exiting the shell.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::{error, options::ShellOption, OPTIONS};
+
+/// How long commands are buffered for, unless `RSHELL_HISTORY_INTERVAL` says otherwise.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+struct History {
+    path: PathBuf,
+    file: File,
+    pending: Vec<String>,
+    /// Whether a flush is already waiting for the interval to pass.
+    scheduled: bool,
+}
+
+static HISTORY: Mutex<Option<History>> = Mutex::new(None);
+
+fn history() -> MutexGuard<'static, Option<History>> {
+    HISTORY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Loads the commands stored in the history file at `path`, oldest first.
+#[must_use]
+pub fn load(path: &Path) -> Vec<String> {
+    std::fs::read_to_string(path)
+        .map(|history| entries(&history))
+        .unwrap_or_default()
+}
+
+/// Returns the commands stored in the contents of a history file, the lines ending with a `\`
+/// going on with the next one, like zsh.
+#[must_use]
+pub fn entries(history: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut entry: Option<String> = None;
+
+    for line in history.lines() {
+        let line = match entry.take() {
+            Some(mut entry) => {
+                entry.push('\n');
+                entry.push_str(line);
+                entry
+            }
+            None => line.to_string(),
+        };
+
+        match line.strip_suffix('\\') {
+            Some(line) => entry = Some(line.to_string()),
+            None => entries.push(line),
+        }
+    }
+    entries.extend(entry);
+
+    entries
+}
+
+/// Returns `command` as it is written to the history file, with a `\` before its newlines, and
+/// a space after a `\` ending it so that it doesn't go on with the next command.
+fn escape(command: &str) -> String {
+    let mut escaped = command.replace('\n', "\\\n");
+    if escaped.ends_with('\\') {
+        escaped.push(' ');
+    }
+
+    escaped
+}
+
+/// Opens the history file at `path` to append commands to it.
+///
+/// # Errors
+///
+/// This function will return an error if the file could not be opened.
+pub fn open(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+
+    *history() = Some(History {
+        path: path.to_path_buf(),
+        file,
+        pending: Vec::new(),
+        scheduled: false,
+    });
+    Ok(())
+}
+
+/// Returns how long commands are buffered for before being written, from the seconds in
+/// `RSHELL_HISTORY_INTERVAL`.
+#[must_use]
+pub fn interval() -> Duration {
+    std::env::var("RSHELL_HISTORY_INTERVAL")
+        .ok()
+        .and_then(|seconds| seconds.trim().parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .unwrap_or(DEFAULT_INTERVAL)
+}
+
+/// Adds `command` to the history file, right away with `histsync`, or with the next flush.
+pub fn push(command: &str) {
+    let sync = OPTIONS
+        .try_lock()
+        .is_ok_and(|options| options.is_set(ShellOption::HistSync));
+
+    let mut guard = history();
+    let Some(history) = guard.as_mut() else {
+        return;
+    };
+    history.pending.push(escape(command));
+
+    if sync || interval().is_zero() {
+        write(&mut guard, sync);
+    } else if !history.scheduled {
+        history.scheduled = true;
+
+        tokio::spawn(async {
+            tokio::time::sleep(interval()).await;
+            flush();
+        });
+    }
+}
+
+/// Writes the buffered commands to the history file.
+pub fn flush() {
+    write(&mut history(), false);
+}
+
+/// Writes the buffered commands, syncing the file to disk if `sync` is set, and gives up on the
+/// file if that fails.
+fn write(guard: &mut Option<History>, sync: bool) {
+    let Some(history) = guard.as_mut() else {
+        return;
+    };
+    history.scheduled = false;
+
+    if history.pending.is_empty() {
+        return;
+    }
+
+    let mut lines = history.pending.join("\n");
+    lines.push('\n');
+    history.pending.clear();
+
+    let result = history.file.write_all(lines.as_bytes()).and_then(|()| {
+        if sync {
+            history.file.sync_data()
+        } else {
+            Ok(())
+        }
+    });
+
+    if let Err(error) = result {
+        error!("could not write to {}: {error}", history.path.display());
+        *guard = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_line_entries_are_read_back() {
+        let commands = ["echo a", "if true\nthen echo b\nfi", "echo c\\", ""];
+        let history = commands
+            .iter()
+            .map(|command| escape(command) + "\n")
+            .collect::<String>();
+
+        assert_eq!(
+            entries(&history),
+            ["echo a", "if true\nthen echo b\nfi", "echo c\\ ", ""]
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Background jobs, started by ending a command list with `&`.
+//!
+//! A job runs its list in a subshell, a forked child of the shell, while the shell goes on, so
+//! like `( LIST )` it can't change the shell's working directory or variables. Like in shells
+//! without job control, a job reads from `/dev/null` unless redirected and ignores CTRL+C, so
+//! that it doesn't compete with the foreground for the terminal.
+
+use std::{
+    fs::File,
+    os::fd::AsRawFd,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use tokio::task::JoinHandle;
+
+use crate::{
+    bell::{self, Event},
+    command, error,
+    list::List,
+    options::ShellOption,
+    subshell, variables, JOBS, OPTIONS, STATUS,
+};
+
+/// Whether the process is the subshell of a job.
+static BACKGROUND: AtomicBool = AtomicBool::new(false);
+
+pub struct Job {
+    pub id: usize,
+    /// The subshell running the job.
+    pub pid: u32,
+    pub command: String,
+    handle: JoinHandle<i32>,
+}
+
+#[allow(clippy::struct_field_names)]
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// The process of the job started last, expanded by `$!`.
+    last_pid: Option<u32>,
+    /// The line an `exit` was held back on because jobs were running.
+    exit_warned: Option<usize>,
+}
+
+impl Jobs {
+    /// Adds a job, numbered one more than the highest running job, and returns its number.
+    fn add(&mut self, pid: u32, command: String, handle: JoinHandle<i32>) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or_default() + 1;
+
+        self.jobs.push(Job {
+            id,
+            pid,
+            command,
+            handle,
+        });
+        self.last_pid = Some(pid);
+
+        id
+    }
+
+    #[must_use]
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    #[must_use]
+    pub fn last_pid(&self) -> Option<u32> {
+        self.last_pid
+    }
+
+    /// Returns how many jobs are still running.
+    #[must_use]
+    pub fn running(&self) -> usize {
+        self.jobs.iter().filter(|job| job.is_running()).count()
+    }
+
+    /// Removes the job numbered `id`, or started with the process `pid`, given as `%ID` or `PID`.
+    fn take(&mut self, spec: &str) -> Option<Job> {
+        let index = if let Some(id) = spec.strip_prefix('%') {
+            let id = id.parse().ok()?;
+            self.jobs.iter().position(|job| job.id == id)?
+        } else {
+            let pid = spec.parse().ok()?;
+            self.jobs.iter().position(|job| job.pid == pid)?
+        };
+
+        Some(self.jobs.remove(index))
+    }
+
+    /// Removes the jobs that are done.
+    fn take_finished(&mut self) -> Vec<Job> {
+        let (finished, running) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|job| job.handle.is_finished());
+        self.jobs = running;
+
+        finished
+    }
+}
+
+impl Job {
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        !self.handle.is_finished()
+    }
+
+    /// Waits for the job and returns its exit code.
+    async fn wait(self) -> i32 {
+        // a job's task only ends early if it panicked
+        self.handle.await.unwrap_or(1)
+    }
+}
+
+/// Starts running `list` in the background, in a subshell whose process ID `$!` expands to.
+pub(crate) async fn spawn(list: &List) {
+    let command = list.to_string();
+    let list = list.clone();
+
+    let forked = subshell::fork(
+        move || async move { subshell::interpret(&list).await },
+        || {
+            BACKGROUND.store(true, Ordering::Relaxed);
+
+            if let Ok(null) = File::open("/dev/null") {
+                // SAFETY: both descriptors are open, and stdin is replaced in the child only.
+                unsafe {
+                    libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO);
+                }
+            }
+        },
+    )
+    .await;
+    let pid = match forked {
+        Ok(pid) => pid,
+        Err(error) => {
+            error!("{error}");
+            return;
+        }
+    };
+
+    let handle = tokio::spawn(async move {
+        match command::wait(pid).await {
+            Ok((status, _)) => command::exit_code(status),
+            Err(error) => {
+                error!("{error}");
+                1
+            }
+        }
+    });
+    let id = JOBS.lock().await.add(pid, command, handle);
+
+    if STATUS.lock().await.interactive {
+        eprintln!("[{id}] {pid}");
+    }
+}
+
+/// Returns whether the process is the subshell of a job.
+pub(crate) fn in_background() -> bool {
+    BACKGROUND.load(Ordering::Relaxed)
+}
+
+/// Forgets the jobs of the shell in a subshell forked from it, which can't wait for them. `$!`
+/// is kept.
+pub(crate) fn forget(jobs: &mut Jobs) {
+    // the tasks waiting for the jobs were left behind in the shell
+    std::mem::forget(std::mem::take(&mut jobs.jobs));
+}
+
+/// Removes the jobs that are done, telling how they exited, if the shell is interactive.
+/// Otherwise they are kept until waited for, so that `wait` still gets their exit code.
+pub async fn notify() {
+    if !STATUS.lock().await.interactive {
+        return;
+    }
+
+    let finished = JOBS.lock().await.take_finished();
+    if !finished.is_empty() {
+        bell::ring(Event::JobDone);
+    }
+
+    for job in finished {
+        let id = job.id;
+        let command = job.command.clone();
+
+        match job.wait().await {
+            0 => eprintln!("[{id}] Done {command}"),
+            code => eprintln!("[{id}] Exit {code} {command}"),
+        }
+    }
+}
+
+/// Returns whether the shell can exit, which an interactive shell with running jobs only does on a
+/// second `exit` or CTRL+D right after the first one, warning about them on the first one, and
+/// listing them with `set -o checkjobs`.
+pub async fn confirm_exit() -> bool {
+    if !STATUS.lock().await.interactive {
+        return true;
+    }
+
+    let mut jobs = JOBS.lock().await;
+    let running = jobs.running();
+    let line = variables::line();
+
+    // the line right after the warning, or the same one for `exit; exit`
+    if running == 0 || jobs.exit_warned.is_some_and(|warned| line <= warned + 1) {
+        return true;
+    }
+
+    jobs.exit_warned = Some(line);
+    match running {
+        1 => error!("1 job is still running, exit again to leave it"),
+        running => error!("{running} jobs are still running, exit again to leave them"),
+    }
+
+    if OPTIONS.lock().await.is_set(ShellOption::CheckJobs) {
+        for job in jobs.jobs.iter().filter(|job| job.is_running()) {
+            crate::errln!("[{}] Running {}", job.id, job.command);
+        }
+    }
+
+    false
+}
+
+/// Waits for the jobs given as `%ID` or `PID`, or all of them, and returns the exit code of the
+/// last one. Jobs that don't exist have an exit code of 127.
+pub async fn wait(specs: &[String]) -> i32 {
+    if specs.is_empty() {
+        let jobs = std::mem::take(&mut JOBS.lock().await.jobs);
+        for job in jobs {
+            job.wait().await;
+        }
+        return 0;
+    }
+
+    let mut code = 0;
+    for spec in specs {
+        // the lock isn't held while waiting, so that other jobs can be started
+        let job = JOBS.lock().await.take(spec);
+
+        code = if let Some(job) = job {
+            job.wait().await
+        } else {
+            crate::errln!("wait: no such job: {spec}");
+            127
+        };
+    }
+
+    code
+}
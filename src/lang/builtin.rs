@@ -1,26 +1,82 @@
-use crate::error;
+use crate::{errln, error, out, outln};
 
-use crate::ALIASES;
+use crate::{
+    chpwd,
+    command::Command,
+    completion, condition, correct, dir_stack, direnv,
+    editor::quote,
+    envdiff, git, glob,
+    guard::Guard,
+    job, math, metrics, named_dirs,
+    options::ShellOption,
+    pager,
+    priority::{self, Priority},
+    project, record,
+    redirect::{self, Plan},
+    snippet, subshell, trap, user, variables, ALIASES, COMPLETIONS, GUARDS, JOBS, OPTIONS,
+    PREVIOUS_EXIT_CODE, RSHISTORY, RSMETRICS, SIGINT_EXIT_CODE, STATUS,
+};
 use async_recursion::async_recursion;
 use clap::Arg;
 use clap::ArgAction;
 
 use std::{
-    env,
+    collections::HashMap,
     fmt::Display,
-    io::BufRead,
-    path::{Path, PathBuf},
+    fs::File,
+    io::{BufRead, Write},
+    path::{Component, Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Builtin {
     Alias,
+    Argparse,
+    Basename,
     Builtin,
     Cd,
+    Chpwd,
+    Complete,
+    Dirname,
+    Dirs,
     Echo,
+    Envdiff,
+    Exec,
     Exit,
+    Export,
+    Glob,
+    Groot,
+    Guard,
+    Hash,
+    Help,
     History,
+    Ionice,
+    Jobs,
+    Math,
+    Nice,
+    Popd,
+    Pushd,
     Pwd,
+    Readlink,
+    Realpath,
+    Record,
+    Repeat,
+    Report,
+    Seq,
+    Set,
+    Shift,
+    Sleep,
+    Snip,
+    Status,
+    String,
+    Test,
+    Trap,
+    Unhash,
+    Unset,
+    Wait,
+    Watch,
 }
 
 pub(crate) enum ErrorKind {
@@ -57,73 +113,267 @@ impl FromStr for Builtin {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "alias" => Ok(Self::Alias),
+            "argparse" => Ok(Self::Argparse),
+            "basename" => Ok(Self::Basename),
+            "dirname" => Ok(Self::Dirname),
+            "dirs" => Ok(Self::Dirs),
             "echo" => Ok(Self::Echo),
+            "envdiff" => Ok(Self::Envdiff),
+            "exec" => Ok(Self::Exec),
             "exit" | "bye" => Ok(Self::Exit),
+            "export" => Ok(Self::Export),
             "builtin" => Ok(Self::Builtin),
+            "glob" => Ok(Self::Glob),
+            "groot" => Ok(Self::Groot),
+            "guard" => Ok(Self::Guard),
+            "hash" => Ok(Self::Hash),
+            "help" => Ok(Self::Help),
             "history" => Ok(Self::History),
+            "ionice" => Ok(Self::Ionice),
+            "jobs" => Ok(Self::Jobs),
             "cd" | "chdir" => Ok(Self::Cd),
+            "chpwd" => Ok(Self::Chpwd),
+            "complete" => Ok(Self::Complete),
+            "math" => Ok(Self::Math),
+            "nice" => Ok(Self::Nice),
+            "popd" => Ok(Self::Popd),
+            "pushd" => Ok(Self::Pushd),
             "pwd" => Ok(Self::Pwd),
+            "readlink" => Ok(Self::Readlink),
+            "realpath" => Ok(Self::Realpath),
+            "record" => Ok(Self::Record),
+            "repeat" => Ok(Self::Repeat),
+            "report" => Ok(Self::Report),
+            "seq" => Ok(Self::Seq),
+            "set" => Ok(Self::Set),
+            "shift" => Ok(Self::Shift),
+            "sleep" => Ok(Self::Sleep),
+            "snip" => Ok(Self::Snip),
+            "status" => Ok(Self::Status),
+            "string" => Ok(Self::String),
+            "test" | "[" => Ok(Self::Test),
+            "trap" => Ok(Self::Trap),
+            "unhash" => Ok(Self::Unhash),
+            "unset" => Ok(Self::Unset),
+            "wait" => Ok(Self::Wait),
+            "watch" => Ok(Self::Watch),
             command => Err(command.to_string()),
         }
     }
 }
 
 impl Builtin {
+    /// The names builtins can be invoked with.
+    pub(crate) const NAMES: &'static [&'static str] = &[
+        "[", "alias", "argparse", "basename", "builtin", "bye", "cd", "chdir", "chpwd", "complete",
+        "dirname", "dirs", "echo", "envdiff", "exec", "exit", "export", "glob", "groot", "guard",
+        "hash", "help", "history", "ionice", "jobs", "math", "nice", "popd", "pushd", "pwd",
+        "readlink", "realpath", "record", "repeat", "report", "seq", "set", "shift", "sleep",
+        "snip", "status", "string", "test", "trap", "unhash", "unset", "wait", "watch",
+    ];
+
     /// Mimics `alias` builtin Unix shell command. [Linux man page](https://man7.org/linux/man-pages/man1/alias.1p.html)
     ///
+    /// Usage: `alias [-p] [NAME[=VALUE]...]`, defining every `NAME=VALUE` and printing the value
+    /// of every `NAME`, or of all aliases without any. `-p` prints them as `alias` commands that
+    /// define them again.
+    ///
     /// # Panics
     ///
     /// Panics if the alias lock could not be obtained.
     pub(crate) async fn alias(args: &[String]) -> i32 {
         let args = clap::Command::new("alias")
-            .arg(
-                Arg::new("alias-name")
-                    .action(ArgAction::Set)
-                    .required(false),
-            )
+            .arg(Arg::new("print").short('p').action(ArgAction::SetTrue))
+            .arg(Arg::new("aliases").action(ArgAction::Append))
             .try_get_matches_from(args);
 
         let Ok(args) = args else {
-            eprintln!("alias: bad argument");
+            errln!("alias: bad argument");
             return 1;
         };
 
+        let reusable = args.get_flag("print");
+        let print = |name: &str, value: &str| {
+            if reusable {
+                outln!("alias {}", quote(&format!("{name}={value}")));
+            } else {
+                outln!("{name}={value}");
+            }
+        };
+
         let mut lock = ALIASES.lock().await;
 
-        let Ok(Some(alias_name)) = args.try_get_one::<String>("alias-name") else {
-            for (key, value) in lock.aliases.clone() {
-                println!("{key}={value}");
+        let Some(aliases) = args.get_many::<String>("aliases") else {
+            let mut names = lock.names().collect::<Vec<_>>();
+            names.sort_unstable();
+
+            for name in names {
+                print(name, lock.get(name).map_or("", String::as_str));
             }
             return 0;
         };
 
-        if alias_name.contains('=') {
-            let (key, value) = alias_name.split_once('=').unwrap();
-            let value = value.trim_matches('\'').trim_matches('"');
-            lock.set(key.to_string(), value.to_string());
-        } else if let Some(value) = lock.get(alias_name) {
-            println!("{alias_name}={value}");
-        } else {
-            eprintln!("alias: {alias_name} not found");
+        let mut code = 0;
+        for alias in aliases {
+            if let Some((name, value)) = alias.split_once('=') {
+                lock.set(name.to_string(), value.to_string());
+            } else if let Some(value) = lock.get(alias) {
+                print(alias, value);
+            } else {
+                errln!("alias: {alias} not found");
+                code = 2;
+            }
+        }
+
+        code
+    }
+
+    /// Mimics `argparse` fish builtin, parsing `ARGS` according to the option `SPEC`s.
+    /// [fish manual](https://fishshell.com/docs/current/cmds/argparse.html)
+    ///
+    /// Usage: `argparse [-n NAME] SPEC... -- ARGS...`, where a `SPEC` like `h/help` is a flag,
+    /// `n/name=` takes a value, `n/name=?` an optional value, and `n/name=+` accumulates values.
+    ///
+    /// Each option found sets the shell variables `_flag_SHORT` and `_flag_LONG` to its values,
+    /// joined with spaces. The other arguments replace the positional parameters, so that `"$@"`
+    /// keeps them apart, and are also joined in `argv`. On bad arguments, or `--help` without a
+    /// `help` spec, a usage generated from the specs is printed.
+    #[must_use]
+    pub(crate) fn argparse(args: &[String]) -> i32 {
+        let (name, args) = match args.get(1).map(String::as_str) {
+            Some("-n" | "--name") if args.len() > 2 => (args[2].as_str(), &args[3..]),
+            _ => ("argparse", &args[1..]),
+        };
+
+        let Some(separator) = args.iter().position(|arg| arg == "--") else {
+            errln!("argparse: expected SPEC... -- ARGS...");
+            return 2;
+        };
+        let (specs, args) = (&args[..separator], &args[separator + 1..]);
+
+        let mut parsed = Vec::new();
+        for spec in specs {
+            let Some(spec) = ArgSpec::parse(spec) else {
+                errln!("argparse: invalid SPEC: {spec}");
+                return 2;
+            };
+            parsed.push(spec);
+        }
+        let specs = parsed;
+
+        let usage = || {
+            let options = specs
+                .iter()
+                .map(ArgSpec::usage)
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("usage: {name} {options} [ARGS...]")
+        };
+
+        // `--help` without a spec asking for it gets the generated usage
+        if !specs
+            .iter()
+            .any(|spec| spec.long.as_deref() == Some("help"))
+            && args.iter().any(|arg| arg == "--help")
+        {
+            outln!("{}", usage());
+            return 1;
+        }
+
+        let (values, positional) = match parse_args(&specs, args) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                errln!("{name}: {error}");
+                errln!("{}", usage());
+                return 2;
+            }
+        };
+
+        for (index, spec) in specs.iter().enumerate() {
+            for variable in spec.variables() {
+                match &values[index] {
+                    Some(values) => variables::set(&variable, &values.join(" ")),
+                    None => variables::unset(&variable),
+                }
+            }
+        }
+        variables::set("argv", &positional.join(" "));
+        variables::set_positional(positional);
+
+        0
+    }
+
+    /// Mimics the `basename` Unix command. [Linux man page](https://man7.org/linux/man-pages/man1/basename.1.html)
+    ///
+    /// Usage: `basename NAME [SUFFIX]` and `basename [-a] [-s SUFFIX] NAME...`
+    #[must_use]
+    pub(crate) fn basename(args: &[String]) -> i32 {
+        let mut multiple = false;
+        let mut suffix = None;
+        let mut names = Vec::new();
+        let mut args = args[1..].iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-a" | "--multiple" => multiple = true,
+                "-s" | "--suffix" => {
+                    multiple = true;
+                    suffix = args.next().map(String::as_str);
+                }
+                name => names.push(name),
+            }
+        }
+
+        if !multiple && names.len() == 2 {
+            suffix = names.pop();
+        }
+
+        if names.is_empty() || (!multiple && names.len() > 1) {
+            errln!("basename: expected NAME [SUFFIX]");
             return 2;
         }
 
+        for name in names {
+            let trimmed = name.trim_end_matches('/');
+            let base = match trimmed.rsplit_once('/') {
+                Some((_, base)) => base,
+                None if trimmed.is_empty() && !name.is_empty() => "/",
+                None => trimmed,
+            };
+
+            // the suffix is kept if it is the whole name
+            let base = suffix
+                .and_then(|suffix| base.strip_suffix(suffix))
+                .filter(|stripped| !stripped.is_empty())
+                .unwrap_or(base);
+
+            outln!("{base}");
+        }
+
         0
     }
 
-    /// Mimics `builtin` builtin Unix shell command. [Linux man page]()
+    /// Mimics `builtin` bash builtin, running `BUILTIN` even where a program of the same
+    /// name would run. [Bash manual](https://www.gnu.org/software/bash/manual/html_node/Bash-Builtins.html)
+    ///
+    /// Usage: `builtin [BUILTIN [ARGS...]]`
     #[async_recursion]
     #[must_use]
     pub(crate) async fn builtin(args: &[String]) -> i32 {
+        // the first word is `builtin` itself
+        let Some(name) = args.get(1) else {
+            return 0;
+        };
+
         match Self::run(&args[1..]).await {
-            Ok(result) => result,
+            Ok(code) => code,
             Err(error) => match error.kind {
                 ErrorKind::InvalidBuiltin => {
-                    error!("no such builtin: {error}");
+                    errln!("builtin: {name}: not a shell builtin");
                     1
                 }
                 ErrorKind::InvalidInput => {
-                    error!("{error}");
+                    errln!("builtin: {error}");
                     2
                 }
             },
@@ -131,23 +381,212 @@ impl Builtin {
     }
 
     /// Mimics `cd` builtin Unix shell command. [Linux man page](https://man7.org/linux/man-pages/man1/cd.1p.html)
+    ///
+    /// Usage: `cd [--] [PATH]`, going home without `PATH`, and back to `$OLDPWD` with `-`. A
+    /// leading `~` of `PATH` is the home directory even if it was quoted.
+    pub(crate) async fn cd(args: &[String]) -> i32 {
+        // `--` lets paths start with `-`
+        let args = match args.get(1).map(String::as_str) {
+            Some("--") => &args[2..],
+            _ => &args[1..],
+        };
+
+        let path = match args {
+            [] => {
+                let Some(home_dir) = user::home_dir() else {
+                    errln!("cd: could not find home directory");
+                    return 1;
+                };
+                home_dir
+            }
+            [path] if path == "-" => {
+                let Some(old_dir) = std::env::var_os("OLDPWD").filter(|dir| !dir.is_empty()) else {
+                    errln!("cd: OLDPWD not set");
+                    return 1;
+                };
+                // like other shells, tell where `cd -` went
+                outln!("{}", old_dir.to_string_lossy());
+                PathBuf::from(old_dir)
+            }
+            [path] => user::expand_tilde(path),
+            _ => {
+                errln!("cd: expected [PATH] argument");
+                return 1;
+            }
+        };
+
+        // `..` can't be looked up from a removed directory, so relative paths start at `$PWD`
+        let path = if path.is_relative() && std::env::current_dir().is_err() {
+            normalize(&user::working_dir().join(path))
+        } else {
+            path
+        };
+
+        let path = if path.exists() {
+            path
+        } else {
+            let correct = OPTIONS.lock().await.is_set(ShellOption::Correct);
+
+            match correct.then(|| correct::directory(&path)).flatten() {
+                Some(corrected)
+                    if user::confirm(&format!(
+                        "cd: correct {} to {}?",
+                        quote(&path.to_string_lossy()),
+                        quote(&corrected.to_string_lossy())
+                    )) =>
+                {
+                    corrected
+                }
+                _ => {
+                    errln!("cd: no such file or directory: {}", path.display());
+                    return 2;
+                }
+            }
+        };
+
+        let old_dir = user::working_dir();
+        if let Err(error) = Self::change_dir(&path).await {
+            errln!("cd: {error}");
+            return 3;
+        }
+
+        if OPTIONS.lock().await.is_set(ShellOption::AutoPushd) {
+            dir_stack::push(old_dir);
+        }
+
+        0
+    }
+
+    /// Changes the working directory to `path`, setting `$OLDPWD` and `$PWD`, then applies what
+    /// depends on it, like `direnv` and the `chpwd` hooks.
+    async fn change_dir(path: &Path) -> std::io::Result<()> {
+        let old_dir = user::working_dir();
+        std::env::set_current_dir(path)?;
+
+        std::env::set_var("OLDPWD", old_dir);
+        if let Ok(current_dir) = std::env::current_dir() {
+            std::env::set_var("PWD", current_dir);
+        }
+
+        direnv::export().await;
+        project::enter().await;
+        chpwd::run().await;
+
+        Ok(())
+    }
+
+    /// Adds `COMMAND` to the hooks run whenever `cd` changes the working directory, or removes
+    /// it with `-r`, or every hook without a command. Without arguments the hooks are printed as
+    /// `chpwd` commands that add them again.
+    ///
+    /// Usage: `chpwd [COMMAND]` or `chpwd -r [COMMAND]`
     #[must_use]
-    pub(crate) fn cd(args: &[String]) -> i32 {
-        if args.len() != 2 {
-            eprintln!("cd: expected [PATH] argument");
-            return 1;
+    pub(crate) fn chpwd(args: &[String]) -> i32 {
+        match &args[1..] {
+            [] => {
+                for hook in chpwd::list() {
+                    outln!("chpwd {}", quote(&hook));
+                }
+            }
+            [flag] if flag == "-r" => chpwd::clear(),
+            [flag, command] if flag == "-r" => {
+                if !chpwd::remove(command) {
+                    errln!("chpwd: no such hook: {command}");
+                    return 1;
+                }
+            }
+            [command] => chpwd::add(command.clone()),
+            _ => {
+                errln!("chpwd: expected a single COMMAND, quoted if it has spaces");
+                return 2;
+            }
         }
 
-        let path = Path::new(&args[1]);
+        0
+    }
+
+    /// Mimics `complete` builtin bash command, for static word lists. [Bash manual](https://www.gnu.org/software/bash/manual/html_node/Programmable-Completion-Builtins.html)
+    ///
+    /// Usage: `complete -W WORDLIST NAME...`, `complete -C PROGRAM NAME...`, `complete [-p]` and
+    /// `complete -r NAME...`
+    pub(crate) async fn complete(args: &[String]) -> i32 {
+        let mut lock = COMPLETIONS.lock().await;
+
+        match args.get(1).map(String::as_str) {
+            None | Some("-p") => {
+                for (command, spec) in lock.specs() {
+                    if !spec.words.is_empty() {
+                        let words = spec
+                            .words
+                            .iter()
+                            .map(|word| word.value.as_str())
+                            .collect::<Vec<_>>();
+                        outln!("complete -W {} {command}", quote(&words.join(" ")));
+                    }
+                    if let Some(completer) = &spec.completer {
+                        outln!("complete -C {} {command}", quote(completer));
+                    }
+                }
+            }
+            Some("-r") => {
+                for command in &args[2..] {
+                    lock.remove(command);
+                }
+            }
+            _ => {
+                if !completion::load_bash(&mut lock, args) {
+                    errln!("complete: expected -W WORDLIST or -C PROGRAM, then NAME...");
+                    return 2;
+                }
+            }
+        }
+
+        0
+    }
+
+    /// Mimics `dirs` bash builtin, printing the working directory followed by the directory
+    /// stack, the directory pushed last first, with `~` for the home directory unless `-l` is
+    /// given. `-v` prints them one per line, numbered from 0, and `-c` empties the stack.
+    /// [bash manual](https://www.gnu.org/software/bash/manual/html_node/Directory-Stack-Builtins.html)
+    ///
+    /// Usage: `dirs [-c|-l|-v]`
+    #[must_use]
+    pub(crate) fn dirs(args: &[String]) -> i32 {
+        match args.get(1..).unwrap_or_default() {
+            [] => print_dirs(false, false),
+            [flag] if flag == "-l" => print_dirs(true, false),
+            [flag] if flag == "-v" => print_dirs(false, true),
+            [flag] if flag == "-c" => dir_stack::clear(),
+            _ => {
+                errln!("dirs: usage: dirs [-c|-l|-v]");
+                return 2;
+            }
+        }
 
-        if !path.exists() {
-            eprintln!("cd: no such file or directory: {}", path.display());
+        0
+    }
+
+    /// Mimics the `dirname` Unix command. [Linux man page](https://man7.org/linux/man-pages/man1/dirname.1.html)
+    ///
+    /// Usage: `dirname NAME...`
+    #[must_use]
+    pub(crate) fn dirname(args: &[String]) -> i32 {
+        if args.len() < 2 {
+            errln!("dirname: expected NAME");
             return 2;
         }
 
-        if let Err(error) = std::env::set_current_dir(path) {
-            eprintln!("cd: {error}");
-            return 3;
+        for name in &args[1..] {
+            let dir = match name.trim_end_matches('/').rsplit_once('/') {
+                Some((dir, _)) => match dir.trim_end_matches('/') {
+                    "" => "/",
+                    dir => dir,
+                },
+                None if name.starts_with('/') => "/",
+                None => ".",
+            };
+
+            outln!("{dir}");
         }
 
         0
@@ -156,73 +595,2391 @@ impl Builtin {
     /// Mimics `echo` builtin Unix shell command. [Linux man page](https://man7.org/linux/man-pages/man1/echo.1p.html)
     #[must_use]
     pub(crate) fn echo(args: &[String]) -> i32 {
-        println!("{}", args[1..].join(" "));
+        outln!("{}", args[1..].join(" "));
         0
     }
 
-    /// Mimics `exit` builtin Unix shell command. [Linux man page](https://man7.org/linux/man-pages/man3/exit.3.html)
+    /// Shows how the variables changed since `envdiff snap` took a snapshot of them, like after
+    /// running the commands of an `env.sh`: `+` for an added variable, `-` for a removed one and
+    /// `~` for a changed one, each with its scope, shell or environment.
+    ///
+    /// Usage: `envdiff snap` or `envdiff`
     #[must_use]
-    pub(crate) fn exit(args: &[String]) -> i32 {
-        args.get(0)
-            .unwrap_or(&String::from("0"))
-            .parse()
-            .unwrap_or(0)
+    pub(crate) fn envdiff(args: &[String]) -> i32 {
+        match args.get(1).map(String::as_str) {
+            Some("snap") => {
+                envdiff::snap();
+                return 0;
+            }
+            Some(command) => {
+                errln!("envdiff: unknown command: {command}");
+                return 2;
+            }
+            None => {}
+        }
+
+        let Some(changes) = envdiff::diff() else {
+            errln!("envdiff: no snapshot, take one with envdiff snap");
+            return 1;
+        };
+
+        for (name, change) in changes {
+            match change {
+                envdiff::Change::Added(value, scope) => outln!("+{name}={value} ({scope})"),
+                envdiff::Change::Removed(value, scope) => outln!("-{name}={value} ({scope})"),
+                envdiff::Change::Changed((old, old_scope), (new, scope)) => {
+                    let scope = if old_scope == scope {
+                        scope.to_string()
+                    } else {
+                        format!("{old_scope} -> {scope}")
+                    };
+                    outln!("~{name}={new} ({scope}, was {old})");
+                }
+            }
+        }
+
+        0
     }
 
-    /// Mimics `history` builtin Unix shell command. [Linux man page](https://www.man7.org/linux/man-pages/man3/history.3.html)
+    /// Mimics `exec` builtin Unix shell command, replacing the shell with `COMMAND`. Its
+    /// redirections, like `exec 3< file` or `exec 3>&-`, are applied to the shell itself
+    /// beforehand, so without a command they last for the rest of the session. [Linux man page](https://man7.org/linux/man-pages/man1/exec.1p.html)
     ///
-    /// # Panics
+    /// Usage: `exec [COMMAND [ARGS...]]`
+    #[must_use]
+    pub(crate) fn exec(args: &[String]) -> i32 {
+        use std::os::unix::process::CommandExt;
+
+        let Some(command) = args.get(1) else {
+            return 0;
+        };
+
+        let plan = match Plan::new(&[]) {
+            Ok(plan) => plan,
+            Err(error) => {
+                errln!("exec: {error}");
+                return 1;
+            }
+        };
+
+        let mut process = std::process::Command::new(command);
+        process.args(&args[2..]);
+        // SAFETY: the installer only calls async-signal-safe functions.
+        unsafe {
+            process.pre_exec(plan.installer());
+        }
+
+        // only returns if it failed
+        let error = process.exec();
+
+        if error.kind() == std::io::ErrorKind::NotFound {
+            errln!("exec: command not found: {command}");
+            127
+        } else {
+            errln!("exec: {command}: {error}");
+            126
+        }
+    }
+
+    /// Mimics `exit` builtin Unix shell command, exiting with `CODE` modulo 256, or the exit
+    /// code of the previous command. In a subshell or a job, only that stops.
+    /// [Linux man page](https://man7.org/linux/man-pages/man1/exit.1p.html)
     ///
-    /// Panics if line from history file could not be read.
-    pub(crate) async fn history(_args: &[String]) -> i32 {
-        let mut history = PathBuf::from(env::var("HOME").unwrap_or_else(|_| "/".to_string()));
-        history.push(".rshistory");
+    /// Usage: `exit [CODE]`
+    pub(crate) async fn exit(args: &[String]) -> i32 {
+        let code = match args.get(1..).unwrap_or_default() {
+            [] => *PREVIOUS_EXIT_CODE.lock().await,
+            [code] => {
+                if let Ok(code) = code.parse::<i64>() {
+                    // in 0..256
+                    i32::try_from(code.rem_euclid(256)).unwrap_or_default()
+                } else {
+                    errln!("{}: {code}: numeric argument required", args[0]);
+                    2
+                }
+            }
+            _ => {
+                errln!("{}: too many arguments", args[0]);
+                return 1;
+            }
+        };
 
-        let Ok(history) = tokio::fs::read(history).await else {
-            error!("could not read from ~/.rshistory");
+        if subshell::exit(code) {
+            return code;
+        }
+
+        if !job::confirm_exit().await {
             return 1;
+        }
+
+        trap::exit(code).await
+    }
+
+    /// Mimics `export` builtin Unix shell command, moving the shell variables `NAME` to the
+    /// environment, where the commands run by the shell see them, after setting those with a
+    /// `VALUE`. Without any, the environment is printed as `export` commands.
+    /// [Linux man page](https://man7.org/linux/man-pages/man1/export.1p.html)
+    ///
+    /// Usage: `export [-p] [NAME[=VALUE]...]`
+    #[must_use]
+    pub(crate) fn export(args: &[String]) -> i32 {
+        let args = match args.get(1).map(String::as_str) {
+            Some("-p") => &args[2..],
+            _ => &args[1..],
         };
 
-        for (i, line) in history.lines().enumerate() {
-            println!("{} {}", i + 1, line.unwrap());
+        if args.is_empty() {
+            let mut vars = std::env::vars_os()
+                .map(|(name, value)| {
+                    format!("{}={}", name.to_string_lossy(), value.to_string_lossy())
+                })
+                .collect::<Vec<_>>();
+            vars.sort();
+
+            for var in vars {
+                outln!("export {}", quote(&var));
+            }
+            return 0;
         }
-        0
+
+        let mut code = 0;
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+
+            if variables::is_name(name) {
+                variables::export(name, value);
+            } else {
+                errln!("export: {name}: not a valid identifier");
+                code = 1;
+            }
+        }
+
+        code
     }
 
-    /// Mimics `pwd` builtin Unix shell command. [Linux man page](https://man7.org/linux/man-pages/man1/pwd.1.html)
+    /// Prints the paths matching each `PATTERN`, like `src/*.rs`, one per line or each followed by
+    /// a NUL character with `-0`, for `xargs -0`. Fails if nothing matched.
+    ///
+    /// Usage: `glob [-0] [--] PATTERN...`
     #[must_use]
-    pub(crate) fn pwd(_args: &[String]) -> i32 {
+    pub(crate) fn glob(args: &[String]) -> i32 {
+        let (flags, patterns) = match parse_flags(&args[1..], &[], &["-0", "--null"]) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                errln!("glob: {error}");
+                return 2;
+            }
+        };
+        if patterns.is_empty() {
+            errln!("glob: expected a PATTERN");
+            return 2;
+        }
+        let terminator = if flags.contains_key("--null") {
+            '\0'
+        } else {
+            '\n'
+        };
+
+        let mut matched = false;
+        for pattern in patterns {
+            for path in glob::expand(pattern) {
+                matched = true;
+                out!("{path}{terminator}");
+            }
+        }
+
+        i32::from(!matched)
+    }
+
+    /// Changes directory to the root of the current git repository, or to `PATH` relative to it.
+    ///
+    /// Usage: `groot [PATH]`
+    pub(crate) async fn groot(args: &[String]) -> i32 {
+        if args.len() > 2 {
+            errln!("groot: too many arguments");
+            return 1;
+        }
+
         let Ok(current_dir) = std::env::current_dir() else {
-            error!("could not find current directory");
+            errln!("groot: could not find current directory");
+            return 1;
+        };
+
+        let Some(root) = git::discover(&current_dir) else {
+            errln!("groot: not inside a git repository");
             return 1;
         };
 
-        println!("{}", current_dir.display());
+        let path = args
+            .get(1)
+            .map_or_else(|| root.clone(), |path| root.join(path));
+
+        Self::cd(&[args[0].clone(), path.display().to_string()]).await
+    }
+
+    /// Guards commands matching `PATTERN`, which is matched against a command's words joined
+    /// with spaces, asking for confirmation before they run. With `--hook`, `PROGRAM` is run
+    /// with the command's words instead and must succeed for the command to run.
+    ///
+    /// Usage: `guard [-p]`, `guard [--hook PROGRAM] PATTERN...` and `guard -e PATTERN...`
+    pub(crate) async fn guard(args: &[String]) -> i32 {
+        let mut lock = GUARDS.lock().await;
+
+        match args.get(1).map(String::as_str) {
+            None | Some("-p") => {
+                for guard in lock.guards() {
+                    if let Some(hook) = &guard.hook {
+                        outln!("guard --hook {} {}", quote(hook), quote(&guard.pattern));
+                    } else {
+                        outln!("guard {}", quote(&guard.pattern));
+                    }
+                }
+            }
+            Some("-e") if args.len() > 2 => {
+                let pattern = args[2..].join(" ");
+                if !lock.remove(&pattern) {
+                    errln!("guard: {pattern}: not found");
+                    return 1;
+                }
+            }
+            Some("--hook") if args.len() > 3 => lock.add(Guard {
+                pattern: args[3..].join(" "),
+                hook: Some(args[2].clone()),
+            }),
+            Some("-e" | "--hook") => {
+                errln!("guard: expected PATTERN");
+                return 2;
+            }
+            Some(_) => lock.add(Guard {
+                pattern: args[1..].join(" "),
+                hook: None,
+            }),
+        }
+
         0
     }
 
-    /// Runs a builtin if it is one.
+    /// Mimics `hash -d` zsh builtin command, naming directories which `~NAME` expands to and the
+    /// prompt shows as `~NAME`. Without `NAME=PATH`, the named directories are printed as
+    /// `hash -d` commands that name them again; hashing commands isn't supported.
+    /// [Zsh manual](https://zsh.sourceforge.io/Doc/Release/Shell-Builtin-Commands.html)
     ///
-    /// # Errors
+    /// Usage: `hash -d [NAME=PATH...]`
+    #[must_use]
+    pub(crate) fn hash(args: &[String]) -> i32 {
+        if args.get(1).map(String::as_str) != Some("-d") {
+            errln!("hash: only named directories are supported, with -d");
+            return 2;
+        }
+
+        if args.len() == 2 {
+            for (name, dir) in named_dirs::all() {
+                outln!("hash -d {}", quote(&format!("{name}={}", dir.display())));
+            }
+            return 0;
+        }
+
+        let mut code = 0;
+        for arg in &args[2..] {
+            match arg.split_once('=') {
+                Some((name, path))
+                    if !name.is_empty() && !name.contains('/') && !path.is_empty() =>
+                {
+                    let dir = user::expand_tilde(path);
+                    let dir = if dir.is_relative() {
+                        normalize(&user::working_dir().join(dir))
+                    } else {
+                        dir
+                    };
+                    named_dirs::set(name.to_string(), dir);
+                }
+                _ => {
+                    errln!("hash: expected NAME=PATH, not {arg}");
+                    code = 1;
+                }
+            }
+        }
+
+        code
+    }
+
+    /// Prints the usage of the given builtins, or of all of them, through the pager if it is
+    /// needed, see [`ShellOption::Pager`].
     ///
-    /// This function will return an error if the command is not a builtin [`std::io::ErrorKind::InvalidInput`].
-    pub(crate) async fn run(args: &[String]) -> Result<i32, Error> {
-        if args.is_empty() {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                String::from("expected 1 argument"),
-            ));
+    /// Usage: `help [BUILTIN...]`
+    pub(crate) fn help(args: &[String]) -> i32 {
+        let mut text = String::new();
+
+        if args.len() == 1 {
+            for name in Self::NAMES {
+                let Ok(builtin) = Self::from_str(name) else {
+                    continue;
+                };
+                // other names, like `bye`, are left out
+                let usage = builtin.usage();
+                if usage.split_whitespace().next() == Some(*name) {
+                    text.push_str(usage);
+                    text.push('\n');
+                }
+            }
         }
 
-        match Self::from_str(args[0].as_str()) {
-            Ok(Self::Alias) => Ok(Self::alias(args).await),
-            Ok(Self::Builtin) => Ok(Self::builtin(args).await),
-            Ok(Self::Cd) => Ok(Self::cd(args)),
-            Ok(Self::Echo) => Ok(Self::echo(args)),
-            Ok(Self::Exit) => Ok(Self::exit(args)),
-            Ok(Self::History) => Ok(Self::history(args).await),
-            Ok(Self::Pwd) => Ok(Self::pwd(args)),
-            Err(command) => Err(Error::new(ErrorKind::InvalidBuiltin, command)),
+        for name in &args[1..] {
+            match Self::from_str(name) {
+                Ok(builtin) => {
+                    text.push_str(builtin.usage());
+                    text.push('\n');
+                }
+                Err(name) => {
+                    errln!("help: no such builtin: {name}");
+                    return 1;
+                }
+            }
+        }
+
+        if let Err(error) = pager::print(&text) {
+            errln!("help: {error}");
+            return 1;
+        }
+
+        0
+    }
+
+    /// Mimics `history` builtin Unix shell command. [Linux man page](https://www.man7.org/linux/man-pages/man3/history.3.html)
+    pub(crate) async fn history(_args: &[String]) -> i32 {
+        // commands waiting to be written are listed too
+        crate::history::flush();
+
+        let mut history = user::home_dir().unwrap_or_else(|| PathBuf::from("/"));
+        history.push(RSHISTORY);
+
+        let Ok(history) = tokio::fs::read_to_string(history).await else {
+            error!("could not read from ~/.rshistory");
+            return 1;
+        };
+
+        for (i, command) in crate::history::entries(&history).iter().enumerate() {
+            outln!("{} {command}", i + 1);
+        }
+        0
+    }
+
+    /// Mimics `math` fish builtin, printing the value of a floating point expression with at
+    /// most `SCALE` decimals, 6 by default. [fish manual](https://fishshell.com/docs/current/cmds/math.html)
+    ///
+    /// Usage: `math [-s SCALE] EXPRESSION...`
+    #[must_use]
+    pub(crate) fn math(args: &[String]) -> i32 {
+        let (scale, expression) = match args.get(1).map(String::as_str) {
+            Some("-s" | "--scale") => {
+                let Some(scale) = args.get(2).and_then(|scale| scale.parse::<usize>().ok()) else {
+                    errln!("math: expected -s SCALE");
+                    return 2;
+                };
+                (scale, &args[3..])
+            }
+            _ => (6, &args[1..]),
+        };
+
+        if expression.is_empty() {
+            errln!("math: expected EXPRESSION");
+            return 2;
+        }
+
+        let value = match math::eval(&expression.join(" ")) {
+            Ok(value) if value.is_finite() => value,
+            Ok(value) => {
+                errln!("math: result is {value}");
+                return 1;
+            }
+            Err(error) => {
+                errln!("math: {error}");
+                return 1;
+            }
+        };
+
+        // a scale of 0 truncates, like fish
+        let value = if scale == 0 { value.trunc() } else { value };
+        let value = format!("{value:.scale$}");
+        let value = if value.contains('.') {
+            value.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            &value
+        };
+
+        // rounding may leave a negative zero
+        outln!("{}", if value == "-0" { "0" } else { value });
+        0
+    }
+
+    /// Mimics `jobs` builtin Unix shell command, listing the jobs started with `&` that weren't
+    /// reported or waited for yet. `-l` also lists their process IDs. [Linux man page](https://man7.org/linux/man-pages/man1/jobs.1p.html)
+    ///
+    /// Usage: `jobs [-l]`
+    pub(crate) async fn jobs(args: &[String]) -> i32 {
+        let pids = match args.get(1).map(String::as_str) {
+            None => false,
+            Some("-l") if args.len() == 2 => true,
+            Some(_) => {
+                errln!("jobs: usage: jobs [-l]");
+                return 2;
+            }
+        };
+
+        for job in JOBS.lock().await.jobs() {
+            let state = if job.is_running() { "Running" } else { "Done" };
+            if pids {
+                outln!("[{}] {} {state} {}", job.id, job.pid, job.command);
+            } else {
+                outln!("[{}] {state} {}", job.id, job.command);
+            }
+        }
+
+        0
+    }
+
+    /// Mimics the util-linux `ionice` command, running `COMMAND` with the processes it spawns in
+    /// the I/O scheduling `CLASS`, `1` or `realtime`, `2` or `best-effort` (the default) and `3` or
+    /// `idle`, at `LEVEL` from 0, the highest, to 7, 4 by default. Only supported on Linux.
+    /// [Linux man page](https://man7.org/linux/man-pages/man1/ionice.1.html)
+    ///
+    /// Usage: `ionice [-c CLASS] [-n LEVEL] [--] COMMAND [ARGS...]`
+    #[async_recursion]
+    pub(crate) async fn ionice(args: &[String]) -> i32 {
+        /// How far the class is shifted in the priority given to `ioprio_set`.
+        const IOPRIO_CLASS_SHIFT: i32 = 13;
+
+        if !cfg!(target_os = "linux") {
+            errln!("ionice: not supported on this system");
+            return 1;
+        }
+
+        let (flags, operands) =
+            match parse_flags(&args[1..], &["-c", "--class", "-n", "--classdata"], &[]) {
+                Ok(parsed) => parsed,
+                Err(error) => {
+                    errln!("ionice: {error}");
+                    return 2;
+                }
+            };
+
+        let class = match flags.get("--class").copied().unwrap_or("2") {
+            "1" | "realtime" => 1,
+            "2" | "best-effort" => 2,
+            "3" | "idle" => 3,
+            class => {
+                errln!("ionice: unknown class: {class}");
+                return 2;
+            }
+        };
+        let level = match flags.get("--classdata").map(|level| level.parse::<i32>()) {
+            None => 4,
+            Some(Ok(level)) if (0..=7).contains(&level) => level,
+            Some(_) => {
+                errln!("ionice: expected a LEVEL from 0 to 7");
+                return 2;
+            }
+        };
+
+        let Some((keyword, command_args)) = operands.split_first() else {
+            errln!("ionice: expected COMMAND");
+            return 2;
+        };
+        let command = Command::new(
+            (*keyword).to_string(),
+            command_args.iter().map(ToString::to_string).collect(),
+        );
+
+        // the idle class has no levels
+        let level = if class == 3 { 0 } else { level };
+        let priority = Priority {
+            io: Some(class << IOPRIO_CLASS_SHIFT | level),
+            ..priority::current()
+        };
+
+        priority::scope(priority, command.interpret()).await
+    }
+
+    /// Mimics the `nice` Unix command, running `COMMAND` with the processes it spawns `ADJUSTMENT`
+    /// nicer than the shell, 10 by default. A negative one, favoring them, needs privileges.
+    /// [Linux man page](https://man7.org/linux/man-pages/man1/nice.1.html)
+    ///
+    /// Usage: `nice [-n ADJUSTMENT] [--] COMMAND [ARGS...]`
+    #[async_recursion]
+    pub(crate) async fn nice(args: &[String]) -> i32 {
+        let (flags, operands) = match parse_flags(&args[1..], &["-n", "--adjustment"], &[]) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                errln!("nice: {error}");
+                return 2;
+            }
+        };
+
+        let adjustment = match flags.get("--adjustment").map(|value| value.parse::<i32>()) {
+            None => 10,
+            Some(Ok(adjustment)) => adjustment.clamp(-39, 39),
+            Some(Err(_)) => {
+                errln!("nice: expected a numeric ADJUSTMENT");
+                return 2;
+            }
+        };
+
+        let Some((keyword, command_args)) = operands.split_first() else {
+            errln!("nice: expected COMMAND");
+            return 2;
+        };
+        let command = Command::new(
+            (*keyword).to_string(),
+            command_args.iter().map(ToString::to_string).collect(),
+        );
+
+        let current = priority::current();
+        let priority = Priority {
+            niceness: current.niceness.saturating_add(adjustment),
+            ..current
+        };
+
+        priority::scope(priority, command.interpret()).await
+    }
+
+    /// Mimics `popd` bash builtin, changing to the directory pushed last and removing it from
+    /// the directory stack, then printing the stack like `dirs`.
+    /// [bash manual](https://www.gnu.org/software/bash/manual/html_node/Directory-Stack-Builtins.html)
+    ///
+    /// Usage: `popd`
+    pub(crate) async fn popd(args: &[String]) -> i32 {
+        if args.len() > 1 {
+            errln!("popd: too many arguments");
+            return 2;
+        }
+
+        let Some(dir) = dir_stack::pop() else {
+            errln!("popd: directory stack empty");
+            return 1;
+        };
+
+        if let Err(error) = Self::change_dir(&dir).await {
+            errln!("popd: {}: {error}", dir.display());
+            return 1;
+        }
+
+        print_dirs(false, false);
+        0
+    }
+
+    /// Mimics `pushd` bash builtin, pushing the working directory onto the directory stack and
+    /// changing to `PATH`, or swapping it with the directory pushed last without `PATH`, then
+    /// printing the stack like `dirs`.
+    /// [bash manual](https://www.gnu.org/software/bash/manual/html_node/Directory-Stack-Builtins.html)
+    ///
+    /// Usage: `pushd [--] [PATH]`
+    pub(crate) async fn pushd(args: &[String]) -> i32 {
+        let args = match args.get(1).map(String::as_str) {
+            Some("--") => &args[2..],
+            _ => &args[1..],
+        };
+
+        let dir = match args {
+            [] => {
+                let Some(dir) = dir_stack::pop() else {
+                    errln!("pushd: no other directory");
+                    return 1;
+                };
+                dir
+            }
+            [path] => normalize(&user::working_dir().join(user::expand_tilde(path))),
+            _ => {
+                errln!("pushd: too many arguments");
+                return 2;
+            }
+        };
+
+        let old_dir = user::working_dir();
+        if let Err(error) = Self::change_dir(&dir).await {
+            errln!("pushd: {}: {error}", dir.display());
+            // the directory it was swapped with stays on the stack
+            if args.is_empty() {
+                dir_stack::push(dir);
+            }
+            return 1;
+        }
+
+        dir_stack::push(old_dir);
+        print_dirs(false, false);
+        0
+    }
+
+    /// Mimics `pwd` builtin Unix shell command. [Linux man page](https://man7.org/linux/man-pages/man1/pwd.1.html)
+    ///
+    /// Prints `$PWD` if the working directory was removed.
+    #[must_use]
+    pub(crate) fn pwd(_args: &[String]) -> i32 {
+        outln!("{}", user::working_dir().display());
+        0
+    }
+
+    /// Mimics the GNU `readlink` command. [Linux man page](https://man7.org/linux/man-pages/man1/readlink.1.html)
+    ///
+    /// Prints the target of every symbolic link `PATH`, failing quietly for the ones that aren't.
+    /// `-f`, `-e` and `-m` print the canonical path of `PATH` instead, like `realpath` does with
+    /// no flag, `-e` and `-m`. `-n` leaves out the newlines.
+    ///
+    /// Usage: `readlink [-f|-e|-m] [-n] [--] PATH...`
+    #[must_use]
+    pub(crate) fn readlink(args: &[String]) -> i32 {
+        let (flags, paths) = match parse_flags(
+            &args[1..],
+            &[],
+            &[
+                "-f",
+                "--canonicalize",
+                "-e",
+                "--canonicalize-existing",
+                "-m",
+                "--canonicalize-missing",
+                "-n",
+                "--no-newline",
+            ],
+        ) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                errln!("readlink: {error}");
+                return 2;
+            }
+        };
+        if paths.is_empty() {
+            errln!("readlink: expected a PATH");
+            return 2;
+        }
+        let terminator = if flags.contains_key("--no-newline") {
+            ""
+        } else {
+            "\n"
+        };
+        let canonical = [
+            "--canonicalize",
+            "--canonicalize-existing",
+            "--canonicalize-missing",
+        ]
+        .iter()
+        .any(|flag| flags.contains_key(flag));
+
+        let mut code = 0;
+        for path in paths {
+            let target = if canonical {
+                canonicalize(Path::new(path), missing_components(&flags))
+            } else {
+                std::fs::read_link(user::working_dir().join(path))
+            };
+
+            match target {
+                Ok(target) => out!("{}{terminator}", target.display()),
+                Err(_) => code = 1,
+            }
+        }
+
+        code
+    }
+
+    /// Mimics the GNU `realpath` command. [Linux man page](https://man7.org/linux/man-pages/man1/realpath.1.html)
+    ///
+    /// Prints the absolute path of every `PATH` with its symbolic links, `.` and `..` resolved.
+    /// Every directory above `PATH` must exist, and `PATH` itself too with `-e`, while `-m` lets
+    /// any of them be missing. `-s` only resolves `.` and `..`, leaving symbolic links as they are.
+    ///
+    /// Usage: `realpath [-e|-m] [-s] [--] PATH...`
+    #[must_use]
+    pub(crate) fn realpath(args: &[String]) -> i32 {
+        let (flags, paths) = match parse_flags(
+            &args[1..],
+            &[],
+            &[
+                "-e",
+                "--canonicalize-existing",
+                "-m",
+                "--canonicalize-missing",
+                "-s",
+                "--no-symlinks",
+            ],
+        ) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                errln!("realpath: {error}");
+                return 2;
+            }
+        };
+        if paths.is_empty() {
+            errln!("realpath: expected a PATH");
+            return 2;
+        }
+
+        let mut code = 0;
+        for path in paths {
+            let resolved = if flags.contains_key("--no-symlinks") {
+                Ok(normalize(&user::working_dir().join(path)))
+            } else {
+                canonicalize(Path::new(path), missing_components(&flags))
+            };
+
+            match resolved {
+                Ok(resolved) => outln!("{}", resolved.display()),
+                Err(error) => {
+                    errln!("realpath: {path}: {error}");
+                    code = 1;
+                }
+            }
+        }
+
+        code
+    }
+
+    /// Records the session to `FILE` in the asciicast format, replayable with `asciinema play`,
+    /// until `record stop`. With `-o`, the output of commands is recorded too. Without arguments,
+    /// prints the running recording.
+    ///
+    /// Usage: `record start [-o] FILE`, `record stop` and `record`
+    #[must_use]
+    pub(crate) fn record(args: &[String]) -> i32 {
+        match args.get(1).map(String::as_str) {
+            None => match record::current() {
+                Some((path, output)) => {
+                    let flag = if output { " -o" } else { "" };
+                    outln!("record start{flag} {}", quote(&path.display().to_string()));
+                }
+                None => return 1,
+            },
+            Some("start") => {
+                let (output, path) = match &args[2..] {
+                    [flag, path] if flag == "-o" || flag == "--output" => (true, path),
+                    [path] => (false, path),
+                    _ => {
+                        errln!("record: expected start [-o] FILE");
+                        return 2;
+                    }
+                };
+
+                if let Err(error) = record::start(Path::new(path), output) {
+                    errln!("record: {path}: {error}");
+                    return 1;
+                }
+            }
+            Some("stop") => {
+                if record::stop().is_none() {
+                    errln!("record: not recording");
+                    return 1;
+                }
+            }
+            Some(command) => {
+                errln!("record: unknown command: {command}");
+                return 2;
+            }
+        }
+
+        0
+    }
+
+    /// Mimics `repeat` zsh builtin, running `COMMAND` `COUNT` times. With the `errexit` option,
+    /// it stops at the first failing run. [zsh manual](https://zsh.sourceforge.io/Doc/Release/Shell-Grammar.html#Complex-Commands)
+    ///
+    /// Usage: `repeat COUNT COMMAND...`
+    ///
+    /// Returns the exit code of the last run.
+    #[async_recursion]
+    pub(crate) async fn repeat(args: &[String]) -> i32 {
+        let Some(count) = args.get(1).and_then(|count| count.parse::<usize>().ok()) else {
+            errln!("repeat: expected COUNT");
+            return 2;
+        };
+
+        let Some((keyword, command_args)) = args.get(2..).and_then(<[String]>::split_first) else {
+            errln!("repeat: expected COMMAND");
+            return 2;
+        };
+        let command = Command::new(keyword.clone(), command_args.to_vec());
+
+        let mut code = 0;
+        for _ in 0..count {
+            code = command.interpret().await;
+
+            if code != 0 && OPTIONS.lock().await.is_set(ShellOption::ErrExit) {
+                break;
+            }
+        }
+
+        code
+    }
+
+    /// Summarizes the commands recorded with `set -o metrics` over the last `WINDOW`, which
+    /// defaults to a day: the slowest commands and the commands failing most often.
+    ///
+    /// Usage: `report [WINDOW]`, where `WINDOW` is like `30m`, `12h` or `7d`.
+    pub(crate) async fn report(args: &[String]) -> i32 {
+        /// How many commands are listed in each section.
+        const TOP: usize = 10;
+
+        let window = match args.get(1) {
+            None => Duration::from_hours(24),
+            Some(window) => {
+                let Some(window) = parse_duration(window) else {
+                    errln!("report: invalid window: {window}");
+                    return 2;
+                };
+                window
+            }
+        };
+
+        let mut records = match metrics::read(window).await {
+            Ok(records) => records,
+            Err(error) => {
+                errln!("report: could not read ~/{RSMETRICS}: {error}");
+                return 1;
+            }
+        };
+
+        let failed = records.iter().filter(|record| record.code != 0).count();
+        outln!(
+            "{} commands, {failed} failed ({:.1}%)",
+            records.len(),
+            percent(failed, records.len())
+        );
+
+        if records.is_empty() {
+            return 0;
+        }
+
+        let failures = metrics::failure_rates(&records)
+            .into_iter()
+            .filter(|(_, failures)| failures.failed > 0)
+            .take(TOP)
+            .map(|(name, failures)| (name.to_string(), failures))
+            .collect::<Vec<_>>();
+
+        records.sort_by_key(|record| std::cmp::Reverse(record.duration));
+
+        outln!("\nslowest:");
+        for record in records.iter().take(TOP) {
+            outln!(
+                "{:>10.3}s  {}  ({})",
+                record.duration.as_secs_f64(),
+                record.command,
+                user::display_path(&record.cwd)
+            );
+        }
+
+        if !failures.is_empty() {
+            outln!("\nfailing:");
+            for (name, failures) in failures {
+                outln!(
+                    "{:>10.1}%  {name}  ({}/{})",
+                    percent(failures.failed, failures.runs),
+                    failures.failed,
+                    failures.runs
+                );
+            }
+        }
+
+        0
+    }
+
+    /// Mimics the `seq` Unix command. [Linux man page](https://man7.org/linux/man-pages/man1/seq.1.html)
+    ///
+    /// Usage: `seq [-w] [-s SEPARATOR] [-f FORMAT] [FIRST [INCREMENT]] LAST`, where `FORMAT` is
+    /// a `printf` floating point conversion like `%.2f`, `%05g` or `%e`.
+    #[must_use]
+    pub(crate) fn seq(args: &[String]) -> i32 {
+        let mut equal_width = false;
+        let mut separator = String::from("\n");
+        let mut format = None;
+        let mut numbers = Vec::new();
+        let mut args = args[1..].iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-w" | "--equal-width" => equal_width = true,
+                "-s" | "--separator" | "-f" | "--format" => {
+                    let Some(value) = args.next() else {
+                        errln!("seq: {arg} expects a value");
+                        return 2;
+                    };
+
+                    if matches!(arg.as_str(), "-s" | "--separator") {
+                        separator.clone_from(value);
+                    } else {
+                        let Some(spec) = FloatFormat::parse(value) else {
+                            errln!("seq: invalid format: {value}");
+                            return 2;
+                        };
+                        format = Some(spec);
+                    }
+                }
+                number => {
+                    let Some(value) = number.parse::<f64>().ok().filter(|value| value.is_finite())
+                    else {
+                        errln!("seq: invalid number: {number}");
+                        return 2;
+                    };
+                    numbers.push((value, number));
+                }
+            }
+        }
+
+        let ((first, first_text), (increment, increment_text), (last, _)) = match numbers[..] {
+            [last] => ((1.0, "1"), (1.0, "1"), last),
+            [first, last] => (first, (1.0, "1"), last),
+            [first, increment, last] => (first, increment, last),
+            _ => {
+                errln!("seq: expected [FIRST [INCREMENT]] LAST");
+                return 2;
+            }
+        };
+
+        if increment == 0.0 {
+            errln!("seq: invalid zero increment");
+            return 2;
+        }
+
+        // like GNU seq, print as many decimals as FIRST and INCREMENT have
+        let decimals = |text: &str| {
+            text.split_once('.')
+                .map_or(0, |(_, decimals)| decimals.len())
+        };
+        let format = format.unwrap_or(FloatFormat {
+            precision: Some(decimals(first_text).max(decimals(increment_text))),
+            conversion: 'f',
+            ..FloatFormat::default()
+        });
+
+        let width = if equal_width {
+            format.format(first).len().max(format.format(last).len())
+        } else {
+            0
+        };
+
+        // written as they come, so that `seq 1 1000000000 | head` doesn't wait for all of them
+        let mut output = std::io::BufWriter::new(redirect::stdout());
+        let mut index = 0.0;
+        loop {
+            let value = first + increment * index;
+
+            // a little slack so that 0.1 steps reach LAST despite rounding
+            let slack = increment.abs() * 1e-10;
+            if (increment > 0.0 && value > last + slack)
+                || (increment < 0.0 && value < last - slack)
+            {
+                break;
+            }
+
+            let text = format.format(value);
+            let text = match text.strip_prefix('-') {
+                Some(digits) => format!("-{digits:0>width$}", width = width.saturating_sub(1)),
+                None => format!("{text:0>width$}"),
+            };
+            let separator = if index == 0.0 { "" } else { separator.as_str() };
+
+            // nobody reads the rest, like after `| head`
+            if write!(output, "{separator}{text}").is_err() {
+                return 1;
+            }
+            index += 1.0;
+        }
+
+        if index > 0.0 && writeln!(output).and_then(|()| output.flush()).is_err() {
+            return 1;
+        }
+
+        0
+    }
+
+    /// Mimics `set` builtin Unix shell command, for the `-o` and `+o` forms, the `-e`, `-u` and
+    /// `-x` flags standing for `errexit`, `nounset` and `xtrace`, which may be grouped like
+    /// `-euo pipefail`, and `--` replacing the positional parameters with `ARGS`. [Linux man page](https://man7.org/linux/man-pages/man1/set.1p.html)
+    pub(crate) async fn set(args: &[String]) -> i32 {
+        if args.get(1).is_some_and(|arg| arg == "--") {
+            variables::set_positional(args[2..].to_vec());
+            return 0;
+        }
+
+        let mut lock = OPTIONS.lock().await;
+        let mut args = args[1..].iter();
+
+        while let Some(flag) = args.next() {
+            let (value, letters) = match (flag.strip_prefix('-'), flag.strip_prefix('+')) {
+                (Some(letters), _) if !letters.is_empty() => (true, letters),
+                (_, Some(letters)) if !letters.is_empty() => (false, letters),
+                _ => {
+                    errln!("set: bad option: {flag}");
+                    return 1;
+                }
+            };
+
+            for letter in letters.chars() {
+                let option = match letter {
+                    'e' => ShellOption::ErrExit,
+                    'u' => ShellOption::NoUnset,
+                    'x' => ShellOption::XTrace,
+                    'o' => {
+                        // `set -o` lists the options, `set +o` prints them as commands
+                        let Some(name) = args.next() else {
+                            for option in ShellOption::ALL {
+                                let is_set = lock.is_set(option);
+
+                                if value {
+                                    outln!("{option:<15} {}", if is_set { "on" } else { "off" });
+                                } else {
+                                    outln!("set {}o {option}", if is_set { '-' } else { '+' });
+                                }
+                            }
+                            return 0;
+                        };
+
+                        match name.parse() {
+                            Ok(option) => option,
+                            Err(name) => {
+                                errln!("set: no such option: {name}");
+                                return 1;
+                            }
+                        }
+                    }
+                    letter => {
+                        errln!("set: bad option: {}{letter}", &flag[..1]);
+                        return 1;
+                    }
+                };
+
+                lock.set(option, value);
+            }
+        }
+
+        0
+    }
+
+    /// Mimics `shift` builtin Unix shell command, removing the first `COUNT` positional
+    /// parameters, 1 by default. [Linux man page](https://man7.org/linux/man-pages/man1/shift.1p.html)
+    ///
+    /// Usage: `shift [COUNT]`
+    #[must_use]
+    pub(crate) fn shift(args: &[String]) -> i32 {
+        let count = match args.get(1).map(|count| count.parse::<usize>()) {
+            None => 1,
+            Some(Ok(count)) => count,
+            Some(Err(_)) => {
+                errln!("shift: {}: numeric argument required", args[1]);
+                return 2;
+            }
+        };
+
+        let mut positional = variables::positional();
+        if count > positional.len() {
+            errln!("shift: {count}: shift count out of range");
+            return 1;
+        }
+
+        positional.drain(..count);
+        variables::set_positional(positional);
+        0
+    }
+
+    /// Mimics the `sleep` Unix command, waiting for the sum of `DURATION`s, which are seconds
+    /// that may be fractional or end with `ms`, `s`, `m`, `h` or `d`. [Linux man page](https://man7.org/linux/man-pages/man1/sleep.1.html)
+    ///
+    /// Usage: `sleep DURATION...`
+    pub(crate) async fn sleep(args: &[String]) -> i32 {
+        if args.len() < 2 {
+            errln!("sleep: expected DURATION");
+            return 2;
+        }
+
+        let mut duration = Duration::ZERO;
+        for arg in &args[1..] {
+            let Some(arg) = parse_duration(arg) else {
+                errln!("sleep: invalid duration: {arg}");
+                return 2;
+            };
+            duration = duration.saturating_add(arg);
+        }
+
+        tokio::select! {
+            () = tokio::time::sleep(duration) => 0,
+            () = interrupted() => SIGINT_EXIT_CODE,
+        }
+    }
+
+    /// Saves `COMMAND` as the snippet `NAME`, to be inserted in the line editor with ALT+S. The
+    /// command may contain placeholders like `{{host}}`, selected one after the other with ALT+S.
+    /// With only a `NAME`, prints the snippet, and without arguments, lists them.
+    ///
+    /// Usage: `snip [-l]`, `snip NAME [COMMAND...]` and `snip -e NAME...`
+    #[must_use]
+    pub(crate) fn snip(args: &[String]) -> i32 {
+        let mut snippets = match snippet::load() {
+            Ok(snippets) => snippets,
+            Err(error) => {
+                errln!("snip: {error}");
+                return 1;
+            }
+        };
+
+        match args.get(1).map(String::as_str) {
+            None | Some("-l") => {
+                for snippet in &snippets {
+                    outln!("{}\t{}", snippet.name, snippet.command);
+                }
+                return 0;
+            }
+            Some("-e") => {
+                for name in &args[2..] {
+                    let len = snippets.len();
+                    snippets.retain(|snippet| snippet.name != *name);
+
+                    if len == snippets.len() {
+                        errln!("snip: {name}: not found");
+                        return 1;
+                    }
+                }
+            }
+            Some(name) if args.len() == 2 => {
+                let Some(snippet) = snippets.iter().find(|snippet| snippet.name == name) else {
+                    errln!("snip: {name}: not found");
+                    return 1;
+                };
+
+                outln!("{}", snippet.command);
+                return 0;
+            }
+            Some(name) => {
+                let command = args[2..].join(" ");
+
+                snippets.retain(|snippet| snippet.name != name);
+                snippets.push(snippet::Snippet {
+                    name: name.to_string(),
+                    command,
+                });
+            }
+        }
+
+        if let Err(error) = snippet::save(&snippets) {
+            errln!("snip: {error}");
+            return 1;
+        }
+
+        0
+    }
+
+    /// Mimics `status` fish builtin, for the `is-interactive`, `is-login`, `current-command` and
+    /// `job-count` queries. Without a query, it summarizes them. [fish manual](https://fishshell.com/docs/current/cmds/status.html)
+    ///
+    /// Usage: `status [QUERY]`
+    pub(crate) async fn status(args: &[String]) -> i32 {
+        let status = STATUS.lock().await;
+        // like fish, the jobs that are done don't count, and without job control none is stopped
+        let job_count = JOBS.lock().await.running();
+
+        match args.get(1).map(String::as_str) {
+            None => {
+                let not = |is| if is { "" } else { "not " };
+                outln!("This is {}a login shell", not(status.login));
+                outln!("This is {}an interactive shell", not(status.interactive));
+                0
+            }
+            Some("is-interactive" | "-i" | "--is-interactive") => i32::from(!status.interactive),
+            Some("is-login" | "-l" | "--is-login") => i32::from(!status.login),
+            Some("current-command") => {
+                outln!("{}", status.current_command);
+                0
+            }
+            Some("job-count") => {
+                outln!("{job_count}");
+                0
+            }
+            Some(query) => {
+                errln!("status: no such query: {query}");
+                2
+            }
+        }
+    }
+
+    /// Mimics `string` fish builtin, for the `length`, `sub`, `split`, `join`, `replace` and
+    /// `match` subcommands. `match` takes shell patterns, as regular expressions aren't
+    /// supported. [fish manual](https://fishshell.com/docs/current/cmds/string.html)
+    ///
+    /// Usage: `string SUBCOMMAND [FLAGS] [ARGUMENTS] [STRING...]`. Without `STRING`s, lines are
+    /// read from stdin unless it is a terminal.
+    pub(crate) fn string(args: &[String]) -> i32 {
+        let Some(subcommand) = args.get(1).map(String::as_str) else {
+            errln!("string: expected a subcommand");
+            return 2;
+        };
+
+        // the flags of every subcommand, the ones taking a value first
+        let (valued, boolean): (&[&str], &[&str]) = match subcommand {
+            "length" | "join" => (&[], &["-q", "--quiet"]),
+            "sub" => (
+                &["-s", "--start", "-l", "--length", "-e", "--end"],
+                &["-q", "--quiet"],
+            ),
+            "split" => (&["-m", "--max"], &["-q", "--quiet", "-r", "--right"]),
+            "replace" => (
+                &[],
+                &["-q", "--quiet", "-a", "--all", "-i", "--ignore-case"],
+            ),
+            "match" => (
+                &[],
+                &["-q", "--quiet", "-i", "--ignore-case", "-v", "--invert"],
+            ),
+            subcommand => {
+                errln!("string: no such subcommand: {subcommand}");
+                return 2;
+            }
+        };
+
+        let (flags, operands) = match parse_flags(&args[2..], valued, boolean) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                errln!("string {subcommand}: {error}");
+                return 2;
+            }
+        };
+
+        let parameters = match subcommand {
+            "split" | "join" | "match" => 1,
+            "replace" => 2,
+            _ => 0,
+        };
+        if operands.len() < parameters {
+            errln!("string {subcommand}: expected {parameters} argument(s) before the strings");
+            return 2;
+        }
+        let (parameters, strings) = operands.split_at(parameters);
+
+        let stdin;
+        let strings = if strings.is_empty() && !redirect::stdin_is_terminal() {
+            let Ok(fd) = redirect::stdin() else {
+                errln!("string: could not read the standard input");
+                return 1;
+            };
+            stdin = std::io::BufReader::new(File::from(fd))
+                .lines()
+                .map_while(Result::ok)
+                .collect::<Vec<_>>();
+            stdin.iter().map(String::as_str).collect()
+        } else {
+            strings.to_vec()
+        };
+
+        let quiet = flags.contains_key("--quiet");
+
+        let result = match subcommand {
+            "length" => Ok((
+                strings
+                    .iter()
+                    .map(|string| string.chars().count().to_string())
+                    .collect(),
+                strings.iter().any(|string| !string.is_empty()),
+            )),
+            "sub" => string_sub(&flags, &strings),
+            "split" => string_split(&flags, parameters[0], &strings),
+            "join" => Ok((vec![strings.join(parameters[0])], strings.len() > 1)),
+            "replace" => Ok(string_replace(
+                &flags,
+                parameters[0],
+                parameters[1],
+                &strings,
+            )),
+            _ => {
+                let ignore_case = flags.contains_key("--ignore-case");
+                let invert = flags.contains_key("--invert");
+
+                let output = strings
+                    .iter()
+                    .filter(|string| glob::matches(parameters[0], string, ignore_case) != invert)
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                let matched = !output.is_empty();
+                Ok((output, matched))
+            }
+        };
+
+        let (output, success) = match result {
+            Ok(result) => result,
+            Err(error) => {
+                errln!("string {subcommand}: {error}");
+                return 2;
+            }
+        };
+
+        if !quiet {
+            for line in output {
+                outln!("{line}");
+            }
+        }
+
+        i32::from(!success)
+    }
+
+    /// Mimics `test` builtin Unix shell command, also run as `[` with a `]` after the
+    /// expression, exiting with 0 if the expression is true, 1 if it is false and 2 if it is
+    /// invalid. [Linux man page](https://man7.org/linux/man-pages/man1/test.1p.html)
+    ///
+    /// Usage: `test [EXPRESSION]` or `[ [EXPRESSION] ]`, see [`condition`] for the expressions.
+    #[must_use]
+    pub(crate) fn test(args: &[String]) -> i32 {
+        let name = args[0].as_str();
+        let expression = if name == "[" {
+            match args[1..].split_last() {
+                Some((last, expression)) if last == "]" => expression,
+                _ => {
+                    errln!("[: missing ']'");
+                    return 2;
+                }
+            }
+        } else {
+            &args[1..]
+        };
+
+        let expression = expression
+            .iter()
+            .map(|arg| condition::Arg::new(arg))
+            .collect::<Vec<_>>();
+
+        match condition::eval(&expression, false) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(error) => {
+                errln!("{name}: {error}");
+                2
+            }
+        }
+    }
+
+    /// Mimics `trap` builtin Unix shell command, running `COMMAND` when the shell exits for the
+    /// `EXIT` (or `0`) condition, the only one supported. `-` removes the trap, and without a
+    /// command the traps are printed as `trap` commands.
+    /// [Linux man page](https://man7.org/linux/man-pages/man1/trap.1p.html)
+    ///
+    /// Usage: `trap [-p]`, `trap COMMAND CONDITION...` or `trap - CONDITION...`
+    #[must_use]
+    pub(crate) fn trap(args: &[String]) -> i32 {
+        let args = match args.get(1).map(String::as_str) {
+            Some("--") => &args[2..],
+            _ => &args[1..],
+        };
+
+        let (command, conditions) = match args {
+            [] => return Self::print_traps(),
+            [flag] if flag == "-p" => return Self::print_traps(),
+            [_] => {
+                errln!("trap: expected a CONDITION");
+                return 2;
+            }
+            [command, conditions @ ..] => (command, conditions),
+        };
+
+        let mut code = 0;
+        for condition in conditions {
+            if !trap::CONDITIONS.contains(&condition.as_str()) {
+                errln!("trap: {condition}: unsupported condition");
+                code = 1;
+                continue;
+            }
+
+            trap::set_exit((command != "-").then(|| command.clone()));
+        }
+
+        code
+    }
+
+    /// Prints the traps as `trap` commands that set them again.
+    fn print_traps() -> i32 {
+        if let Some(command) = trap::get_exit() {
+            outln!("trap -- {} EXIT", quote(&command));
+        }
+        0
+    }
+
+    /// Mimics `unhash -d` zsh builtin command, removing the named directories `NAME`.
+    /// [Zsh manual](https://zsh.sourceforge.io/Doc/Release/Shell-Builtin-Commands.html)
+    ///
+    /// Usage: `unhash -d NAME...`
+    #[must_use]
+    pub(crate) fn unhash(args: &[String]) -> i32 {
+        if args.get(1).map(String::as_str) != Some("-d") {
+            errln!("unhash: only named directories are supported, with -d");
+            return 2;
+        }
+
+        let mut code = 0;
+        for name in &args[2..] {
+            if !named_dirs::remove(name) {
+                errln!("unhash: no such named directory: {name}");
+                code = 1;
+            }
+        }
+
+        code
+    }
+
+    /// Mimics `unset` builtin Unix shell command, removing the variables `NAME`, whether they
+    /// are shell variables or in the environment.
+    /// [Linux man page](https://man7.org/linux/man-pages/man1/unset.1p.html)
+    ///
+    /// Usage: `unset NAME...`
+    #[must_use]
+    pub(crate) fn unset(args: &[String]) -> i32 {
+        let mut code = 0;
+
+        for name in &args[1..] {
+            if variables::is_name(name) {
+                variables::unset(name);
+            } else {
+                errln!("unset: {name}: not a valid identifier");
+                code = 1;
+            }
+        }
+
+        code
+    }
+
+    /// Mimics `wait` builtin Unix shell command, waiting for the given jobs or all of them.
+    /// [Linux man page](https://man7.org/linux/man-pages/man1/wait.1p.html)
+    ///
+    /// Usage: `wait [%ID|PID...]`
+    pub(crate) async fn wait(args: &[String]) -> i32 {
+        job::wait(&args[1..]).await
+    }
+
+    /// Mimics the `watch` Unix command, running `COMMAND` every `SECONDS` (2 by default) on a
+    /// cleared screen until CTRL+C is pressed. [Linux man page](https://man7.org/linux/man-pages/man1/watch.1.html)
+    ///
+    /// Usage: `watch [-n SECONDS] COMMAND...`
+    #[async_recursion]
+    pub(crate) async fn watch(args: &[String]) -> i32 {
+        let (interval, words) = match args.get(1).map(String::as_str) {
+            Some("-n" | "--interval") => {
+                let Some(interval) = args.get(2).and_then(|interval| parse_duration(interval))
+                else {
+                    errln!("watch: expected -n SECONDS");
+                    return 2;
+                };
+                (interval, &args[3..])
+            }
+            _ => (Duration::from_secs(2), &args[1..]),
+        };
+
+        let Some((keyword, command_args)) = words.split_first() else {
+            errln!("watch: expected COMMAND");
+            return 2;
+        };
+        let command = Command::new(keyword.clone(), command_args.to_vec());
+
+        let run = async {
+            loop {
+                out!("{}{}", termion::clear::All, termion::cursor::Goto(1, 1));
+                outln!("Every {interval:?}: {}\n", words.join(" "));
+
+                command.interpret().await;
+                tokio::time::sleep(interval).await;
+            }
+        };
+
+        tokio::select! {
+            () = run => unreachable!("watch only stops on CTRL+C"),
+            () = interrupted() => 0,
+        }
+    }
+
+    /// Returns the usage of the builtin, one line per form.
+    const fn usage(&self) -> &'static str {
+        match self {
+            Self::Alias => "alias [-p] [NAME[=VALUE]...]",
+            Self::Argparse => "argparse [-n NAME] SPEC... -- ARGS...",
+            Self::Basename => "basename NAME [SUFFIX]\nbasename [-a] [-s SUFFIX] NAME...",
+            Self::Builtin => "builtin [BUILTIN [ARGS...]]",
+            Self::Cd => "cd [--] [PATH]\ncd -",
+            Self::Chpwd => "chpwd [COMMAND]\nchpwd -r [COMMAND]",
+            Self::Complete => {
+                "complete -W WORDLIST NAME...\ncomplete -C PROGRAM NAME...\ncomplete [-p]\n\
+                 complete -r NAME..."
+            }
+            Self::Dirname => "dirname NAME...",
+            Self::Dirs => "dirs [-c|-l|-v]",
+            Self::Echo => "echo [ARGS...]",
+            Self::Envdiff => "envdiff snap\nenvdiff",
+            Self::Exec => "exec [COMMAND [ARGS...]]",
+            Self::Exit => "exit [CODE]",
+            Self::Export => "export [-p] [NAME[=VALUE]...]",
+            Self::Glob => "glob [-0] [--] PATTERN...",
+            Self::Groot => "groot [PATH]",
+            Self::Guard => "guard [-p]\nguard [--hook PROGRAM] PATTERN...\nguard -e PATTERN...",
+            Self::Hash => "hash -d [NAME=PATH...]",
+            Self::Help => "help [BUILTIN...]",
+            Self::History => "history",
+            Self::Ionice => "ionice [-c CLASS] [-n LEVEL] [--] COMMAND [ARGS...]",
+            Self::Jobs => "jobs [-l]",
+            Self::Math => "math [-s SCALE] EXPRESSION...",
+            Self::Nice => "nice [-n ADJUSTMENT] [--] COMMAND [ARGS...]",
+            Self::Popd => "popd",
+            Self::Pushd => "pushd [--] [PATH]",
+            Self::Pwd => "pwd",
+            Self::Readlink => "readlink [-f|-e|-m] [-n] [--] PATH...",
+            Self::Realpath => "realpath [-e|-m] [-s] [--] PATH...",
+            Self::Record => "record start [-o] FILE\nrecord stop\nrecord",
+            Self::Repeat => "repeat COUNT COMMAND...",
+            Self::Report => "report [WINDOW]",
+            Self::Seq => "seq [-w] [-s SEPARATOR] [-f FORMAT] [FIRST [INCREMENT]] LAST",
+            Self::Set => "set [-eux] [-o [OPTION]]\nset [+eux] [+o [OPTION]]\nset -- [ARGS...]",
+            Self::Shift => "shift [COUNT]",
+            Self::Sleep => "sleep DURATION...",
+            Self::Snip => "snip [-l]\nsnip NAME [COMMAND...]\nsnip -e NAME...",
+            Self::Status => "status [QUERY]",
+            Self::String => "string SUBCOMMAND [FLAGS] [ARGUMENTS] [STRING...]",
+            Self::Test => "test [EXPRESSION]\n[ [EXPRESSION] ]",
+            Self::Trap => "trap [-p]\ntrap COMMAND CONDITION...\ntrap - CONDITION...",
+            Self::Unhash => "unhash -d NAME...",
+            Self::Unset => "unset NAME...",
+            Self::Wait => "wait [%ID|PID...]",
+            Self::Watch => "watch [-n SECONDS] COMMAND...",
+        }
+    }
+
+    /// Runs a builtin if it is one.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the command is not a builtin [`std::io::ErrorKind::InvalidInput`].
+    pub(crate) async fn run(args: &[String]) -> Result<i32, Error> {
+        if args.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                String::from("expected 1 argument"),
+            ));
+        }
+
+        match Self::from_str(args[0].as_str()) {
+            Ok(Self::Alias) => Ok(Self::alias(args).await),
+            Ok(Self::Argparse) => Ok(Self::argparse(args)),
+            Ok(Self::Basename) => Ok(Self::basename(args)),
+            Ok(Self::Builtin) => Ok(Self::builtin(args).await),
+            Ok(Self::Cd) => Ok(Self::cd(args).await),
+            Ok(Self::Chpwd) => Ok(Self::chpwd(args)),
+            Ok(Self::Complete) => Ok(Self::complete(args).await),
+            Ok(Self::Dirname) => Ok(Self::dirname(args)),
+            Ok(Self::Dirs) => Ok(Self::dirs(args)),
+            Ok(Self::Echo) => Ok(Self::echo(args)),
+            Ok(Self::Envdiff) => Ok(Self::envdiff(args)),
+            Ok(Self::Exec) => Ok(Self::exec(args)),
+            Ok(Self::Exit) => Ok(Self::exit(args).await),
+            Ok(Self::Export) => Ok(Self::export(args)),
+            Ok(Self::Glob) => Ok(Self::glob(args)),
+            Ok(Self::Groot) => Ok(Self::groot(args).await),
+            Ok(Self::Guard) => Ok(Self::guard(args).await),
+            Ok(Self::Hash) => Ok(Self::hash(args)),
+            Ok(Self::Help) => Ok(Self::help(args)),
+            Ok(Self::History) => Ok(Self::history(args).await),
+            Ok(Self::Ionice) => Ok(Self::ionice(args).await),
+            Ok(Self::Jobs) => Ok(Self::jobs(args).await),
+            Ok(Self::Math) => Ok(Self::math(args)),
+            Ok(Self::Nice) => Ok(Self::nice(args).await),
+            Ok(Self::Popd) => Ok(Self::popd(args).await),
+            Ok(Self::Pushd) => Ok(Self::pushd(args).await),
+            Ok(Self::Pwd) => Ok(Self::pwd(args)),
+            Ok(Self::Readlink) => Ok(Self::readlink(args)),
+            Ok(Self::Realpath) => Ok(Self::realpath(args)),
+            Ok(Self::Record) => Ok(Self::record(args)),
+            Ok(Self::Repeat) => Ok(Self::repeat(args).await),
+            Ok(Self::Report) => Ok(Self::report(args).await),
+            Ok(Self::Seq) => Ok(Self::seq(args)),
+            Ok(Self::Set) => Ok(Self::set(args).await),
+            Ok(Self::Shift) => Ok(Self::shift(args)),
+            Ok(Self::Sleep) => Ok(Self::sleep(args).await),
+            Ok(Self::Snip) => Ok(Self::snip(args)),
+            Ok(Self::Status) => Ok(Self::status(args).await),
+            Ok(Self::String) => Ok(Self::string(args)),
+            Ok(Self::Test) => Ok(Self::test(args)),
+            Ok(Self::Trap) => Ok(Self::trap(args)),
+            Ok(Self::Unhash) => Ok(Self::unhash(args)),
+            Ok(Self::Unset) => Ok(Self::unset(args)),
+            Ok(Self::Wait) => Ok(Self::wait(args).await),
+            Ok(Self::Watch) => Ok(Self::watch(args).await),
+            Err(command) => Err(Error::new(ErrorKind::InvalidBuiltin, command)),
+        }
+    }
+}
+
+/// Returns once CTRL+C is pressed, if the shell is interactive and this is the shell itself.
+/// Otherwise it never returns, and SIGINT is left alone: a script or a subshell in the
+/// foreground dies of it, and a job ignores it.
+async fn interrupted() {
+    if STATUS.lock().await.interactive && !subshell::is_forked() {
+        let _ = tokio::signal::ctrl_c().await;
+    } else {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Parses a duration like `1.5`, `500ms`, `2m`, `12h` or `7d`, in seconds without a suffix.
+fn parse_duration(duration: &str) -> Option<Duration> {
+    let (number, unit) = duration.split_at(
+        duration
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(duration.len()),
+    );
+
+    let seconds = match unit {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        _ => return None,
+    };
+
+    Duration::try_from_secs_f64(number.parse::<f64>().ok()? * seconds).ok()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn percent(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+/// A `printf` floating point conversion, as used by `seq -f`.
+#[derive(Clone, Copy, Default)]
+struct FloatFormat<'a> {
+    prefix: &'a str,
+    suffix: &'a str,
+    zero_pad: bool,
+    left_align: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+impl<'a> FloatFormat<'a> {
+    /// Parses a format containing exactly one `%f`, `%g` or `%e` conversion, with optional `-`
+    /// and `0` flags, width and precision.
+    fn parse(format: &'a str) -> Option<Self> {
+        let start = format.find('%')?;
+        let (prefix, rest) = format.split_at(start);
+        let rest = &rest[1..];
+
+        let end = rest.find(['f', 'g', 'e', 'F', 'G', 'E'])?;
+        let (spec, suffix) = (&rest[..end], &rest[end + 1..]);
+
+        if suffix.replace("%%", "").contains('%') {
+            return None;
+        }
+
+        let flags = spec.len() - spec.trim_start_matches(['-', '0']).len();
+        let (width, precision) = match spec[flags..].split_once('.') {
+            Some((width, precision)) => (width, Some(precision.parse().ok()?)),
+            None => (&spec[flags..], None),
+        };
+
+        Some(Self {
+            prefix,
+            suffix,
+            zero_pad: spec[..flags].contains('0'),
+            left_align: spec[..flags].contains('-'),
+            width: if width.is_empty() {
+                0
+            } else {
+                width.parse().ok()?
+            },
+            precision,
+            conversion: rest[end..].chars().next()?.to_ascii_lowercase(),
+        })
+    }
+
+    fn format(&self, value: f64) -> String {
+        let precision = self.precision.unwrap_or(6);
+        let number = match self.conversion {
+            'e' => {
+                // C writes the exponent with a sign and at least two digits
+                let number = format!("{value:.precision$e}");
+                let (mantissa, exponent) = number.split_once('e').unwrap_or((&number, "0"));
+                let (sign, digits) = match exponent.strip_prefix('-') {
+                    Some(digits) => ('-', digits),
+                    None => ('+', exponent),
+                };
+                format!("{mantissa}e{sign}{digits:0>2}")
+            }
+            'f' => format!("{value:.precision$}"),
+            // `%g` keeps `precision` significant digits, without trailing zeros
+            _ => {
+                #[allow(clippy::cast_possible_truncation)]
+                let magnitude = if value == 0.0 {
+                    0
+                } else {
+                    value.abs().log10().floor() as i32
+                };
+                let decimals = usize::try_from(
+                    i32::try_from(precision.max(1)).unwrap_or(i32::MAX) - 1 - magnitude,
+                )
+                .unwrap_or(0);
+                let number = format!("{value:.decimals$}");
+
+                if number.contains('.') {
+                    number
+                        .trim_end_matches('0')
+                        .trim_end_matches('.')
+                        .to_string()
+                } else {
+                    number
+                }
+            }
+        };
+
+        let width = self.width;
+        let number = if self.left_align {
+            format!("{number:<width$}")
+        } else if self.zero_pad {
+            match number.strip_prefix('-') {
+                Some(digits) => format!("-{digits:0>width$}", width = width.saturating_sub(1)),
+                None => format!("{number:0>width$}"),
+            }
+        } else {
+            format!("{number:>width$}")
+        };
+
+        format!(
+            "{}{number}{}",
+            self.prefix.replace("%%", "%"),
+            self.suffix.replace("%%", "%")
+        )
+    }
+}
+
+/// An option specification of `argparse`, like `n/name=`.
+struct ArgSpec {
+    short: Option<char>,
+    long: Option<String>,
+    value: ArgValue,
+}
+
+#[derive(PartialEq, Eq)]
+enum ArgValue {
+    None,
+    Required,
+    Optional,
+    Multiple,
+}
+
+impl ArgSpec {
+    fn parse(spec: &str) -> Option<Self> {
+        let (names, value) = match spec.split_once('=') {
+            None => (spec, ArgValue::None),
+            Some((names, "")) => (names, ArgValue::Required),
+            Some((names, "?")) => (names, ArgValue::Optional),
+            Some((names, "+")) => (names, ArgValue::Multiple),
+            Some(_) => return None,
+        };
+
+        let (short, long) = match names.split_once('/') {
+            Some((short, long)) => (Some(short), Some(long)),
+            None if names.chars().count() == 1 => (Some(names), None),
+            None => (None, Some(names)),
+        };
+
+        let short = match short.map(|short| short.chars().collect::<Vec<_>>()) {
+            Some(chars) => match chars[..] {
+                [c] if c.is_alphanumeric() => Some(c),
+                _ => return None,
+            },
+            None => None,
+        };
+
+        if long.is_some_and(|long| long.is_empty() || long.contains(char::is_whitespace)) {
+            return None;
+        }
+
+        Some(Self {
+            short,
+            long: long.map(String::from),
+            value,
+        })
+    }
+
+    fn usage(&self) -> String {
+        let names = [
+            self.short.map(|short| format!("-{short}")),
+            self.long.as_ref().map(|long| format!("--{long}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("|");
+
+        match self.value {
+            ArgValue::None => format!("[{names}]"),
+            ArgValue::Required | ArgValue::Multiple => format!("[{names} VALUE]"),
+            ArgValue::Optional => format!("[{names}[=VALUE]]"),
+        }
+    }
+
+    fn variables(&self) -> impl Iterator<Item = String> + '_ {
+        self.short
+            .map(|short| format!("_flag_{short}"))
+            .into_iter()
+            .chain(
+                self.long
+                    .iter()
+                    .map(|long| format!("_flag_{}", long.replace('-', "_"))),
+            )
+    }
+}
+
+/// Matches `args` against `specs`, returning the values of every spec, in order, and the
+/// positional arguments.
+///
+/// Flags without values get the flag itself as their value, like fish does.
+#[allow(clippy::type_complexity)]
+fn parse_args(
+    specs: &[ArgSpec],
+    args: &[String],
+) -> Result<(Vec<Option<Vec<String>>>, Vec<String>), String> {
+    let mut values = specs
+        .iter()
+        .map(|_| None)
+        .collect::<Vec<Option<Vec<String>>>>();
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        // each item is an option, its spelling and the value attached to it
+        let options = if arg == "--" {
+            positional.extend(args.by_ref().cloned());
+            break;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (long, attached) = match long.split_once('=') {
+                Some((long, value)) => (long, Some(value.to_string())),
+                None => (long, None),
+            };
+            let index = specs
+                .iter()
+                .position(|spec| spec.long.as_deref() == Some(long))
+                .ok_or_else(|| format!("unknown option: --{long}"))?;
+
+            vec![(index, format!("--{long}"), attached)]
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            let mut options = Vec::new();
+            for (offset, c) in arg[1..].char_indices() {
+                let index = specs
+                    .iter()
+                    .position(|spec| spec.short == Some(c))
+                    .ok_or_else(|| format!("unknown option: -{c}"))?;
+
+                // the rest of a group is the value of an option taking one, like `-n3`
+                let rest = &arg[1 + offset + c.len_utf8()..];
+                if specs[index].value != ArgValue::None && !rest.is_empty() {
+                    options.push((index, format!("-{c}"), Some(rest.to_string())));
+                    break;
+                }
+                options.push((index, format!("-{c}"), None));
+            }
+
+            options
+        } else {
+            positional.push(arg.clone());
+            continue;
+        };
+
+        for (index, flag, attached) in options {
+            let value = match (&specs[index].value, attached) {
+                (ArgValue::None, Some(_)) => return Err(format!("{flag} doesn't take a value")),
+                (ArgValue::None, None) => flag,
+                (ArgValue::Optional, attached) => attached.unwrap_or_default(),
+                (_, Some(attached)) => attached,
+                (_, None) => args
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("{flag} expects a value"))?,
+            };
+
+            let values = values[index].get_or_insert_with(Vec::new);
+            if specs[index].value != ArgValue::Multiple && specs[index].value != ArgValue::None {
+                values.clear();
+            }
+            values.push(value);
+        }
+    }
+
+    Ok((values, positional))
+}
+
+type Flags<'a> = HashMap<&'a str, &'a str>;
+
+/// Parses the flags at the start of `args`, by their long form, and the operands after them.
+///
+/// The flags in `valued` take a value, the ones in `boolean` don't, and every short flag is
+/// followed by its long form.
+fn parse_flags<'a>(
+    args: &'a [String],
+    valued: &[&'a str],
+    boolean: &[&'a str],
+) -> Result<(Flags<'a>, Vec<&'a str>), String> {
+    let mut flags = HashMap::new();
+    let mut operands = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            operands.extend(args.map(String::as_str));
+            break;
+        }
+
+        if !operands.is_empty() || !arg.starts_with('-') || arg.len() == 1 {
+            operands.push(arg.as_str());
+        } else if valued.contains(&arg.as_str()) {
+            let Some(value) = args.next() else {
+                return Err(format!("{arg} expects a value"));
+            };
+            flags.insert(long_flag(arg, valued), value.as_str());
+        } else if boolean.contains(&arg.as_str()) {
+            flags.insert(long_flag(arg, boolean), "");
+        } else {
+            return Err(format!("bad flag: {arg}"));
+        }
+    }
+
+    Ok((flags, operands))
+}
+
+/// Runs `string sub`, returning the substrings and whether it succeeded.
+fn string_sub(flags: &Flags, strings: &[&str]) -> Result<(Vec<String>, bool), String> {
+    let number = |flag| {
+        flags
+            .get(flag)
+            .map(|value: &&str| value.parse::<i64>().map_err(|_| *value))
+            .transpose()
+    };
+    let (start, length, end) = match (number("--start"), number("--length"), number("--end")) {
+        (Ok(start), Ok(length), Ok(end))
+            if start != Some(0) && length.is_none_or(|length| length >= 0) =>
+        {
+            (start.unwrap_or(1), length, end)
+        }
+        (Err(value), ..) | (_, Err(value), _) | (.., Err(value)) => {
+            return Err(format!("invalid number: {value}"));
+        }
+        _ => return Err(String::from("START can't be 0, nor LENGTH negative")),
+    };
+
+    let mut output = Vec::new();
+    for string in strings {
+        let chars = string.chars().collect::<Vec<_>>();
+        let position = |index: i64| {
+            let index = if index < 0 {
+                i64::try_from(chars.len()).unwrap_or(i64::MAX) + index
+            } else {
+                index
+            };
+            usize::try_from(index.max(0)).unwrap_or(0).min(chars.len())
+        };
+
+        let from = position(if start > 0 { start - 1 } else { start });
+        let to = match (length, end) {
+            (Some(length), _) => from.saturating_add(usize::try_from(length).unwrap_or(0)),
+            (None, Some(end)) => position(end),
+            (None, None) => chars.len(),
+        }
+        .clamp(from, chars.len());
+
+        output.push(chars[from..to].iter().collect());
+    }
+
+    Ok((output, true))
+}
+
+/// Runs `string split`, returning the pieces and whether anything was split.
+fn string_split(
+    flags: &Flags,
+    separator: &str,
+    strings: &[&str],
+) -> Result<(Vec<String>, bool), String> {
+    let max = match flags.get("--max").map(|max| max.parse::<usize>()) {
+        None => usize::MAX,
+        Some(Ok(max)) => max,
+        Some(Err(_)) => return Err(format!("invalid number: {}", flags["--max"])),
+    };
+
+    let mut output = Vec::new();
+    let mut split = false;
+
+    for string in strings {
+        let pieces = split_string(string, separator, max, flags.contains_key("--right"));
+        split |= pieces.len() > 1;
+        output.extend(pieces);
+    }
+
+    Ok((output, split))
+}
+
+/// Runs `string replace`, returning the strings and whether anything was replaced.
+fn string_replace(
+    flags: &Flags,
+    pattern: &str,
+    replacement: &str,
+    strings: &[&str],
+) -> (Vec<String>, bool) {
+    let ignore_case = flags.contains_key("--ignore-case");
+    let mut output = Vec::new();
+    let mut replaced = false;
+
+    for string in strings {
+        let mut result = String::new();
+        let mut from = 0;
+
+        while let Some((start, end)) = find(string, pattern, from, ignore_case) {
+            result.push_str(&string[from..start]);
+            result.push_str(replacement);
+            from = end;
+            replaced = true;
+
+            if !flags.contains_key("--all") || pattern.is_empty() {
+                break;
+            }
+        }
+
+        result.push_str(&string[from..]);
+        output.push(result);
+    }
+
+    (output, replaced)
+}
+
+/// Returns the long form of `flag`, which is paired with it in `flags`.
+fn long_flag<'a>(flag: &'a str, flags: &[&'a str]) -> &'a str {
+    if flag.starts_with("--") {
+        return flag;
+    }
+
+    flags
+        .iter()
+        .position(|candidate| *candidate == flag)
+        .and_then(|index| flags.get(index + 1))
+        .copied()
+        .unwrap_or(flag)
+}
+
+/// Splits `string` on `separator` at most `max` times, starting from the right if `right`.
+/// An empty separator splits between characters.
+fn split_string(string: &str, separator: &str, max: usize, right: bool) -> Vec<String> {
+    if separator.is_empty() {
+        let chars = string.chars().map(String::from).collect::<Vec<_>>();
+        if max >= chars.len() {
+            return chars;
+        }
+
+        return if right {
+            let (rest, split) = chars.split_at(chars.len() - max);
+            std::iter::once(rest.concat())
+                .chain(split.iter().cloned())
+                .collect()
+        } else {
+            let (split, rest) = chars.split_at(max);
+            split
+                .iter()
+                .cloned()
+                .chain(std::iter::once(rest.concat()))
+                .collect()
+        };
+    }
+
+    if right {
+        let mut pieces = string
+            .rsplitn(max.saturating_add(1), separator)
+            .map(String::from)
+            .collect::<Vec<_>>();
+        pieces.reverse();
+        pieces
+    } else {
+        string
+            .splitn(max.saturating_add(1), separator)
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// Prints the working directory and the directory stack for `dirs`, with `~` for the home
+/// directory unless `long` is set, on one line or numbered one per line if `verbose` is set.
+fn print_dirs(long: bool, verbose: bool) {
+    let dirs = std::iter::once(user::working_dir())
+        .chain(dir_stack::all())
+        .map(|dir| {
+            if long {
+                dir.display().to_string()
+            } else {
+                user::display_path(&dir)
+            }
+        });
+
+    if verbose {
+        for (index, dir) in dirs.enumerate() {
+            outln!("{index:2}  {dir}");
+        }
+    } else {
+        outln!("{}", dirs.collect::<Vec<_>>().join(" "));
+    }
+}
+
+/// Removes the `.` and `..` components of `path`, without looking at the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Returns the absolute path of `path` with its symbolic links, `.` and `..` resolved, letting up
+/// to `missing` of its last components not exist.
+fn canonicalize(path: &Path, missing: usize) -> std::io::Result<PathBuf> {
+    let path = user::working_dir().join(path);
+    let error = match std::fs::canonicalize(&path) {
+        Ok(canonical) => return Ok(canonical),
+        Err(error) => error,
+    };
+
+    // the components after the longest existing directory are resolved without it
+    let components = path.components().collect::<Vec<_>>();
+    for existing in (components.len().saturating_sub(missing)..components.len()).rev() {
+        let prefix = components[..existing].iter().collect::<PathBuf>();
+        if let Ok(prefix) = std::fs::canonicalize(prefix) {
+            let rest = components[existing..].iter().collect::<PathBuf>();
+            return Ok(normalize(&prefix.join(rest)));
+        }
+    }
+
+    Err(error)
+}
+
+/// Returns how many of the last components of a path the canonicalization flags of `realpath` and
+/// `readlink` let be missing: none with `-e`, any with `-m` and the last one otherwise.
+fn missing_components(flags: &Flags) -> usize {
+    if flags.contains_key("--canonicalize-existing") {
+        0
+    } else if flags.contains_key("--canonicalize-missing") {
+        usize::MAX
+    } else {
+        1
+    }
+}
+
+/// Finds the first occurrence of `pattern` in `string` at or after the byte index `from`.
+///
+/// Returns the byte range of the occurrence.
+fn find(string: &str, pattern: &str, from: usize, ignore_case: bool) -> Option<(usize, usize)> {
+    if !ignore_case {
+        return string[from..]
+            .find(pattern)
+            .map(|start| (from + start, from + start + pattern.len()));
+    }
+
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+
+    string[from..].char_indices().find_map(|(start, _)| {
+        let start = from + start;
+        let mut end = start;
+        let mut chars = string[start..].chars();
+
+        for p in pattern.chars() {
+            let c = chars.next()?;
+            if fold(c) != fold(p) {
+                return None;
+            }
+            end += c.len_utf8();
+        }
+
+        Some((start, end))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    /// Runs the builtin `args` with its standard output and error captured, returning its exit
+    /// code and what it wrote to each.
+    async fn run(args: &[&str]) -> (i32, String, String) {
+        let (mut stdout, stdout_writer) = std::io::pipe().unwrap();
+        let (mut stderr, stderr_writer) = std::io::pipe().unwrap();
+
+        let plan = Plan::piped(
+            vec![(1, stdout_writer.into()), (2, stderr_writer.into())],
+            &[],
+        )
+        .unwrap();
+        let code = plan
+            .scope(Builtin::run(&self::args(args)))
+            .await
+            .unwrap_or_else(|error| panic!("{error}"));
+
+        let mut out = String::new();
+        let mut err = String::new();
+        stdout.read_to_string(&mut out).unwrap();
+        stderr.read_to_string(&mut err).unwrap();
+
+        (code, out, err)
+    }
+
+    #[test]
+    fn float_formats() {
+        let format = |format, value| FloatFormat::parse(format).map(|spec| spec.format(value));
+
+        assert_eq!(format("%.2f", 1.5).as_deref(), Some("1.50"));
+        assert_eq!(format("%05.1f", -2.0).as_deref(), Some("-02.0"));
+        assert_eq!(format("%-4g|", 1.0).as_deref(), Some("1   |"));
+        assert_eq!(format("%g", 0.000_1).as_deref(), Some("0.0001"));
+        assert_eq!(format("%e", 1234.5).as_deref(), Some("1.234500e+03"));
+        assert_eq!(format("%.0f%%", 50.0).as_deref(), Some("50%"));
+        assert!(FloatFormat::parse("%d").is_none());
+        assert!(FloatFormat::parse("%f %f").is_none());
+    }
+
+    #[test]
+    fn seq_rejects_invalid_arguments() {
+        assert_eq!(Builtin::seq(&args(&["seq", "1", "0", "3"])), 2);
+        assert_eq!(Builtin::seq(&args(&["seq", "a"])), 2);
+        assert_eq!(Builtin::seq(&args(&["seq", "-f", "%d", "3"])), 2);
+        assert_eq!(Builtin::seq(&args(&["seq"])), 2);
+        assert_eq!(Builtin::seq(&args(&["seq", "1", "2", "3", "4"])), 2);
+    }
+
+    #[test]
+    fn string_flags() {
+        let args = args(&["-s", "2", "-q", "abc", "-l"]);
+        let (flags, operands) = parse_flags(&args, &["-s", "--start"], &["-q", "--quiet"]).unwrap();
+
+        assert_eq!(flags.get("--start"), Some(&"2"));
+        assert!(flags.contains_key("--quiet"));
+        assert_eq!(operands, ["abc", "-l"]);
+        assert!(parse_flags(&args, &[], &["-q", "--quiet"]).is_err());
+    }
+
+    #[test]
+    fn string_sub_counts_characters() {
+        let flags = Flags::from([("--start", "2"), ("--length", "3")]);
+        assert_eq!(
+            string_sub(&flags, &["abcdef", "é1"]),
+            Ok((vec![String::from("bcd"), String::from("1")], true))
+        );
+
+        let flags = Flags::from([("--start", "-2")]);
+        assert_eq!(
+            string_sub(&flags, &["abcdef"]),
+            Ok((vec![String::from("ef")], true))
+        );
+        assert!(string_sub(&Flags::from([("--start", "0")]), &["a"]).is_err());
+    }
+
+    #[test]
+    fn string_split_and_replace() {
+        assert_eq!(
+            split_string("a,b,c", ",", usize::MAX, false),
+            ["a", "b", "c"]
+        );
+        assert_eq!(split_string("a,b,c", ",", 1, true), ["a,b", "c"]);
+        assert_eq!(split_string("abc", "", 1, false), ["a", "bc"]);
+
+        let flags = Flags::from([("--all", ""), ("--ignore-case", "")]);
+        assert_eq!(
+            string_replace(&flags, "o", "0", &["fOo", "bar"]),
+            (vec![String::from("f00"), String::from("bar")], true)
+        );
+        assert_eq!(find("aXbx", "x", 2, true), Some((3, 4)));
+    }
+
+    #[test]
+    fn string_rejects_bad_usage() {
+        assert_eq!(Builtin::string(&args(&["string"])), 2);
+        assert_eq!(Builtin::string(&args(&["string", "upper", "a"])), 2);
+        assert_eq!(Builtin::string(&args(&["string", "sub", "--bad", "a"])), 2);
+        assert_eq!(Builtin::string(&args(&["string", "replace", "a"])), 2);
+    }
+
+    #[test]
+    fn math_rejects_invalid_expressions() {
+        assert_eq!(Builtin::math(&args(&["math"])), 2);
+        assert_eq!(Builtin::math(&args(&["math", "-s", "x", "1"])), 2);
+        assert_eq!(Builtin::math(&args(&["math", "1", "/", "0"])), 1);
+        assert_eq!(Builtin::math(&args(&["math", "2", "^", "2000"])), 1);
+    }
+
+    #[tokio::test]
+    async fn builtin_runs_nested_builtins() {
+        assert_eq!(Builtin::builtin(&args(&["builtin"])).await, 0);
+        assert_eq!(
+            Builtin::builtin(&args(&["builtin", "builtin", "echo"])).await,
+            0
+        );
+        assert_eq!(
+            Builtin::builtin(&args(&["builtin", "builtin", "seq", "x"])).await,
+            2
+        );
+        assert_eq!(Builtin::builtin(&args(&["builtin", "nosuch"])).await, 1);
+    }
+
+    #[tokio::test]
+    async fn builtins_write_to_their_descriptors() {
+        assert_eq!(
+            run(&["builtin", "builtin", "echo", "hi"]).await,
+            (0, String::from("hi\n"), String::new())
+        );
+        assert_eq!(
+            run(&["builtin", "nosuch"]).await,
+            (
+                1,
+                String::new(),
+                String::from("builtin: nosuch: not a shell builtin\n")
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn seq_string_and_math_output() {
+        assert_eq!(run(&["seq", "1", "2", "5"]).await.1, "1\n3\n5\n");
+        assert_eq!(
+            run(&["seq", "-s", ",", "-w", "8", "10"]).await.1,
+            "08,09,10\n"
+        );
+        assert_eq!(run(&["string", "split", ",", "a,b"]).await.1, "a\nb\n");
+        assert_eq!(
+            run(&["string", "match", "*.rs", "main.rs", "lib.c"]).await,
+            (0, String::from("main.rs\n"), String::new())
+        );
+        assert_eq!(run(&["math", "-s", "2", "10", "/", "3"]).await.1, "3.33\n");
+        assert_eq!(
+            run(&["math", "1", "/", "0"]).await,
+            (1, String::new(), String::from("math: division by zero\n"))
+        );
+    }
+
+    #[tokio::test]
+    async fn seq_rejects_infinite_bounds() {
+        for bound in ["inf", "-inf", "nan"] {
+            let (code, out, err) = run(&["seq", "1", bound]).await;
+            assert_eq!((code, out.as_str()), (2, ""));
+            assert_eq!(err, format!("seq: invalid number: {bound}\n"));
         }
     }
 }
@@ -2,20 +2,77 @@
 
 use crate::lang::tokens::{Token, TokenType};
 use itertools::Itertools;
+use std::ops::Range;
 
 #[derive(Clone, Debug)]
 #[repr(i32)]
 pub enum ErrorKind {
     UnexpectedToken(Token, Token, Vec<TokenType>) = 1,
     RequiredTokenNotFound(Token, Token, Vec<TokenType>) = 2,
+    /// A redirection with an invalid target, like `2>&x`, with the redirection and its target.
+    InvalidRedirection(Token, Token) = 3,
+    /// A here-document whose delimiter line wasn't reached, with its delimiter.
+    UnterminatedHereDoc(Token) = 4,
+    /// A `${...}` that isn't a valid parameter, like `${}` or `${NAME^}`.
+    BadSubstitution(Token) = 5,
+    /// A quote, `$(` or `` ` `` still open at the end of the input, with its opening token.
+    Unterminated(Token) = 6,
 }
 
 impl ErrorKind {
+    /// Returns whether the error is only that the input ended early, before the command after
+    /// `&&`, `||`, `|`, `(`, `{`, a reserved word like `then` or a `case` arm, the end of a
+    /// compound command like `}`, `fi` or `]]`, or the body of a here-document, which the next lines may
+    /// hold.
+    #[must_use]
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            Self::UnexpectedToken(token, after, _) => {
+                token.r#type == TokenType::Eof
+                    && [
+                        TokenType::AndAnd,
+                        TokenType::OrOr,
+                        TokenType::Pipe,
+                        TokenType::LeftParen,
+                        TokenType::LeftBrace,
+                        TokenType::If,
+                        TokenType::Then,
+                        TokenType::Elif,
+                        TokenType::Else,
+                        TokenType::PatternsEnd,
+                        TokenType::DoubleSemicolon,
+                    ]
+                    .contains(&after.r#type)
+            }
+            Self::RequiredTokenNotFound(token, _, expected) => {
+                token.r#type == TokenType::Eof
+                    && expected.iter().any(|r#type| {
+                        [
+                            TokenType::RightParen,
+                            TokenType::RightBrace,
+                            TokenType::Then,
+                            TokenType::Fi,
+                            TokenType::In,
+                            TokenType::Esac,
+                            TokenType::RightBrackets,
+                        ]
+                        .contains(r#type)
+                    })
+            }
+            Self::UnterminatedHereDoc(_) | Self::Unterminated(_) => true,
+            Self::InvalidRedirection(_, _) | Self::BadSubstitution(_) => false,
+        }
+    }
+
     #[must_use]
     pub fn code(self) -> i32 {
         match self {
-            Self::UnexpectedToken(_, _, _) => 1,
-            Self::RequiredTokenNotFound(_, _, _) => 2,
+            Self::UnexpectedToken(_, _, _)
+            | Self::InvalidRedirection(_, _)
+            | Self::BadSubstitution(_) => 1,
+            Self::RequiredTokenNotFound(_, _, _)
+            | Self::UnterminatedHereDoc(_)
+            | Self::Unterminated(_) => 2,
         }
     }
 }
@@ -30,30 +87,145 @@ impl std::fmt::Display for ErrorKind {
                 "expected {}",
                 expected_tokens.iter().map(ToString::to_string).join(" or ")
             )),
+            Self::InvalidRedirection(redirection, target) => f.write_fmt(format_args!(
+                "invalid redirection {}{}",
+                redirection.lexeme, target.lexeme
+            )),
+            Self::UnterminatedHereDoc(_) => f.write_str("unterminated here-document"),
+            Self::BadSubstitution(token) => write!(f, "bad substitution {}", token.lexeme),
+            Self::Unterminated(token) => write!(f, "unterminated {}", token.lexeme),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Error {
-    kind: ErrorKind,
+    /// Boxed, as tokens take some room.
+    kind: Box<ErrorKind>,
+    /// The errors found after this one in the same input, reported along with it.
+    others: Vec<Self>,
+    /// The input, to show where the errors are in it.
+    source: Option<String>,
 }
 
 impl Error {
     #[must_use]
     pub fn kind(&self) -> ErrorKind {
-        self.kind.clone()
+        (*self.kind).clone()
+    }
+
+    /// Returns the kinds of this error and of the ones found after it.
+    pub fn kinds(&self) -> impl Iterator<Item = &ErrorKind> {
+        std::iter::once(&*self.kind).chain(self.others.iter().map(|other| &*other.kind))
     }
 }
 
 impl Error {
     #[must_use]
     pub fn new(kind: ErrorKind) -> Self {
-        Self { kind }
+        Self {
+            kind: Box::new(kind),
+            others: Vec::new(),
+            source: None,
+        }
+    }
+
+    /// Returns the error with the `source` it was found in, which is then shown with the
+    /// offending tokens underlined.
+    #[must_use]
+    pub fn with_source(self, source: &str) -> Self {
+        Self {
+            source: Some(source.to_string()),
+            ..self
+        }
+    }
+
+    /// Returns the error found in a part of the input starting at `offset`, like the command of
+    /// a substitution, with its spans moved to where they are in the whole input.
+    #[must_use]
+    pub fn shifted(self, offset: usize) -> Self {
+        let shift = |token: &mut Token| {
+            token.span = token.span.start + offset..token.span.end + offset;
+        };
+
+        let mut kind = self.kind;
+        match &mut *kind {
+            ErrorKind::UnexpectedToken(token, after, _)
+            | ErrorKind::RequiredTokenNotFound(token, after, _)
+            | ErrorKind::InvalidRedirection(token, after) => {
+                shift(token);
+                shift(after);
+            }
+            ErrorKind::UnterminatedHereDoc(token)
+            | ErrorKind::BadSubstitution(token)
+            | ErrorKind::Unterminated(token) => shift(token),
+        }
+
+        Self {
+            kind,
+            others: self
+                .others
+                .into_iter()
+                .map(|other| other.shifted(offset))
+                .collect(),
+            source: self.source,
+        }
+    }
+
+    /// Returns the span of the tokens the error is about.
+    pub(crate) fn span(&self) -> Range<usize> {
+        match &*self.kind {
+            ErrorKind::UnexpectedToken(token, _, _)
+            | ErrorKind::RequiredTokenNotFound(token, _, _)
+            | ErrorKind::UnterminatedHereDoc(token)
+            | ErrorKind::BadSubstitution(token)
+            | ErrorKind::Unterminated(token) => token.span.clone(),
+            ErrorKind::InvalidRedirection(redirection, target) => {
+                redirection.span.start..target.span.end
+            }
+        }
+    }
+
+    /// Returns the first of `errors` with the others found after it, or `None` if there are
+    /// none. The ones found after each of `errors` are kept in order.
+    #[must_use]
+    pub fn all(errors: Vec<Self>) -> Option<Self> {
+        let mut errors = errors.into_iter().flat_map(|mut error| {
+            let others = std::mem::take(&mut error.others);
+            std::iter::once(error).chain(others)
+        });
+        let first = errors.next()?;
+
+        Some(Self {
+            others: errors.collect(),
+            ..first
+        })
     }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = self.source.as_deref();
+        self.fmt_kind(f, source)?;
+
+        for other in &self.others {
+            f.write_str("\n\n")?;
+            other.fmt_kind(f, source)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error {
+    /// Writes this error alone, without the ones found after it, showing where it is in
+    /// `source`.
+    fn fmt_kind(&self, f: &mut std::fmt::Formatter<'_>, source: Option<&str>) -> std::fmt::Result {
+        write!(f, "{}\n\n", self.kind)?;
+        if let Some(source) = source {
+            writeln!(f, "{}", snippet(source, &self.span()))?;
+        }
+
         match self.kind() {
             ErrorKind::UnexpectedToken(unexpected_token, after_token, expected_tokens) => {
                 let location = if unexpected_token.r#type == TokenType::Eof {
@@ -63,8 +235,7 @@ impl std::fmt::Display for Error {
                 };
 
                 f.write_fmt(format_args!(
-                    "{}\n\nexpected {}, not {} {}",
-                    self.kind,
+                    "expected {}, not {} {}",
                     expected_tokens.iter().map(ToString::to_string).join(" or "),
                     unexpected_token.r#type,
                     location
@@ -78,13 +249,81 @@ impl std::fmt::Display for Error {
                 };
 
                 f.write_fmt(format_args!(
-                    "{}\n\nexpected {}, not {} {}",
-                    self.kind,
+                    "expected {}, not {} {}",
                     expected_tokens.iter().map(ToString::to_string).join(","),
                     found_token.r#type,
                     location
                 ))
             }
+            ErrorKind::InvalidRedirection(_, _) => write!(
+                f,
+                "expected a descriptor from 0 to {} or -",
+                crate::redirect::MAX_FD
+            ),
+            ErrorKind::UnterminatedHereDoc(delimiter) => {
+                write!(f, "expected a line with just {}", delimiter.lexeme)
+            }
+            ErrorKind::BadSubstitution(_) => f.write_str(
+                "expected a name, a number or a special parameter, then maybe an operator like \
+                 :-, :=, :+, :?, #, %, / or [N]",
+            ),
+            ErrorKind::Unterminated(token) => {
+                let close = match token.lexeme.as_str() {
+                    "$(" => ")",
+                    "$'" => "'",
+                    open => open,
+                };
+                write!(f, "expected a closing {close} before the end")
+            }
         }
     }
 }
+
+/// Returns the line of `source` with the start of `span` in it, its characters in `span`
+/// underlined with carets, and its number if `source` has more than one line.
+fn snippet(source: &str, span: &Range<usize>) -> String {
+    let chars = source.chars().collect::<Vec<_>>();
+    let start = span.start.min(chars.len());
+
+    let line_start = chars[..start]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |index| index + 1);
+    let line_end = chars[start..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(chars.len(), |index| start + index);
+    let line = chars[line_start..line_end].iter().collect::<String>();
+
+    let gutter = if chars.contains(&'\n') {
+        let number = chars[..line_start].iter().filter(|&&c| c == '\n').count() + 1;
+        format!("{number} | ")
+    } else {
+        String::from("  ")
+    };
+
+    // tabs before the span keep the carets under it
+    let indent = chars[line_start..start]
+        .iter()
+        .map(|&c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let width = span.end.min(line_end).saturating_sub(start).max(1);
+
+    format!(
+        "{gutter}{line}\n{}{indent}{}",
+        " ".repeat(gutter.chars().count()),
+        "^".repeat(width)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippets_underline_the_span() {
+        assert_eq!(snippet("a && && b", &(5..7)), "  a && && b\n       ^^");
+        assert_eq!(snippet("a\n\tb )", &(5..6)), "2 | \tb )\n    \t  ^");
+        assert_eq!(snippet("a &&", &(4..4)), "  a &&\n      ^");
+    }
+}
@@ -1,9 +1,56 @@
-use super::tokens::{Token, TokenType};
-use crate::Command;
+//! The parser, turning the tokens of a line into its syntax tree, which the shell runs by walking
+//! it.
+//!
+//! - a [`List`] joins pipelines with `&&`, `||`, `;` and `&`;
+//! - a [`Pipeline`] joins commands with `|`, negated by a leading `!`;
+//! - a [`Command`] has assignments, words and [`Redirect`]s, or a [`Compound`] command like a
+//!   subshell, `if` or `case` holding more lists;
+//! - a [`Word`] is made of [`Piece`](crate::word::Piece)s, text and the parameters expanded in
+//!   it, or command substitutions holding the [`List`] of their command;
+//! - a [`Redirect`] to a file has a [`Word`] for its path, expanded when the command runs.
+//!
+//! [`parse`] builds the tree of a line from its source.
+
+use super::{
+    scanner::Scanner,
+    tokens::{Token, TokenType},
+};
+use crate::{
+    command::Compound, list::List, log, pipeline::Pipeline, redirect::Redirect, word::Word, Command,
+};
 use error::{Error, ErrorKind};
 
 pub mod error;
 
+/// Parses `source` into the syntax tree of a list of pipelines, or `None` if there are none,
+/// without running anything.
+///
+/// # Errors
+///
+/// This function will return an error if `source` is not a valid command line, with the errors
+/// found after the first one and in the commands of substitutions, shown in `source`.
+pub async fn parse(source: &str) -> Result<Option<List>, Error> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().await;
+
+    if log::enabled(log::Level::Trace) {
+        log!(Trace, "scanner", "{tokens:?}");
+    }
+
+    // the commands of substitutions are parsed as they are scanned
+    let mut errors = scanner.errors();
+    let list = Parser::new(tokens).parse_tokens().unwrap_or_else(|error| {
+        errors.push(error);
+        None
+    });
+    errors.sort_by_key(|error| error.span().start);
+
+    match Error::all(errors) {
+        Some(error) => Err(error.with_source(source)),
+        None => Ok(list),
+    }
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
@@ -26,156 +73,545 @@ impl Parser {
         }
     }
 
-    fn check_next(&self, r#type: &TokenType) -> bool {
-        if self.is_at_end() {
-            false
-        } else {
-            &self.peek_next().r#type == r#type
-        }
-    }
-
     fn is_at_end(&self) -> bool {
         self.peek().r#type == TokenType::Eof
     }
 
-    fn match_next(&mut self, r#type: &TokenType) -> bool {
-        if self.check_next(r#type) {
-            self.advance();
-            true
-        } else {
-            false
-        }
-    }
-
     #[must_use]
     pub fn new(tokens: Vec<Token>) -> Self {
         Self { tokens, current: 0 }
     }
 
-    /// Returns the parse tokens of this [`Parser`].
+    /// Parses the tokens into the syntax tree of a list of pipelines, or `None` if there are none.
     ///
     /// # Errors
     ///
-    /// This function will return an error if .
-    pub fn parse_tokens(&mut self) -> Result<Vec<Command>, Error> {
-        let mut commands = Vec::new();
-        let mut first_command = Vec::new();
+    /// This function will return an error if the tokens are not a valid command line, with the
+    /// errors found after the first one, as parsing goes on past the next `;` or `&`.
+    pub fn parse_tokens(&mut self) -> Result<Option<List>, Error> {
+        let mut list = None;
+        let mut errors = Vec::new();
 
-        // EOF token
-        if self.is_at_end() {
-            return Ok(Vec::new());
+        while !self.is_at_end() {
+            match self.top_item() {
+                Ok(item) => {
+                    list = Some(match list {
+                        Some(list) => List::Sequence(Box::new(list), Box::new(item)),
+                        None => item,
+                    });
+                }
+                Err(error) => {
+                    errors.push(error);
+                    self.synchronize();
+                }
+            }
         }
 
+        match Error::all(errors) {
+            Some(error) => Err(error),
+            None => Ok(list),
+        }
+    }
+
+    /// Parses an item of the line, which must be followed by a `;`, a `&` or the end.
+    fn top_item(&mut self) -> Result<List, Error> {
+        let (item, separated) = self.item()?;
+
+        // only a `)`, `}` or reserved word like `fi` out of place stops an item before the end
+        if !separated && !self.is_at_end() {
+            return Err(Error::new(ErrorKind::UnexpectedToken(
+                self.peek().clone(),
+                self.peek_back().clone(),
+                vec![TokenType::Semicolon, TokenType::Eof],
+            )));
+        }
+
+        Ok(item)
+    }
+
+    /// Skips the tokens up to the next `;` or `&` outside of brackets after an error, for
+    /// parsing to go on and report the errors after it too.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+
         while !self.is_at_end() {
-            let t = self.advance().clone();
-            match t.r#type {
-                TokenType::AndAnd => {
-                    let next_token = self.peek();
-
-                    if vec![
-                        TokenType::Pipe,
-                        TokenType::And,
-                        TokenType::AndAnd,
-                        TokenType::Eof,
-                        TokenType::OrOr,
-                        TokenType::Semicolon,
-                    ]
-                    .contains(&next_token.r#type)
-                    {
-                        return Err(Error::new(ErrorKind::UnexpectedToken(
-                            next_token.clone(),
-                            t,
-                            vec![TokenType::DollarSign, TokenType::Part],
-                        )));
-                    }
-
-                    let other_commands = self.parse_tokens()?;
-
-                    for command in other_commands {
-                        commands.push(command);
-                    }
+            match self.advance().r#type {
+                TokenType::LeftParen
+                | TokenType::LeftBrace
+                | TokenType::If
+                | TokenType::Case
+                | TokenType::LeftBrackets => {
+                    depth += 1;
+                }
+                TokenType::RightParen
+                | TokenType::RightBrace
+                | TokenType::Fi
+                | TokenType::Esac
+                | TokenType::RightBrackets => {
+                    depth -= 1;
+                }
+                TokenType::Semicolon | TokenType::And if depth <= 0 => break,
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns whether the current token ends a list inside a compound command, like `)` or
+    /// `then`.
+    fn is_at_list_end(&self) -> bool {
+        [
+            TokenType::RightParen,
+            TokenType::RightBrace,
+            TokenType::Then,
+            TokenType::Elif,
+            TokenType::Else,
+            TokenType::Fi,
+            TokenType::DoubleSemicolon,
+            TokenType::Esac,
+        ]
+        .contains(&self.peek().r#type)
+    }
+
+    /// Parses and-or lists separated by `;` or `&`, which may end the line, a subshell, a group
+    /// or a part of a conditional.
+    fn list(&mut self) -> Result<List, Error> {
+        let (mut list, mut separated) = self.item()?;
+
+        while separated && !self.is_at_end() && !self.is_at_list_end() {
+            let (item, item_separated) = self.item()?;
+            list = List::Sequence(Box::new(list), Box::new(item));
+            separated = item_separated;
+        }
+
+        Ok(list)
+    }
+
+    /// Parses an and-or list and the `;` or `&` after it, returning whether there was one.
+    fn item(&mut self) -> Result<(List, bool), Error> {
+        let list = self.and_or()?;
+
+        if self.r#match(&TokenType::And) {
+            return Ok((List::Background(Box::new(list)), true));
+        }
+
+        Ok((list, self.r#match(&TokenType::Semicolon)))
+    }
+
+    /// Parses pipelines joined by `&&` and `||`.
+    fn and_or(&mut self) -> Result<List, Error> {
+        let mut list = List::Pipeline(self.pipeline()?);
+
+        loop {
+            let join = match self.peek().r#type {
+                TokenType::AndAnd => List::And,
+                TokenType::OrOr => List::Or,
+                _ => break,
+            };
+
+            let operator = self.advance().clone();
+            self.expect_command(operator)?;
+
+            list = join(Box::new(list), Box::new(List::Pipeline(self.pipeline()?)));
+        }
+
+        Ok(list)
+    }
+
+    /// Parses commands joined by `|`, negated by the `!`s before them.
+    fn pipeline(&mut self) -> Result<Pipeline, Error> {
+        let mut negated = false;
+        while self.check(&TokenType::Bang) {
+            let bang = self.advance().clone();
+            self.expect_command(bang)?;
+            negated = !negated;
+        }
+
+        let mut commands = vec![self.command()?];
+
+        while self.check(&TokenType::Pipe) {
+            let operator = self.advance().clone();
+            self.expect_command(operator)?;
+
+            commands.push(self.command()?);
+        }
+
+        Ok(Pipeline::new(commands, negated))
+    }
+
+    /// Parses the words and redirections of a command, up to the next operator.
+    fn command(&mut self) -> Result<Command, Error> {
+        if [
+            TokenType::LeftParen,
+            TokenType::LeftBrace,
+            TokenType::If,
+            TokenType::Case,
+            TokenType::LeftBrackets,
+        ]
+        .contains(&self.peek().r#type)
+        {
+            return self.compound();
+        }
+
+        let mut assignments = Vec::new();
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+
+        loop {
+            match self.peek().r#type {
+                TokenType::Eof
+                | TokenType::And
+                | TokenType::AndAnd
+                | TokenType::OrOr
+                | TokenType::Pipe
+                | TokenType::Semicolon
+                | TokenType::RightParen
+                | TokenType::Then
+                | TokenType::Elif
+                | TokenType::Else
+                | TokenType::Fi
+                | TokenType::DoubleSemicolon
+                | TokenType::Esac => break,
+
+                // a subshell or compound command is a command of its own, and `!` negates whole
+                // pipelines
+                TokenType::LeftParen
+                | TokenType::Bang
+                | TokenType::If
+                | TokenType::Case
+                | TokenType::LeftBrackets => {
+                    return Err(Error::new(ErrorKind::UnexpectedToken(
+                        self.peek().clone(),
+                        self.peek_back().clone(),
+                        vec![TokenType::Semicolon, TokenType::Pipe],
+                    )));
                 }
 
-                TokenType::And => unimplemented!(),
+                TokenType::Assignment if words.is_empty() => {
+                    let token = self.advance().clone();
+                    let word = token.word.unwrap_or_else(|| Word::from(token.lexeme));
+                    let Some((name, value)) = word.assignment() else {
+                        unreachable!("the scanner only makes assignments of `NAME=` words");
+                    };
+
+                    assignments.push((name, value));
+                }
 
-                TokenType::Part => {
-                    first_command.push(t.lexeme);
+                // braces are plain words past the start of a command
+                TokenType::Part
+                | TokenType::Assignment
+                | TokenType::LeftBrace
+                | TokenType::RightBrace => {
+                    let token = self.advance();
+                    words.push(Self::word(token));
                 }
 
-                // end of command
-                TokenType::Eof => break,
+                TokenType::Redirect => {
+                    let operator = self.advance().clone();
+                    redirects.push(self.redirect(operator)?);
+                }
 
+                // the scanner only leaves a `${` that isn't a parameter
                 TokenType::DollarSign => {
-                    let t = self.peek().clone();
-                    match t.r#type {
-                        TokenType::Part => {
-                            let var = self.advance().lexeme.clone();
-                            first_command.push(std::env::var(var).unwrap_or_default());
-                        }
-                        TokenType::LeftBrace => {
-                            if !self.match_next(&TokenType::Part) {
-                                return Err(Error::new(ErrorKind::UnexpectedToken(
-                                    self.peek_next().clone(),
-                                    t,
-                                    vec![TokenType::Part],
-                                )));
-                            }
-
-                            let var = self.advance().lexeme.clone();
-
-                            // If there is syntax like this: "echo ${HOME:-false}"
-                            if self.r#match(&TokenType::ColonDash) && self.r#match(&TokenType::Part)
-                            {
-                                first_command.push(
-                                    std::env::var(var)
-                                        .unwrap_or_else(|_| self.previous().lexeme.clone()),
-                                );
-                            } else {
-                                first_command.push(std::env::var(var).unwrap_or_default());
-                            }
-
-                            if !self.r#match(&TokenType::RightBrace) {
-                                return Err(Error::new(ErrorKind::RequiredTokenNotFound(
-                                    self.peek().clone(),
-                                    self.peek_back().clone(),
-                                    vec![TokenType::RightBrace],
-                                )));
-                            }
-                        }
-                        _ => {
-                            return Err(Error::new(ErrorKind::UnexpectedToken(
-                                t,
-                                self.peek_back().clone(),
-                                vec![TokenType::Part, TokenType::LeftBrace],
-                            )))
-                        }
-                    }
+                    return Err(Error::new(ErrorKind::BadSubstitution(self.peek().clone())));
                 }
-                token => {
-                    eprintln!("{token:?} is not implemented currently.");
-                    return Ok(Vec::new());
+
+                // bodies only follow the delimiters of here-documents, only a `case` has `in`
+                // and the `)` ending patterns, and only a `[[` has a `]]`
+                TokenType::HereDoc
+                | TokenType::In
+                | TokenType::PatternsEnd
+                | TokenType::RightBrackets => {
+                    return Err(Error::new(ErrorKind::UnexpectedToken(
+                        self.peek().clone(),
+                        self.peek_back().clone(),
+                        vec![TokenType::Part],
+                    )));
                 }
             }
         }
 
-        commands.insert(
-            0,
-            Command::new(first_command[0].clone(), first_command[1..].to_vec()),
-        );
+        Ok(Command {
+            assignments,
+            words,
+            redirects,
+            compound: None,
+        })
+    }
 
-        Ok(commands)
+    /// Parses a `( LIST )` subshell, a `{ LIST; }` group, an `if` conditional, a `case`
+    /// statement or a `[[ ]]` test, and the redirections after it.
+    fn compound(&mut self) -> Result<Command, Error> {
+        let compound = match self.peek().r#type {
+            TokenType::If => self.conditional()?,
+            TokenType::Case => self.case()?,
+            TokenType::LeftBrackets => self.test()?,
+            _ => self.group()?,
+        };
+
+        let mut redirects = Vec::new();
+        while self.check(&TokenType::Redirect) {
+            let operator = self.advance().clone();
+            redirects.push(self.redirect(operator)?);
+        }
+
+        if !self.is_at_end()
+            && ![
+                TokenType::And,
+                TokenType::AndAnd,
+                TokenType::OrOr,
+                TokenType::Pipe,
+                TokenType::Semicolon,
+                TokenType::RightParen,
+                TokenType::RightBrace,
+                TokenType::DoubleSemicolon,
+            ]
+            .contains(&self.peek().r#type)
+        {
+            return Err(Error::new(ErrorKind::UnexpectedToken(
+                self.peek().clone(),
+                self.peek_back().clone(),
+                vec![TokenType::Semicolon, TokenType::Pipe],
+            )));
+        }
+
+        Ok(Command {
+            assignments: Vec::new(),
+            words: Vec::new(),
+            redirects,
+            compound: Some(compound),
+        })
+    }
+
+    /// Parses a `( LIST )` subshell or a `{ LIST; }` group.
+    fn group(&mut self) -> Result<Compound, Error> {
+        let open = self.advance().clone();
+        let close = if open.r#type == TokenType::LeftParen {
+            TokenType::RightParen
+        } else {
+            TokenType::RightBrace
+        };
+        self.expect_command(open)?;
+
+        let list = Box::new(self.list()?);
+        self.consume(&close, vec![close.clone()])?;
+
+        Ok(if close == TokenType::RightParen {
+            Compound::Subshell(list)
+        } else {
+            Compound::Group(list)
+        })
+    }
+
+    /// Parses `if LIST; then LIST; [elif LIST; then LIST;]... [else LIST;] fi`.
+    fn conditional(&mut self) -> Result<Compound, Error> {
+        let mut branches = Vec::new();
+
+        // `if` or `elif`
+        while branches.is_empty() || self.check(&TokenType::Elif) {
+            let keyword = self.advance().clone();
+            self.expect_command(keyword)?;
+            let condition = self.list()?;
+
+            let then = self.consume(&TokenType::Then, vec![TokenType::Then])?;
+            self.expect_command(then)?;
+            branches.push((condition, self.list()?));
+        }
+
+        let otherwise = if self.check(&TokenType::Else) {
+            let keyword = self.advance().clone();
+            self.expect_command(keyword)?;
+            Some(Box::new(self.list()?))
+        } else {
+            None
+        };
+
+        self.consume(
+            &TokenType::Fi,
+            vec![TokenType::Elif, TokenType::Else, TokenType::Fi],
+        )?;
+
+        Ok(Compound::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    /// Parses `case WORD in [(]PATTERN[|PATTERN]...) [LIST] ;; ... esac`, where the `;;` of the
+    /// last arm is optional.
+    fn case(&mut self) -> Result<Compound, Error> {
+        let keyword = self.advance().clone();
+        if !self.r#match(&TokenType::Part) {
+            return Err(Error::new(ErrorKind::UnexpectedToken(
+                self.peek().clone(),
+                keyword,
+                vec![TokenType::Part],
+            )));
+        }
+        let word = Self::word(self.previous());
+        self.consume(&TokenType::In, vec![TokenType::In])?;
+
+        let mut arms = Vec::new();
+        loop {
+            // newlines may separate the arms
+            while self.r#match(&TokenType::Semicolon) {}
+            if self.r#match(&TokenType::Esac) {
+                break;
+            }
+
+            self.r#match(&TokenType::LeftParen);
+            let mut patterns = vec![self.pattern()?];
+            while self.r#match(&TokenType::Pipe) {
+                patterns.push(self.pattern()?);
+            }
+            self.consume(
+                &TokenType::PatternsEnd,
+                vec![TokenType::Pipe, TokenType::PatternsEnd],
+            )?;
+
+            while self.r#match(&TokenType::Semicolon) {}
+            let list = if self.is_at_end()
+                || self.check(&TokenType::DoubleSemicolon)
+                || self.check(&TokenType::Esac)
+            {
+                None
+            } else {
+                Some(self.list()?)
+            };
+            arms.push((patterns, list));
+
+            if !self.r#match(&TokenType::DoubleSemicolon) {
+                self.consume(
+                    &TokenType::Esac,
+                    vec![TokenType::DoubleSemicolon, TokenType::Esac],
+                )?;
+                break;
+            }
+        }
+
+        Ok(Compound::Case { word, arms })
+    }
+
+    /// Parses `[[ EXPRESSION ]]`, whose words are only expanded as the test runs.
+    fn test(&mut self) -> Result<Compound, Error> {
+        self.advance();
+
+        let mut words = Vec::new();
+        while self.r#match(&TokenType::Part) {
+            words.push(Self::word(self.previous()));
+        }
+        self.consume(&TokenType::RightBrackets, vec![TokenType::RightBrackets])?;
+
+        Ok(Compound::Test(words))
+    }
+
+    /// Parses a pattern of a `case` arm.
+    fn pattern(&mut self) -> Result<Word, Error> {
+        // the end of the line may still be followed by patterns
+        if self.is_at_end() {
+            return Err(Error::new(ErrorKind::RequiredTokenNotFound(
+                self.peek().clone(),
+                self.peek_back().clone(),
+                vec![TokenType::Esac],
+            )));
+        }
+
+        if !self.r#match(&TokenType::Part) {
+            return Err(Error::new(ErrorKind::UnexpectedToken(
+                self.peek().clone(),
+                self.peek_back().clone(),
+                vec![TokenType::Part],
+            )));
+        }
+
+        Ok(Self::word(self.previous()))
+    }
+
+    /// Returns the word of `token`, with the parameters to expand in it.
+    fn word(token: &Token) -> Word {
+        token
+            .word
+            .clone()
+            .unwrap_or_else(|| Word::from(token.lexeme.clone()))
+    }
+
+    /// Parses the token closing a compound command, like `)` or `fi`, or fails expecting one of
+    /// `expected`.
+    fn consume(&mut self, r#type: &TokenType, expected: Vec<TokenType>) -> Result<Token, Error> {
+        if !self.r#match(r#type) {
+            return Err(Error::new(ErrorKind::RequiredTokenNotFound(
+                self.peek().clone(),
+                self.peek_back().clone(),
+                expected,
+            )));
+        }
+
+        Ok(self.previous().clone())
+    }
+
+    /// Checks that a command follows the `operator` just parsed, like `&&` or `|`.
+    fn expect_command(&self, operator: Token) -> Result<(), Error> {
+        let next_token = self.peek();
+
+        if [
+            TokenType::Pipe,
+            TokenType::And,
+            TokenType::AndAnd,
+            TokenType::Eof,
+            TokenType::OrOr,
+            TokenType::Semicolon,
+            TokenType::RightParen,
+            TokenType::RightBrace,
+            TokenType::Then,
+            TokenType::Elif,
+            TokenType::Else,
+            TokenType::Fi,
+            TokenType::DoubleSemicolon,
+            TokenType::Esac,
+        ]
+        .contains(&next_token.r#type)
+        {
+            return Err(Error::new(ErrorKind::UnexpectedToken(
+                next_token.clone(),
+                operator,
+                vec![TokenType::Part],
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Parses the target of the redirection `operator`.
+    fn redirect(&mut self, operator: Token) -> Result<Redirect, Error> {
+        if !self.r#match(&TokenType::Part) {
+            return Err(Error::new(ErrorKind::UnexpectedToken(
+                self.peek().clone(),
+                operator,
+                vec![TokenType::Part],
+            )));
+        }
+
+        let target = self.previous().clone();
+
+        if operator.lexeme.contains("<<") {
+            if !self.r#match(&TokenType::HereDoc) {
+                return Err(Error::new(ErrorKind::UnterminatedHereDoc(target)));
+            }
+
+            let body = self.previous().lexeme.clone();
+            return Ok(Redirect::here_doc(&operator.lexeme, &target.lexeme, body));
+        }
+
+        Redirect::new(&operator.lexeme, Self::word(&target))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidRedirection(operator, target)))
     }
 
     fn peek(&self) -> &Token {
         &self.tokens[self.current]
     }
 
+    /// Returns the token before the current one, or the current one at the start.
     fn peek_back(&self) -> &Token {
-        &self.tokens[self.current - 1]
-    }
-
-    fn peek_next(&self) -> &Token {
-        &self.tokens[self.current + 1]
+        &self.tokens[self.current.saturating_sub(1)]
     }
 
     fn previous(&self) -> &Token {
@@ -191,3 +627,350 @@ impl Parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the tree of `list` with its groups in parentheses and its commands by keyword.
+    fn shape(list: &List) -> String {
+        match list {
+            List::Pipeline(pipeline) => {
+                let commands = pipeline
+                    .commands()
+                    .iter()
+                    .map(command_shape)
+                    .collect::<Vec<_>>()
+                    .join(" | ");
+                if pipeline.is_negated() {
+                    format!("!{commands}")
+                } else {
+                    commands
+                }
+            }
+            List::And(left, right) => format!("({} && {})", shape(left), shape(right)),
+            List::Or(left, right) => format!("({} || {})", shape(left), shape(right)),
+            List::Sequence(left, right) => format!("({}; {})", shape(left), shape(right)),
+            List::Background(list) => format!("({} &)", shape(list)),
+        }
+    }
+
+    /// Writes the keyword of `command`, or the shape of its compound command.
+    fn command_shape(command: &Command) -> String {
+        match command.compound() {
+            Some(Compound::Subshell(list)) => format!("[{}]", shape(list)),
+            Some(Compound::Group(list)) => format!("{{{}}}", shape(list)),
+            Some(Compound::If {
+                branches,
+                otherwise,
+            }) => {
+                let branches = branches
+                    .iter()
+                    .map(|(condition, list)| format!("{} ? {}", shape(condition), shape(list)))
+                    .collect::<Vec<_>>()
+                    .join(" : ");
+                let otherwise = otherwise
+                    .as_ref()
+                    .map(|list| format!(" : {}", shape(list)))
+                    .unwrap_or_default();
+                format!("if[{branches}{otherwise}]")
+            }
+            Some(Compound::Test(words)) => {
+                let words = words.iter().map(Word::text).collect::<Vec<_>>();
+                format!("[[{}]]", words.join(" "))
+            }
+            Some(Compound::Case { word, arms }) => {
+                let arms = arms
+                    .iter()
+                    .map(|(patterns, list)| {
+                        let patterns = patterns.iter().map(ToString::to_string).collect::<Vec<_>>();
+                        let list = list.as_ref().map(shape).unwrap_or_default();
+                        format!("{} ? {list}", patterns.join(" | "))
+                    })
+                    .collect::<Vec<_>>();
+                format!("case[{word}: {}]", arms.join(" : "))
+            }
+            None => command.words()[0].to_string(),
+        }
+    }
+
+    async fn parse_shape(source: &str) -> String {
+        match parse(source).await {
+            Ok(Some(list)) => shape(&list),
+            Ok(None) => String::new(),
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn pipelines() {
+        assert_eq!(parse_shape("a 1 | b 2>&1 | c").await, "a | b | c");
+
+        let Ok(Some(List::Pipeline(pipeline))) = parse("a | b 2>&1").await else {
+            panic!("a pipeline didn't parse");
+        };
+        assert_eq!(pipeline.commands[1].redirects.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn pipes_need_commands() {
+        for source in ["a |", "a | | b", "a | && b"] {
+            assert!(matches!(
+                parse(source).await.map_err(|error| error.kind()),
+                Err(ErrorKind::UnexpectedToken(..))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn lists() {
+        assert_eq!(parse_shape("a && b || c; d").await, "(((a && b) || c); d)");
+        assert_eq!(parse_shape("a | b || c && d").await, "((a | b || c) && d)");
+        assert_eq!(parse_shape("a;").await, "a");
+        assert_eq!(parse_shape("").await, "");
+    }
+
+    #[tokio::test]
+    async fn operators_need_commands() {
+        for source in ["a &&", "a || || b", "a && ; b"] {
+            assert!(parse(source).await.is_err(), "{source}");
+        }
+    }
+
+    #[tokio::test]
+    async fn background_lists() {
+        assert_eq!(parse_shape("a && b & c").await, "(((a && b) &); c)");
+        assert_eq!(parse_shape("a & b &").await, "((a &); (b &))");
+
+        let Ok(Some(list)) = parse("a && b || c; d &").await else {
+            panic!("the list didn't parse");
+        };
+        assert_eq!(list.to_string(), "a && b || c; d &");
+    }
+
+    #[tokio::test]
+    async fn subshells() {
+        assert_eq!(parse_shape("(a; b) | c").await, "[(a; b)] | c");
+        assert_eq!(parse_shape("a && (b || (c))").await, "(a && [(b || [c])])");
+
+        let Ok(Some(List::Pipeline(pipeline))) = parse("(a) > f").await else {
+            panic!("the subshell didn't parse");
+        };
+        assert_eq!(pipeline.commands[0].redirects.len(), 1);
+
+        for source in ["(a", "a )", "()", "(a) b"] {
+            assert!(parse(source).await.is_err(), "{source}");
+        }
+    }
+
+    #[tokio::test]
+    async fn groups() {
+        assert_eq!(parse_shape("{ a; b; } | c").await, "{(a; b)} | c");
+        assert_eq!(parse_shape("a || { b && c; }").await, "(a || {(b && c)})");
+        assert_eq!(parse_shape("echo }").await, "echo");
+
+        let Ok(Some(List::Pipeline(pipeline))) = parse("{ a; } 2>&1").await else {
+            panic!("the group didn't parse");
+        };
+        assert_eq!(pipeline.commands[0].redirects.len(), 1);
+
+        for source in ["{ a", "{ a }", "{ }"] {
+            assert!(parse(source).await.is_err(), "{source}");
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_are_all_reported() {
+        let Err(error) = parse("a && && b; c | ; d & e").await else {
+            panic!("the errors weren't found");
+        };
+        assert_eq!(error.kinds().count(), 2);
+        assert!(error
+            .kinds()
+            .all(|kind| matches!(kind, ErrorKind::UnexpectedToken(..))));
+
+        let Err(error) = parse("a &&; b").await else {
+            panic!("the error wasn't found");
+        };
+        assert_eq!(error.kinds().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn incomplete_input() {
+        for source in [
+            "a &&",
+            "a |",
+            "a ||",
+            "{ a",
+            "(a",
+            "(a;",
+            "cat <<EOF",
+            "echo \"a",
+            "echo $(a",
+        ] {
+            let Err(error) = parse(source).await else {
+                panic!("{source} parsed");
+            };
+            assert!(error.kinds().any(ErrorKind::is_incomplete), "{source}");
+        }
+
+        for source in ["a )", "a && && b", "a 2>&x"] {
+            let Err(error) = parse(source).await else {
+                panic!("{source} parsed");
+            };
+            assert!(!error.kinds().any(ErrorKind::is_incomplete), "{source}");
+        }
+    }
+
+    #[tokio::test]
+    async fn newlines_end_commands() {
+        assert_eq!(parse_shape("a\nb").await, "(a; b)");
+        assert_eq!(parse_shape("a &&\nb |\nc").await, "(a && b | c)");
+        assert_eq!(parse_shape("{\na\n}").await, "{a}");
+    }
+
+    #[tokio::test]
+    async fn comments_end_at_the_line() {
+        assert_eq!(parse_shape("# a comment").await, "");
+        assert_eq!(parse_shape("a # b\nc").await, "(a; c)");
+    }
+
+    #[tokio::test]
+    async fn conditionals() {
+        assert_eq!(
+            parse_shape("if a; then b; elif c; then d; else e; fi").await,
+            "if[a ? b : c ? d : e]"
+        );
+        assert_eq!(
+            parse_shape("if a && b\nthen\nc; d\nfi | e").await,
+            "if[(a && b) ? (c; d)] | e"
+        );
+        assert_eq!(parse_shape("echo if then fi").await, "echo");
+
+        for source in ["if a; fi", "if a; then fi", "then a", "if a; then b; fi c"] {
+            assert!(parse(source).await.is_err(), "{source}");
+        }
+
+        let Err(error) = parse("if a; then b").await else {
+            panic!("an unfinished conditional parsed");
+        };
+        assert!(error.kinds().any(ErrorKind::is_incomplete));
+    }
+
+    #[tokio::test]
+    async fn case_statements() {
+        assert_eq!(
+            parse_shape("case $x in a | b) c ;; *) d; e ;; esac").await,
+            "case[${x}: a | b ? c : '*' ? (d; e)]"
+        );
+        assert_eq!(
+            parse_shape("case x in\n(a) ;;\nb) c\nesac").await,
+            "case[x: a ?  : b ? c]"
+        );
+        assert_eq!(parse_shape("case x in esac").await, "case[x: ]");
+
+        for source in ["case x a) b;; esac", "case x in a b) c;; esac", "case in"] {
+            assert!(parse(source).await.is_err(), "{source}");
+        }
+
+        let Err(error) = parse("case x in").await else {
+            panic!("an unfinished case statement parsed");
+        };
+        assert!(error.kinds().any(ErrorKind::is_incomplete));
+    }
+
+    #[tokio::test]
+    async fn tests() {
+        assert_eq!(
+            parse_shape("[[ -n a && ( b < c || ! d ) ]] && e").await,
+            "([[-n a && ( b < c || ! d )]] && e)"
+        );
+        assert_eq!(parse_shape("echo [[ a ]]").await, "echo");
+
+        for source in ["[[ a", "[[ a ]] b", "[[ a | b ]]"] {
+            assert!(parse(source).await.is_err(), "{source}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reserved_words_after_assignments() {
+        assert_eq!(parse_shape("X=1 [[ -n a ]]").await, "'[['");
+        assert_eq!(parse_shape("X=1 ! a").await, "'!'");
+
+        for source in [
+            "FOO=1 if true; then echo x; fi",
+            "FOO=1 case x in a) b ;; esac",
+        ] {
+            assert!(parse(source).await.is_err(), "{source}");
+        }
+    }
+
+    #[tokio::test]
+    async fn negation() {
+        assert_eq!(parse_shape("! a | b && ! c").await, "(!a | b && !c)");
+        assert_eq!(parse_shape("echo !").await, "echo");
+
+        for source in ["!", "! && a", "a | ! b"] {
+            assert!(parse(source).await.is_err(), "{source}");
+        }
+    }
+
+    #[tokio::test]
+    async fn commands_expose_their_parts() {
+        let Ok(Some(List::Pipeline(pipeline))) = parse("A=1 B=$x echo \"$y\"z 2>&1").await else {
+            panic!("the command didn't parse");
+        };
+        let [command] = pipeline.commands() else {
+            panic!("{pipeline:?}");
+        };
+
+        let names = command
+            .assignments()
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["A", "B"]);
+        assert_eq!(command.words().len(), 2);
+        assert!(!command.words()[1].is_text());
+        assert_eq!(command.redirects(), [Redirect::Dup { fd: 2, source: 1 }]);
+        assert!(command.compound().is_none());
+    }
+
+    #[tokio::test]
+    async fn lists_are_written_back() {
+        for (source, written) in [
+            ("a && b || c; d &", "a && b || c; d &"),
+            ("if a; then b; fi | c", "if a; then b; fi | c"),
+            (
+                "A=1 echo \"$x\" $(a|b) > f",
+                "A=1 echo \"${x}\" $(a | b) >f",
+            ),
+        ] {
+            let Ok(Some(list)) = parse(source).await else {
+                panic!("{source} didn't parse");
+            };
+            assert_eq!(list.to_string(), written);
+        }
+    }
+
+    #[tokio::test]
+    async fn substitution_errors_are_reported_where_they_are() {
+        let Err(error) = parse("echo $(a &&) $(b ||)").await else {
+            panic!("the substitutions parsed");
+        };
+
+        assert_eq!(error.kinds().count(), 2);
+        assert_eq!(error.span(), 11..11);
+        assert!(error.to_string().contains("$(a &&)"));
+    }
+
+    #[tokio::test]
+    async fn bad_substitutions() {
+        let Err(error) = parse("echo ${} ${a:}").await else {
+            panic!("the substitutions parsed");
+        };
+        assert!(error
+            .kinds()
+            .all(|kind| matches!(kind, ErrorKind::BadSubstitution(_))));
+    }
+}
@@ -1,6 +1,18 @@
+use std::ops::Range;
+
+use async_recursion::async_recursion;
+
 use crate::{
-    lang::tokens::{Token, TokenType},
-    ALIASES, PREVIOUS_EXIT_CODE,
+    lang::{
+        parser::{
+            error::{Error, ErrorKind},
+            Parser,
+        },
+        tokens::{Token, TokenType},
+    },
+    log, user, variables,
+    word::{Operator, Parameter, Piece, Word},
+    ALIASES,
 };
 
 #[derive(Clone)]
@@ -9,6 +21,40 @@ pub(crate) struct Scanner {
     current: usize,
     tokens: Vec<Token>,
     source: Vec<char>,
+    /// The index of the first token that may start a here-document without a body yet.
+    here_docs: usize,
+    /// The syntax errors of the commands of substitutions, which are parsed as they are scanned,
+    /// and the quotes and substitutions left open at the end.
+    errors: Vec<Error>,
+    /// The aliases being expanded, which aren't expanded again inside their own values.
+    aliases: Vec<String>,
+    /// Whether the source ended inside a quote, a substitution or `${`, or after a `\\`, and
+    /// goes on on the next line.
+    incomplete: bool,
+    /// What the words scanned are part of.
+    context: Context,
+}
+
+/// What words are part of, which changes what some of them are scanned as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Context {
+    /// Commands, with their arguments.
+    Commands,
+    /// The patterns of a `case` arm, up to its `)`, where only `esac` is a reserved word and
+    /// aliases aren't expanded.
+    Patterns,
+    /// The expression of a `[[ ]]`, up to its `]]`, where `&&`, `||`, `<`, `>` and parentheses
+    /// are words and newlines are whitespace.
+    Test,
+}
+
+/// The brackets and quotes of a line, see [`Scanner::brackets`].
+#[derive(Debug, Default)]
+pub(crate) struct Brackets {
+    /// The indexes of matching `{}` and `()` pairs.
+    pub(crate) pairs: Vec<(usize, usize)>,
+    /// The indexes of brackets without a match, and of a quote that isn't closed.
+    pub(crate) unmatched: Vec<usize>,
 }
 
 #[derive(Clone)]
@@ -40,11 +86,13 @@ impl Scanner {
     fn add_token(&mut self, r#type: TokenType) {
         let text: String = self.source[self.start..self.current].iter().collect();
 
-        self.tokens.push(Token::new(r#type, text, self.current));
+        self.tokens
+            .push(Token::new(r#type, text, self.start..self.current));
     }
 
     fn add_token_with_lexeme(&mut self, r#type: TokenType, lexeme: String) {
-        self.tokens.push(Token::new(r#type, lexeme, self.current));
+        self.tokens
+            .push(Token::new(r#type, lexeme, self.start..self.current));
     }
 
     fn advance(&mut self) -> char {
@@ -56,8 +104,71 @@ impl Scanner {
         self.current >= self.source.len()
     }
 
-    fn is_part(c: char) -> bool {
-        c.is_alphanumeric() || vec!['=', '\'', '"', '.', '/', '-'].contains(&c)
+    /// Returns whether `c` ends a word outside of quotes, being whitespace or an operator.
+    fn is_operator(c: char) -> bool {
+        c.is_whitespace() || ['&', '|', ';', '<', '>', '(', ')'].contains(&c)
+    }
+
+    /// Returns whether the character just scanned is a word of its own, like a `{` or a `$`.
+    fn is_alone(&self) -> bool {
+        self.is_at_end() || Self::is_operator(self.peek())
+    }
+
+    /// Pairs the brackets of `source` outside of quotes and comments, for the editor to highlight
+    /// problems before the line is run.
+    #[must_use]
+    pub(crate) fn brackets(source: &[char]) -> Brackets {
+        let mut brackets = Brackets::default();
+        let mut open = Vec::new();
+        let mut quote: Option<(QuoteType, usize)> = None;
+
+        let mut escaped = false;
+        let mut comment = false;
+
+        for (index, &c) in source.iter().enumerate() {
+            if std::mem::take(&mut escaped) {
+                continue;
+            }
+
+            if comment {
+                comment = c != '\n';
+                continue;
+            }
+
+            if let Some((quote_type, _)) = &quote {
+                let quote_char = char::from(quote_type.clone());
+                if c == quote_char {
+                    quote = None;
+                } else if c == '\\' && quote_char == '"' {
+                    escaped = true;
+                }
+                continue;
+            }
+
+            match c {
+                '\\' => escaped = true,
+                '\'' | '"' => quote = Some((c.into(), index)),
+                '#' if index == 0 || Self::is_operator(source[index - 1]) => comment = true,
+                '{' | '(' => open.push(index),
+                '}' | ')' => {
+                    let opening = if c == '}' { '{' } else { '(' };
+                    match open.last() {
+                        Some(&start) if source[start] == opening => {
+                            open.pop();
+                            brackets.pairs.push((start, index));
+                        }
+                        _ => brackets.unmatched.push(index),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        brackets.unmatched.extend(open);
+        brackets.unmatched.extend(quote.map(|(_, index)| index));
+        brackets.unmatched.sort_unstable();
+
+        brackets
     }
 
     #[must_use]
@@ -67,95 +178,688 @@ impl Scanner {
             current: 0,
             tokens: Vec::new(),
             source: source.chars().collect::<Vec<_>>(),
+            here_docs: 0,
+            errors: Vec::new(),
+            aliases: Vec::new(),
+            incomplete: false,
+            context: Context::Commands,
         }
     }
 
-    fn part(&mut self, quote_type: QuoteType) {
-        if let QuoteType::Any = quote_type {
-            let mut quote_type: Option<QuoteType> = None;
+    /// Scans a word starting with the character just advanced over, without its quotes. The word
+    /// runs up to whitespace or an operator like `|` or `;` outside of quotes.
+    ///
+    /// Single quotes keep everything in them as it is. Outside of them, parameters like `$NAME`
+    /// and the commands of `$(COMMAND)` and `` `COMMAND` `` are left to be expanded when the
+    /// command runs, which also splits them into fields outside of double quotes. A leading `~`
+    /// is expanded to a home directory. In the position of a command, an unquoted reserved word
+    /// like `if` is a token of its own, and an unquoted word naming an alias is replaced by the
+    /// words of its value.
+    async fn word(&mut self) {
+        self.current = self.start;
+        let (mut pieces, mut word) = self.pieces(false).await;
 
-            let mut inside_quotes = false;
-            let mut c = self.peek();
+        // a `${` that isn't a parameter, reported by the parser
+        if self.current == self.start {
+            let close = self.closing_brace();
+            self.incomplete |= close.is_none();
+            self.current = close.map_or(self.source.len(), |close| close + 1);
+            self.add_token(TokenType::DollarSign);
+            return;
+        }
 
-            while Self::is_part(c) || (inside_quotes && c == ' ') {
-                self.advance();
-                c = self.peek();
+        let source = &self.source[self.start..self.current];
+        let quoted = !pieces.is_empty() || source.iter().copied().ne(word.chars());
+
+        // a leading `~` is a home directory unless the user name is quoted
+        let user_name = source.split(|&c| c == '/').next().unwrap_or_default();
+        let first = match pieces.first_mut() {
+            Some(Piece::Text(text)) => text,
+            _ => &mut word,
+        };
+        if first.starts_with('~') && !user_name.iter().any(|c| ['\'', '"', '\\'].contains(c)) {
+            let home = user::expand_tilde(first).to_string_lossy().into_owned();
+            log!(Debug, "expansion", "{first} -> {home}");
+            *first = home;
+        }
 
-                if let Some(quote_type) = quote_type.clone() {
-                    inside_quotes = if c == quote_type.clone().into() || inside_quotes {
-                        true
+        // `NAME=VALUE` sets a variable instead of being the keyword
+        let assignment = self.is_command_position()
+            && source.iter().position(|&c| c == '=').is_some_and(|equals| {
+                variables::is_name(&source[..equals].iter().collect::<String>())
+            });
+
+        if !pieces.is_empty() || assignment {
+            pieces.push(Piece::Text(word));
+            let word = pieces.into_iter().collect::<Word>();
+            let r#type = if assignment {
+                TokenType::Assignment
+            } else {
+                TokenType::Part
+            };
+
+            self.add_token_with_lexeme(r#type, word.text());
+            if let Some(token) = self.tokens.last_mut() {
+                token.word = Some(word);
+            }
+            return;
+        }
+
+        if let Some(reserved) = self.reserved_word(&word).filter(|_| !quoted) {
+            match reserved {
+                TokenType::In => self.context = Context::Patterns,
+                TokenType::LeftBrackets => self.context = Context::Test,
+                TokenType::Esac | TokenType::RightBrackets => self.context = Context::Commands,
+                _ => {}
+            }
+            self.add_token_with_lexeme(reserved, word);
+            return;
+        }
+
+        if !quoted && self.is_command_position() {
+            if let Some(tokens) = self.alias(&word).await {
+                self.tokens.extend(tokens);
+                return;
+            }
+        }
+
+        self.add_token_with_lexeme(TokenType::Part, word);
+    }
+
+    /// Scans the pieces of a word, up to whitespace or an operator outside of quotes, or up to
+    /// the end of the source if `whole` is set. Returns them with the text after the last
+    /// parameter, which is all of it without parameters.
+    #[async_recursion]
+    async fn pieces(&mut self, whole: bool) -> (Vec<Piece>, String) {
+        let mut word = String::new();
+        // the text and parameters before `word`, once a parameter was found
+        let mut pieces = Vec::new();
+
+        while !self.is_at_end() {
+            match self.peek() {
+                quote @ ('\'' | '"') => {
+                    let open = self.current;
+                    self.advance();
+
+                    while !self.is_at_end() && self.peek() != quote {
+                        let c = self.advance();
+
+                        if quote == '"'
+                            && c == '\\'
+                            && ['$', '`', '"', '\\', '\n'].contains(&self.peek())
+                        {
+                            // an escaped newline joins the lines
+                            let escaped = self.advance();
+                            if escaped != '\n' {
+                                word.push(escaped);
+                            }
+                        } else if quote == '"' && c == '`' {
+                            let substitution = self.substitute('`').await;
+                            pieces.push(Piece::Text(std::mem::take(&mut word)));
+                            pieces.push(substitution);
+                        } else if quote == '"' && c == '$' && self.r#match('(') {
+                            let substitution = self.substitute(')').await;
+                            pieces.push(Piece::Text(std::mem::take(&mut word)));
+                            pieces.push(substitution);
+                        } else if quote == '"' && c == '$' {
+                            if let Some(parameter) = self.parameter(true).await {
+                                pieces.push(Piece::Text(std::mem::take(&mut word)));
+                                pieces.push(parameter);
+                            } else {
+                                word.push(c);
+                            }
+                        } else {
+                            word.push(c);
+                        }
+                    }
+                    // an unclosed quote runs to the end of the line
+                    if !self.r#match(quote) {
+                        self.unterminated(open..open + 1);
+                    }
+                }
+                '\\' => {
+                    self.advance();
+
+                    // an escaped newline joins the lines, and a backslash at the end stays
+                    if self.is_at_end() {
+                        self.incomplete = true;
+                        word.push('\\');
+                    } else if self.peek() == '\n' {
+                        self.advance();
                     } else {
-                        c == quote_type.into() && !inside_quotes
+                        word.push(self.advance());
                     }
-                } else {
-                    inside_quotes = if vec!['\'', '"'].contains(&c) || inside_quotes {
-                        quote_type = Some(c.into());
-                        true
+                }
+                '`' => {
+                    self.advance();
+                    let substitution = self.substitute('`').await;
+                    pieces.push(Piece::Text(std::mem::take(&mut word)));
+                    pieces.push(Piece::Split(Box::new(substitution)));
+                }
+                '$' => {
+                    self.advance();
+
+                    if self.r#match('(') {
+                        let substitution = self.substitute(')').await;
+                        pieces.push(Piece::Text(std::mem::take(&mut word)));
+                        pieces.push(Piece::Split(Box::new(substitution)));
+                    } else if self.r#match('\'') {
+                        word.push_str(&self.ansi_c());
+                    } else if let Some(parameter) = self.parameter(false).await {
+                        pieces.push(Piece::Text(std::mem::take(&mut word)));
+                        pieces.push(Piece::Split(Box::new(parameter)));
+                    } else if !whole && self.peek() == '{' {
+                        // a bad substitution
+                        self.current -= 1;
+                        break;
                     } else {
-                        vec!['\'', '"'].contains(&c) && !inside_quotes
+                        word.push('$');
                     }
                 }
+                c if !whole && Self::is_operator(c) => break,
+                c => {
+                    self.advance();
+                    word.push(c);
+                }
             }
-        } else {
-            let quote_type: char = char::from(quote_type);
+        }
+
+        (pieces, word)
+    }
 
-            let mut inside_quotes = false;
-            let mut c = self.peek();
+    /// Scans a parameter past its `$`, like `$NAME`, `${NAME}`, `$?`, `$1` or `${NAME:-WORD}`,
+    /// or returns `None` if the `$` is just a dollar sign or starts a bad substitution. Inside
+    /// double quotes, `quoted` is set and `$*` is a single word.
+    async fn parameter(&mut self, quoted: bool) -> Option<Piece> {
+        match self.peek() {
+            '{' => {
+                let close = self.closing_brace()?;
+                let inner = self.source[self.current + 1..close].to_vec();
+                let piece = self.braced(&inner, close, quoted).await?;
 
-            while Self::is_part(c) || (inside_quotes && c == ' ') {
+                self.current = close + 1;
+                Some(piece)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = self.current;
+                while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
+                    self.advance();
+                }
+                Some(Piece::Variable(
+                    self.source[start..self.current].iter().collect(),
+                ))
+            }
+            // only one digit, `$10` being `$1` followed by `0`
+            c => {
+                let piece = Self::special(&[c], quoted)?;
                 self.advance();
-                c = self.peek();
+                Some(piece)
+            }
+        }
+    }
 
-                inside_quotes = if c == quote_type || inside_quotes {
-                    true
-                } else {
-                    c == quote_type && !inside_quotes
-                };
+    /// Returns the piece of the parameter `name` without an operator, like `NAME`, `?` or `10`,
+    /// or `None` if it isn't one.
+    fn special(name: &[char], quoted: bool) -> Option<Piece> {
+        match name {
+            ['?'] => Some(Piece::Status),
+            ['@'] => Some(Piece::Arguments),
+            ['*'] if !quoted => Some(Piece::Arguments),
+            ['*' | '#' | '$' | '!'] => Some(Piece::Variable(name.iter().collect())),
+            [first, ..]
+                if first.is_ascii_alphabetic()
+                    || *first == '_'
+                    || name.iter().all(char::is_ascii_digit) =>
+            {
+                name.iter()
+                    .all(|c| c.is_ascii_alphanumeric() || *c == '_')
+                    .then(|| Piece::Variable(name.iter().collect()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses `inner`, what is between the braces of `${...}` up to the `}` at `close`, scanning
+    /// the words of its operator, or returns `None` if it is a bad substitution.
+    async fn braced(&mut self, inner: &[char], close: usize, quoted: bool) -> Option<Piece> {
+        if let Some(piece) = Self::special(inner, quoted) {
+            return Some(piece);
+        }
+
+        let source = inner.iter().collect::<String>();
+        let parameter = |name: &[char], operator| {
+            Some(Piece::Parameter(Box::new(Parameter {
+                name: name.iter().collect(),
+                operator,
+                source: source.clone(),
+            })))
+        };
+
+        match inner {
+            ['#', name @ ..] if Self::special(name, true).is_some() => {
+                return parameter(name, Operator::Length);
+            }
+            ['!', prefix @ .., '*' | '@']
+                if variables::is_name(&prefix.iter().collect::<String>()) =>
+            {
+                return parameter(prefix, Operator::Names);
             }
+            ['!', name @ ..] if Self::special(name, true).is_some() => {
+                return parameter(name, Operator::Indirect);
+            }
+            _ => {}
         }
 
-        // let alias_lock = ALIASES.lock().await;
+        let length = match inner.first()? {
+            c if c.is_ascii_alphabetic() || *c == '_' => inner
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                .count(),
+            c if c.is_ascii_digit() => inner.iter().take_while(|c| c.is_ascii_digit()).count(),
+            '?' | '#' | '@' | '*' | '$' | '!' => 1,
+            _ => return None,
+        };
+        let (name, rest) = inner.split_at(length);
+        let (colon, rest) = match rest {
+            [':', rest @ ..] => (true, rest),
+            _ => (false, rest),
+        };
 
-        // if let Some(value) = alias_lock.get(
-        //     self.source[start..self.current]
-        //         .iter()
-        //         .collect::<String>()
-        //         .as_str(),
-        // ) {
-        //     // handle multiple args
-        //     for value in value.split(' ') {
-        //         self.add_token_with_lexeme(TokenType::Part, value.to_string());
-        //     }
-        //     return;
-        // }
+        let operator = match (colon, rest) {
+            (_, ['-', word @ ..]) => Operator::Default {
+                colon,
+                word: self.operand(word, close - word.len()).await,
+            },
+            (_, ['=', word @ ..]) => Operator::Assign {
+                colon,
+                word: self.operand(word, close - word.len()).await,
+            },
+            (_, ['+', word @ ..]) => Operator::Alternative {
+                colon,
+                word: self.operand(word, close - word.len()).await,
+            },
+            (_, ['?', word @ ..]) => Operator::Error {
+                colon,
+                word: self.operand(word, close - word.len()).await,
+            },
+            (false, ['#', '#', pattern @ ..]) => Operator::RemovePrefix {
+                longest: true,
+                pattern: self.operand(pattern, close - pattern.len()).await,
+            },
+            (false, ['#', pattern @ ..]) => Operator::RemovePrefix {
+                longest: false,
+                pattern: self.operand(pattern, close - pattern.len()).await,
+            },
+            (false, ['%', '%', pattern @ ..]) => Operator::RemoveSuffix {
+                longest: true,
+                pattern: self.operand(pattern, close - pattern.len()).await,
+            },
+            (false, ['%', pattern @ ..]) => Operator::RemoveSuffix {
+                longest: false,
+                pattern: self.operand(pattern, close - pattern.len()).await,
+            },
+            (false, ['[', index @ .., ']'])
+                if variables::is_name(&name.iter().collect::<String>()) =>
+            {
+                Operator::Index(match index {
+                    ['@' | '*'] => None,
+                    index => Some(index.iter().collect::<String>().parse().ok()?),
+                })
+            }
+            (false, ['/', rest @ ..]) => {
+                let (all, rest) = match rest {
+                    ['/', rest @ ..] => (true, rest),
+                    _ => (false, rest),
+                };
+                let (pattern, replacement) = match rest.iter().position(|&c| c == '/') {
+                    Some(slash) => (&rest[..slash], &rest[slash + 1..]),
+                    None => (rest, &[][..]),
+                };
 
-        self.add_token(TokenType::Part);
+                Operator::Replace {
+                    all,
+                    pattern: self.operand(pattern, close - rest.len()).await,
+                    replacement: self.operand(replacement, close - replacement.len()).await,
+                }
+            }
+            _ => return None,
+        };
+
+        parameter(name, operator)
     }
 
-    async fn part_return_lexeme(&mut self, start: usize) -> String {
-        let mut inside_quotes = false;
-        let mut c = self.peek();
+    /// Scans `source`, the word of an operator in `${...}` starting at `start`, as a whole word.
+    async fn operand(&mut self, source: &[char], start: usize) -> Word {
+        let mut scanner = Self {
+            source: source.to_vec(),
+            aliases: self.aliases.clone(),
+            ..Self::new("")
+        };
 
-        while Self::is_part(c) || (inside_quotes && c == ' ') {
-            self.advance();
-            c = self.peek();
+        let (mut pieces, word) = scanner.pieces(true).await;
+        self.errors
+            .extend(scanner.errors.into_iter().map(|error| error.shifted(start)));
+        pieces.push(Piece::Text(word));
+        pieces.into_iter().collect()
+    }
 
-            inside_quotes = if vec!['\'', '"'].contains(&c) || inside_quotes {
-                true
-            } else {
-                vec!['\'', '"'].contains(&c) && !inside_quotes
+    /// Scans a `$'...'` string past its opening quote, returning its text with the escape
+    /// sequences like `\n`, `\t`, `\e`, `\x41` or `\u00e9` replaced by what they stand for.
+    fn ansi_c(&mut self) -> String {
+        let open = self.current;
+        let mut string = String::new();
+
+        while !self.is_at_end() && self.peek() != '\'' {
+            let c = self.advance();
+            if c != '\\' || self.is_at_end() {
+                string.push(c);
+                continue;
+            }
+
+            let escape = self.advance();
+            let unescaped = match escape {
+                'a' => Some('\x07'),
+                'b' => Some('\x08'),
+                'e' | 'E' => Some('\x1b'),
+                'f' => Some('\x0c'),
+                'n' => Some('\n'),
+                'r' => Some('\r'),
+                't' => Some('\t'),
+                'v' => Some('\x0b'),
+                '\\' | '\'' | '"' | '?' => Some(escape),
+                '0'..='7' => {
+                    self.current -= 1;
+                    self.code(8, 3)
+                }
+                'x' => self.code(16, 2),
+                'u' => self.code(16, 4),
+                'U' => self.code(16, 8),
+                'c' if !self.is_at_end() => char::from_u32(u32::from(self.advance()) & 0x1f),
+                _ => None,
             };
+
+            if let Some(c) = unescaped {
+                string.push(c);
+            } else {
+                string.push('\\');
+                string.push(escape);
+            }
+        }
+        // an unclosed quote runs to the end of the line
+        if !self.r#match('\'') {
+            self.unterminated(open - 2..open);
         }
 
-        let text: String = self.source[start..self.current].iter().collect();
+        string
+    }
 
-        let alias_lock = ALIASES.lock().await;
+    /// Scans up to `length` digits in `radix` and returns the character with that code, or
+    /// `None` if there are no digits or no such character.
+    fn code(&mut self, radix: u32, length: usize) -> Option<char> {
+        let start = self.current;
+        while self.current - start < length && self.peek().is_digit(radix) {
+            self.advance();
+        }
 
-        if let Some(value) = alias_lock.get(text.as_str()) {
-            value.to_string()
-        } else {
-            text
+        let digits = self.source[start..self.current].iter().collect::<String>();
+        u32::from_str_radix(&digits, radix)
+            .ok()
+            .and_then(char::from_u32)
+    }
+
+    /// Returns the index of the `}` closing the `{` at the current character, skipping nested
+    /// braces and quotes.
+    fn closing_brace(&self) -> Option<usize> {
+        let mut depth = 0;
+        let mut quote = None;
+
+        let mut escaped = false;
+
+        for (index, &c) in self.source.iter().enumerate().skip(self.current) {
+            if std::mem::take(&mut escaped) {
+                continue;
+            }
+
+            match quote {
+                Some(open) if c == open => quote = None,
+                Some('"') if c == '\\' => escaped = true,
+                Some(_) => {}
+                None => match c {
+                    '\\' => escaped = true,
+                    '\'' | '"' => quote = Some(c),
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(index);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        None
+    }
+
+    /// Returns whether the next word is the name of a command, starting a pipeline or a list.
+    fn is_command_position(&self) -> bool {
+        self.context == Context::Commands
+            && matches!(
+                self.tokens.last().map(|token| &token.r#type),
+                None | Some(
+                    TokenType::AndAnd
+                        | TokenType::And
+                        | TokenType::Assignment
+                        | TokenType::LeftParen
+                        | TokenType::LeftBrace
+                        | TokenType::OrOr
+                        | TokenType::Pipe
+                        | TokenType::Semicolon
+                        | TokenType::If
+                        | TokenType::Then
+                        | TokenType::Elif
+                        | TokenType::Else
+                        | TokenType::PatternsEnd
+                        | TokenType::Bang
+                )
+            )
+    }
+
+    /// Returns the reserved word, like `if`, that the unquoted `word` is in the position of a
+    /// command, where it is recognized before aliases. `in` is one after `case WORD`, only
+    /// `esac` is one among the patterns of a `case` arm, and only `]]` in a `[[ ]]`.
+    fn reserved_word(&self, word: &str) -> Option<TokenType> {
+        match self.context {
+            Context::Patterns => return (word == "esac").then_some(TokenType::Esac),
+            Context::Test => return (word == "]]").then_some(TokenType::RightBrackets),
+            Context::Commands => {}
+        }
+
+        let mut previous = self.tokens.iter().rev().map(|token| &token.r#type);
+        let previous_is_assignment = self
+            .tokens
+            .last()
+            .is_some_and(|token| token.r#type == TokenType::Assignment);
+        if word == "in"
+            && previous.next() == Some(&TokenType::Part)
+            && previous.next() == Some(&TokenType::Case)
+        {
+            return Some(TokenType::In);
+        }
+
+        // after `NAME=VALUE` the word is the name of a simple command, like `FOO=1 if`
+        if !self.is_command_position() || previous_is_assignment {
+            return None;
         }
+
+        match word {
+            "if" => Some(TokenType::If),
+            "then" => Some(TokenType::Then),
+            "elif" => Some(TokenType::Elif),
+            "else" => Some(TokenType::Else),
+            "fi" => Some(TokenType::Fi),
+            "case" => Some(TokenType::Case),
+            "esac" => Some(TokenType::Esac),
+            "[[" => Some(TokenType::LeftBrackets),
+            "!" => Some(TokenType::Bang),
+            _ => None,
+        }
+    }
+
+    /// Returns the tokens of the value of the alias `name`, scanned as if it was typed instead of
+    /// the name, or `None` if there is no such alias or it is already being expanded.
+    #[async_recursion]
+    async fn alias(&self, name: &str) -> Option<Vec<Token>> {
+        if self.aliases.iter().any(|alias| alias == name) {
+            return None;
+        }
+        let value = ALIASES.lock().await.get(name)?.clone();
+        log!(Debug, "expansion", "alias {name} -> {value}");
+
+        let mut aliases = self.aliases.clone();
+        aliases.push(name.to_string());
+        let mut scanner = Self {
+            aliases,
+            ..Self::new(&value)
+        };
+
+        let mut tokens = scanner.scan_tokens().await;
+        // EOF
+        tokens.pop();
+        for token in &mut tokens {
+            token.span = self.start..self.current;
+        }
+
+        Some(tokens)
+    }
+
+    /// Scans the command of a substitution up to `close`, past the opening `$(` or `` ` ``, and
+    /// returns it parsed, to be run when the word is expanded. Its syntax errors are kept for
+    /// [`Scanner::errors`].
+    async fn substitute(&mut self, close: char) -> Piece {
+        let start = self.current;
+        let mut end = self.source.len();
+        let mut depth = 0;
+        let mut quote = None;
+
+        while !self.is_at_end() {
+            let c = self.advance();
+
+            match quote {
+                Some(open) if c == open => quote = None,
+                None if c == close && depth == 0 => {
+                    end = self.current - 1;
+                    break;
+                }
+                // backticks don't nest
+                None if close == ')' => match c {
+                    '\'' | '"' => quote = Some(c),
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        if end == self.source.len() {
+            let open = if close == ')' { start - 2 } else { start - 1 };
+            self.unterminated(open..start);
+        }
+
+        let source = self.source[start..end].iter().collect::<String>();
+        let mut scanner = Self {
+            aliases: self.aliases.clone(),
+            ..Self::new(&source)
+        };
+        let tokens = scanner.scan_tokens().await;
+
+        let errors = match Parser::new(tokens).parse_tokens() {
+            Ok(Some(list)) => {
+                self.errors
+                    .extend(scanner.errors.into_iter().map(|error| error.shifted(start)));
+                return Piece::Substitution(list);
+            }
+            Ok(None) => scanner.errors,
+            Err(error) => scanner.errors.into_iter().chain([error]).collect(),
+        };
+        self.errors
+            .extend(errors.into_iter().map(|error| error.shifted(start)));
+
+        // nothing to run
+        Piece::Text(String::new())
+    }
+
+    /// Scans the rest of a redirection operator starting with `operator`, like `>>`, `<&` or
+    /// `<<-`.
+    fn redirect(&mut self, operator: char) {
+        if operator == '>' {
+            self.r#match('>');
+        } else if self.r#match('<') {
+            self.r#match('-');
+            self.add_token(TokenType::Redirect);
+            return;
+        }
+        self.r#match('&');
+
+        self.add_token(TokenType::Redirect);
+    }
+
+    /// Reads the bodies of the here-documents started on the line that just ended, each up to
+    /// the line with just its delimiter, and adds them after their delimiters.
+    ///
+    /// A here-document without its delimiter line gets no body, which the parser reports.
+    fn here_doc_bodies(&mut self) {
+        let starts = (self.here_docs..self.tokens.len().saturating_sub(1))
+            .filter(|&index| {
+                self.tokens[index].r#type == TokenType::Redirect
+                    && self.tokens[index].lexeme.contains("<<")
+                    && self.tokens[index + 1].r#type == TokenType::Part
+            })
+            .collect::<Vec<_>>();
+        self.here_docs = self.tokens.len();
+
+        let mut bodies = Vec::new();
+        for index in starts {
+            let strip_tabs = self.tokens[index].lexeme.ends_with('-');
+            let delimiter = self.tokens[index + 1].lexeme.clone();
+
+            let mut body = String::new();
+            loop {
+                if self.is_at_end() {
+                    return;
+                }
+
+                let end = self.source[self.current..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map_or(self.source.len(), |length| self.current + length);
+                let mut line = &self.source[self.current..end];
+                self.current = (end + 1).min(self.source.len());
+
+                if strip_tabs {
+                    let tabs = line.iter().take_while(|&&c| c == '\t').count();
+                    line = &line[tabs..];
+                }
+                if line.iter().copied().eq(delimiter.chars()) {
+                    break;
+                }
+
+                body.extend(line);
+                body.push('\n');
+            }
+
+            bodies.push((index + 1, body));
+        }
+
+        // later bodies first, so that the indexes of earlier delimiters still hold
+        for (index, body) in bodies.into_iter().rev() {
+            let span = self.tokens[index].span.clone();
+            self.tokens
+                .insert(index + 1, Token::new(TokenType::HereDoc, body, span));
+        }
+        self.here_docs = self.tokens.len();
     }
 
     fn peek(&self) -> char {
@@ -176,6 +880,10 @@ impl Scanner {
     }
 
     async fn scan_token(&mut self) {
+        if self.context == Context::Test {
+            return self.scan_test_token().await;
+        }
+
         match self.advance() {
             '&' => {
                 if self.r#match('&') {
@@ -191,42 +899,105 @@ impl Scanner {
                     self.add_token(TokenType::Pipe);
                 }
             }
-            '$' => {
-                if self.r#match('?') {
-                    let previous_exit_code = *PREVIOUS_EXIT_CODE.lock().await;
-                    self.add_token_with_lexeme(TokenType::Part, previous_exit_code.to_string());
-                    return;
+            '{' if self.is_alone() => self.add_token(TokenType::LeftBrace),
+            '}' if self.is_alone() => self.add_token(TokenType::RightBrace),
+            '(' => self.add_token(TokenType::LeftParen),
+            ')' if self.context == Context::Patterns => {
+                self.context = Context::Commands;
+                self.add_token(TokenType::PatternsEnd);
+            }
+            ')' => self.add_token(TokenType::RightParen),
+            '\n' => {
+                // a newline ends a command like `;`, unless it continues after an operator or a
+                // reserved word like `then`
+                let ends_command = self.tokens.last().is_some_and(|token| {
+                    ![
+                        TokenType::And,
+                        TokenType::AndAnd,
+                        TokenType::OrOr,
+                        TokenType::Pipe,
+                        TokenType::Semicolon,
+                        TokenType::LeftParen,
+                        TokenType::LeftBrace,
+                        TokenType::If,
+                        TokenType::Then,
+                        TokenType::Elif,
+                        TokenType::Else,
+                        TokenType::In,
+                        TokenType::PatternsEnd,
+                        TokenType::DoubleSemicolon,
+                    ]
+                    .contains(&token.r#type)
+                });
+                if ends_command {
+                    self.add_token(TokenType::Semicolon);
                 }
-                self.add_token(TokenType::DollarSign);
+                self.here_doc_bodies();
             }
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
-            ' ' | '\n' | '\t' | '\r' => {}
-            ':' => {
-                if self.r#match('-') {
-                    self.add_token(TokenType::ColonDash);
+            ' ' | '\t' | '\r' => {}
+            // a `#` starting a word comments out the rest of the line, while `a#b` and `$#` are
+            // words
+            '#' => {
+                while !self.is_at_end() && self.peek() != '\n' {
+                    self.advance();
                 }
             }
-            '~' => {
-                let text = format!(
-                    "{}{}",
-                    std::env::var("HOME").unwrap_or_default(),
-                    if Self::is_part(self.advance()) {
-                        self.part_return_lexeme(self.start + 1).await
-                    } else {
-                        String::new()
-                    }
-                );
-
-                self.add_token_with_lexeme(TokenType::Part, text);
+            ';' if self.r#match(';') => {
+                self.context = Context::Patterns;
+                self.add_token(TokenType::DoubleSemicolon);
+            }
+            ';' => self.add_token(TokenType::Semicolon),
+            '<' => self.redirect('<'),
+            '>' => self.redirect('>'),
+            c if c.is_ascii_digit() && ['<', '>'].contains(&self.peek()) => {
+                let operator = self.advance();
+                self.redirect(operator);
             }
+            _ => self.word().await,
+        }
+    }
+
+    /// Scans a token of the expression of a `[[ ]]`, where operators like `&&` and `<` are words
+    /// for the test to look at.
+    async fn scan_test_token(&mut self) {
+        match self.advance() {
+            '&' if self.r#match('&') => self.add_token(TokenType::Part),
+            '|' if self.r#match('|') => self.add_token(TokenType::Part),
+            '(' | ')' | '<' | '>' => self.add_token(TokenType::Part),
+            ' ' | '\t' | '\r' | '\n' => {}
+            // anything else ends the test, for the parser to report
+            '&' => self.add_token(TokenType::And),
+            '|' => self.add_token(TokenType::Pipe),
             ';' => self.add_token(TokenType::Semicolon),
-            '\'' => self.part(QuoteType::Single),
-            '"' => self.part(QuoteType::Double),
-            _ => self.part(QuoteType::Any),
+            _ => self.word().await,
         }
     }
 
+    /// Returns whether the source scanned goes on on the next line, as it ended inside a quote,
+    /// a substitution or `${`, or after a `\\`.
+    pub(crate) fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    /// Marks the source as going on on the next line, as the quote or substitution opened at
+    /// `span` isn't closed, which is an error if it doesn't.
+    fn unterminated(&mut self, span: Range<usize>) {
+        self.incomplete = true;
+
+        let lexeme = self.source[span.clone()].iter().collect();
+        self.errors
+            .push(Error::new(ErrorKind::Unterminated(Token::new(
+                TokenType::Part,
+                lexeme,
+                span,
+            ))));
+    }
+
+    /// Returns the syntax errors of the commands of the substitutions scanned.
+    pub(crate) fn errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.errors)
+    }
+
     pub(crate) async fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
@@ -237,9 +1008,200 @@ impl Scanner {
         self.tokens.push(Token::new(
             TokenType::default(),
             String::new(),
-            self.current,
+            self.current..self.current,
         ));
 
         self.tokens.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn scan(source: &str) -> Vec<Token> {
+        Scanner::new(source).scan_tokens().await
+    }
+
+    async fn types(source: &str) -> Vec<TokenType> {
+        scan(source)
+            .await
+            .into_iter()
+            .map(|token| token.r#type)
+            .collect()
+    }
+
+    async fn lexemes(source: &str) -> Vec<String> {
+        scan(source)
+            .await
+            .into_iter()
+            .map(|token| token.lexeme)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn redirections() {
+        assert_eq!(
+            lexemes("c 2>&1 3>>f").await,
+            ["c", "2>&", "1", "3>>", "f", ""]
+        );
+        assert_eq!(types("c 2>&1").await[1], TokenType::Redirect);
+    }
+
+    #[test]
+    fn brackets() {
+        let source = "echo ${a} (b \"(\"".chars().collect::<Vec<_>>();
+        let brackets = Scanner::brackets(&source);
+
+        assert_eq!(brackets.pairs, [(6, 8)]);
+        assert_eq!(brackets.unmatched, [10]);
+
+        let source = "echo 'a".chars().collect::<Vec<_>>();
+        assert_eq!(Scanner::brackets(&source).unmatched, [5]);
+    }
+
+    #[tokio::test]
+    async fn pipes() {
+        use TokenType::{Eof, OrOr, Part, Pipe};
+
+        assert_eq!(
+            types("a | b || c").await,
+            [Part, Pipe, Part, OrOr, Part, Eof]
+        );
+    }
+
+    #[tokio::test]
+    async fn operators() {
+        use TokenType::{And, AndAnd, Eof, Part, Pipe, Semicolon};
+
+        assert_eq!(
+            types("echo a && b | c; d &").await,
+            [Part, Part, AndAnd, Part, Pipe, Part, Semicolon, Part, And, Eof]
+        );
+    }
+
+    #[tokio::test]
+    async fn words_end_at_unquoted_operators() {
+        use TokenType::{Eof, Part, Pipe, Semicolon};
+
+        assert_eq!(
+            types("a;b|c 'd;e'").await,
+            [Part, Semicolon, Part, Pipe, Part, Part, Eof]
+        );
+        assert_eq!(lexemes(r#"echo "a b"'c'd"#).await[1], "a bcd");
+    }
+
+    #[tokio::test]
+    async fn parameters_are_pieces() {
+        let tokens = scan(r#"echo x$A"${B}y"'$C'"#).await;
+        let word = tokens[1].word.clone().unwrap_or_default();
+
+        let mut expected = Word::default();
+        expected.push(Piece::Text(String::from("x")));
+        expected.push(Piece::Split(Box::new(Piece::Variable(String::from("A")))));
+        expected.push(Piece::Variable(String::from("B")));
+        expected.push(Piece::Text(String::from("y$C")));
+        assert_eq!(word, expected);
+    }
+
+    #[tokio::test]
+    async fn spans() {
+        let spans = scan("ab  'c d'>f")
+            .await
+            .into_iter()
+            .map(|token| token.span)
+            .collect::<Vec<_>>();
+
+        assert_eq!(spans, [0..2, 4..9, 9..10, 10..11, 11..11]);
+    }
+
+    #[tokio::test]
+    async fn escapes() {
+        assert_eq!(
+            lexemes(r"echo a\ b\;c \'d").await,
+            ["echo", "a b;c", "'d", ""]
+        );
+        assert_eq!(lexemes(r#"echo "\$a \"b\" \c""#).await[1], r#"$a "b" \c"#);
+        assert_eq!(lexemes(r"echo '\n'").await[1], r"\n");
+    }
+
+    #[tokio::test]
+    async fn ansi_c_strings() {
+        assert_eq!(lexemes(r"echo $'a\tb\n'").await[1], "a\tb\n");
+        assert_eq!(lexemes(r"echo $'\x41\101é\''").await[1], "AAé'");
+        assert_eq!(lexemes(r"echo x$'\\'y").await[1], r"x\y");
+    }
+
+    #[tokio::test]
+    async fn unterminated_input_is_incomplete() {
+        for source in [
+            "echo \"a", "echo 'a", "echo $(a", "echo `a", "echo $'a", "echo ${a", "a \\",
+        ] {
+            let mut scanner = Scanner::new(source);
+            scanner.scan_tokens().await;
+
+            assert!(scanner.is_incomplete(), "{source}");
+        }
+
+        let mut scanner = Scanner::new("echo 'a' \"b\" $(c) \\\n");
+        scanner.scan_tokens().await;
+        assert!(!scanner.is_incomplete());
+    }
+
+    #[tokio::test]
+    async fn escaped_newlines_join_lines() {
+        assert_eq!(lexemes("echo a\\\nb").await, ["echo", "ab", ""]);
+    }
+
+    #[tokio::test]
+    async fn comments() {
+        assert_eq!(lexemes("echo a # b 'c").await, ["echo", "a", ""]);
+        assert_eq!(
+            lexemes("echo a#b '#c' \\#d").await,
+            ["echo", "a#b", "#c", "#d", ""]
+        );
+        assert_eq!(lexemes("  # only a comment").await, [""]);
+    }
+
+    #[tokio::test]
+    async fn reserved_words_only_start_commands() {
+        use TokenType::{Eof, Fi, If, Part, Semicolon, Then};
+
+        assert_eq!(
+            types("if true; then echo if; fi").await,
+            [If, Part, Semicolon, Then, Part, Part, Semicolon, Fi, Eof]
+        );
+        assert_eq!(types("'if' a").await, [Part, Part, Eof]);
+    }
+
+    #[tokio::test]
+    async fn substitutions_are_parsed() {
+        let tokens = scan("echo \"$(true)\" `false` x$(a | b)").await;
+        let word = |index: usize| tokens[index].word.clone().unwrap_or_default();
+
+        assert!(matches!(word(1).pieces(), [Piece::Substitution(_)]));
+        assert!(matches!(
+            word(2).pieces(),
+            [Piece::Split(piece)] if matches!(**piece, Piece::Substitution(_))
+        ));
+        assert!(matches!(
+            word(3).pieces(),
+            [Piece::Text(_), Piece::Split(piece)]
+                if matches!(&**piece, Piece::Substitution(crate::list::List::Pipeline(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn unterminated_quotes_are_errors() {
+        for source in ["echo \"a", "echo 'a", "echo $(a", "echo `a", "echo $'a"] {
+            let mut scanner = Scanner::new(source);
+            scanner.scan_tokens().await;
+
+            let errors = scanner.errors();
+            assert!(
+                matches!(errors[..], [ref error] if matches!(error.kind(), ErrorKind::Unterminated(_))),
+                "{source}"
+            );
+        }
+    }
+}
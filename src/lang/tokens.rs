@@ -1,41 +1,71 @@
+use std::ops::Range;
+
+use crate::word::Word;
+
 #[derive(Clone, Debug)]
 pub struct Token {
-    pub location: usize,
+    /// The characters of the source the token was scanned from.
+    pub span: Range<usize>,
     pub r#type: TokenType,
     pub lexeme: String,
+    /// The pieces of a word with parameters to expand, whose text is the lexeme.
+    pub word: Option<Word>,
 }
 
 impl Token {
     #[must_use]
-    pub fn new(r#type: TokenType, lexeme: String, location: usize) -> Self {
+    pub fn new(r#type: TokenType, lexeme: String, span: Range<usize>) -> Self {
         Self {
-            location,
+            span,
             r#type,
             lexeme,
+            word: None,
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[repr(u8)]
 pub enum TokenType {
     AndAnd,
     And,
     Part,
+    /// A `NAME=VALUE` word before the keyword of a command.
+    Assignment,
+    /// A `${...}` that isn't a valid parameter.
     DollarSign,
     Pipe,
     OrOr,
+    #[default]
     Eof,
     Semicolon,
     LeftBrace,
     RightBrace,
-    ColonDash,
-}
-
-impl Default for TokenType {
-    fn default() -> Self {
-        Self::Eof
-    }
+    LeftParen,
+    RightParen,
+    /// A redirection operator with its descriptor, like `2>&`, followed by its target.
+    Redirect,
+    /// The body of a here-document, following its delimiter.
+    HereDoc,
+    /// The reserved words of a conditional, only recognized in the position of a command.
+    If,
+    Then,
+    Elif,
+    Else,
+    Fi,
+    /// The reserved words of a `case` statement, `in` only following `case WORD`.
+    Case,
+    In,
+    Esac,
+    /// The `)` ending the patterns of a `case` arm.
+    PatternsEnd,
+    /// The `;;` ending a `case` arm.
+    DoubleSemicolon,
+    /// The `!` negating a pipeline, only recognized in the position of a command.
+    Bang,
+    /// The `[[` starting a test in the position of a command, and the `]]` ending it.
+    LeftBrackets,
+    RightBrackets,
 }
 
 impl std::fmt::Display for TokenType {
@@ -44,6 +74,7 @@ impl std::fmt::Display for TokenType {
             Self::AndAnd => "'&&'",
             Self::And => "'&'",
             Self::Part => "identifier",
+            Self::Assignment => "assignment",
             Self::DollarSign => "'$'",
             Self::Pipe => "'|'",
             Self::OrOr => "'||'",
@@ -51,7 +82,22 @@ impl std::fmt::Display for TokenType {
             Self::Semicolon => "';'",
             Self::LeftBrace => "'{'",
             Self::RightBrace => "'}'",
-            Self::ColonDash => "':-'",
+            Self::LeftParen => "'('",
+            Self::RightParen | Self::PatternsEnd => "')'",
+            Self::Redirect => "redirection",
+            Self::HereDoc => "here-document",
+            Self::If => "'if'",
+            Self::Then => "'then'",
+            Self::Elif => "'elif'",
+            Self::Else => "'else'",
+            Self::Fi => "'fi'",
+            Self::Case => "'case'",
+            Self::In => "'in'",
+            Self::Esac => "'esac'",
+            Self::DoubleSemicolon => "';;'",
+            Self::Bang => "'!'",
+            Self::LeftBrackets => "'[['",
+            Self::RightBrackets => "']]'",
         })
     }
 }
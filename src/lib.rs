@@ -1,12 +1,53 @@
 #![warn(clippy::all, clippy::pedantic, clippy::style, clippy::use_self)]
 
-use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::LazyLock};
 
 use tokio::sync::Mutex;
 
+use completion::Completions;
+use guard::Guards;
+use job::Jobs;
+use options::Options;
+
+pub mod bell;
+pub mod chpwd;
 pub mod command;
+pub mod completion;
+pub mod condition;
+pub mod correct;
+pub mod dir_stack;
+pub mod direnv;
+pub mod editor;
+pub mod envdiff;
+pub mod errexit;
+pub mod git;
+pub mod glob;
+pub mod guard;
+pub mod history;
+pub mod job;
 pub mod lang;
+pub mod list;
+pub mod log;
+pub mod math;
+pub mod metrics;
+pub mod named_dirs;
+pub mod options;
+pub mod pager;
+pub mod pipeline;
+pub mod priority;
+pub mod project;
+pub mod prompt;
+pub mod record;
+pub mod redirect;
+pub mod rusage;
+pub mod snippet;
+pub mod subshell;
+pub mod substitution;
+pub mod tmux;
+pub mod trap;
+pub mod user;
+pub mod variables;
+pub mod word;
 
 pub use command::Command;
 
@@ -18,21 +59,34 @@ pub const GREEN_FG_COLOR: termion::color::Fg<termion::color::Green> =
 pub const RED_FG_COLOR: termion::color::Fg<termion::color::Red> =
     termion::color::Fg(termion::color::Red);
 
+/// Yellow foreground color.
+pub const YELLOW_FG_COLOR: termion::color::Fg<termion::color::Yellow> =
+    termion::color::Fg(termion::color::Yellow);
+
 /// Reset foreground color.
 pub const RESET_FG_COLOR: termion::color::Fg<termion::color::Reset> =
     termion::color::Fg(termion::color::Reset);
 
 pub const PROMPT_UNICODE: char = '❯';
 pub const HOURGLASS_UNICODE: char = '';
+pub const NESTING_UNICODE: char = '↳';
 pub const RSHISTORY: &str = ".rshistory";
 pub const RSHELL_RC: &str = ".rshellrc";
+pub const RSHELL_MOTD: &str = ".rshell_motd";
+pub const RSHELL_LOGOUT: &str = ".rshell_logout";
+pub const RSMETRICS: &str = ".rshell_metrics";
 pub const SIGINT_EXIT_CODE: i32 = 130;
 
-lazy_static! {
-    pub static ref ALIASES: Mutex<Aliases> = Mutex::new(Aliases::new());
-    pub static ref PREVIOUS_EXIT_CODE: Mutex<i32> = Mutex::new(0);
-}
+pub static ALIASES: LazyLock<Mutex<Aliases>> = LazyLock::new(|| Mutex::new(Aliases::new()));
+pub static COMPLETIONS: LazyLock<Mutex<Completions>> =
+    LazyLock::new(|| Mutex::new(Completions::default()));
+pub static GUARDS: LazyLock<Mutex<Guards>> = LazyLock::new(|| Mutex::new(Guards::default()));
+pub static JOBS: LazyLock<Mutex<Jobs>> = LazyLock::new(|| Mutex::new(Jobs::default()));
+pub static OPTIONS: LazyLock<Mutex<Options>> = LazyLock::new(|| Mutex::new(Options::default()));
+pub static PREVIOUS_EXIT_CODE: LazyLock<Mutex<i32>> = LazyLock::new(|| Mutex::new(0));
+pub static STATUS: LazyLock<Mutex<Status>> = LazyLock::new(|| Mutex::new(Status::default()));
 
+#[derive(Clone)]
 pub struct Aliases {
     aliases: HashMap<String, String>,
 }
@@ -43,6 +97,11 @@ impl Aliases {
         self.aliases.get(key)
     }
 
+    /// Returns the names of the defined aliases.
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.aliases.keys()
+    }
+
     fn new() -> Self {
         Self {
             aliases: HashMap::new(),
@@ -54,9 +113,60 @@ impl Aliases {
     }
 }
 
+/// What the shell is doing, as queried by the `status` builtin.
+#[derive(Default)]
+pub struct Status {
+    /// Whether commands are read from a terminal.
+    pub interactive: bool,
+    /// Whether the shell was started as a login shell.
+    pub login: bool,
+    /// The command line being run.
+    pub current_command: String,
+}
+
 #[macro_export]
 macro_rules! error {
     ($($args:tt)*) => {
-        eprintln!("rshell: {}", format_args!($($args)*))
+        $crate::errln!("rshell: {}", format_args!($($args)*))
+    };
+}
+
+/// Like `print!`, to the standard output of the command being run, see
+/// [`redirect::stdout`](crate::redirect::stdout).
+#[macro_export]
+macro_rules! out {
+    ($($args:tt)*) => {{
+        use std::io::Write as _;
+        let _ = $crate::redirect::stdout().write_all(format!($($args)*).as_bytes());
+    }};
+}
+
+/// Like `println!`, to the standard output of the command being run.
+#[macro_export]
+macro_rules! outln {
+    () => {
+        $crate::out!("\n")
+    };
+    ($($args:tt)*) => {
+        $crate::out!("{}\n", format_args!($($args)*))
+    };
+}
+
+/// Like `eprintln!`, to the standard error of the command being run.
+#[macro_export]
+macro_rules! errln {
+    ($($args:tt)*) => {{
+        use std::io::Write as _;
+        let _ = $crate::redirect::stderr()
+            .write_all(format!("{}\n", format_args!($($args)*)).as_bytes());
+    }};
+}
+
+/// Logs a record with a [`log::Level`] variant and a target naming the subsystem, like
+/// `log!(Debug, "spawn", "spawned {command} with pid {pid}")`.
+#[macro_export]
+macro_rules! log {
+    ($level:ident, $target:expr, $($args:tt)*) => {
+        $crate::log::write($crate::log::Level::$level, $target, format_args!($($args)*))
     };
 }
@@ -0,0 +1,76 @@
+//! Lists of pipelines joined by `&&`, `||`, `;` and `&`, parsed into a tree.
+//!
+//! `&&` and `||` bind tighter than `;` and `&`, and group from the left, so `a && b || c; d` is
+//! `((a && b) || c); d`.
+
+use std::fmt::Display;
+
+use async_recursion::async_recursion;
+
+use crate::{errexit, job, pipeline::Pipeline, subshell, PREVIOUS_EXIT_CODE};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum List {
+    Pipeline(Pipeline),
+    /// Runs the right side if the left one succeeded.
+    And(Box<Self>, Box<Self>),
+    /// Runs the right side if the left one failed.
+    Or(Box<Self>, Box<Self>),
+    /// Runs both sides one after the other.
+    Sequence(Box<Self>, Box<Self>),
+    /// Runs the list as a job, without waiting for it.
+    Background(Box<Self>),
+}
+
+impl List {
+    /// Runs the list, returning the exit code of the last pipeline that ran.
+    #[async_recursion]
+    pub(crate) async fn interpret(&self) -> i32 {
+        match self {
+            // a negated pipeline is tested, so it doesn't stop anything with `set -e`
+            Self::Pipeline(pipeline) if pipeline.negated => {
+                let code = i32::from(errexit::tested(pipeline.interpret()).await == 0);
+                *PREVIOUS_EXIT_CODE.lock().await = code;
+                code
+            }
+            Self::Pipeline(pipeline) => {
+                let code = pipeline.interpret().await;
+                *PREVIOUS_EXIT_CODE.lock().await = code;
+                errexit::check(code).await;
+                code
+            }
+            // only the last pipeline of `&&` and `||` lists stops them with `set -e`
+            Self::And(left, right) => match errexit::tested(left.interpret()).await {
+                0 if !subshell::is_exiting() => right.interpret().await,
+                code => code,
+            },
+            Self::Or(left, right) => match errexit::tested(left.interpret()).await {
+                code if code == 0 || subshell::is_exiting() => code,
+                _ => right.interpret().await,
+            },
+            Self::Sequence(left, right) => {
+                let code = left.interpret().await;
+                if subshell::is_exiting() {
+                    return code;
+                }
+                right.interpret().await
+            }
+            Self::Background(list) => {
+                job::spawn(list).await;
+                0
+            }
+        }
+    }
+}
+
+impl Display for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pipeline(pipeline) => write!(f, "{pipeline}"),
+            Self::And(left, right) => write!(f, "{left} && {right}"),
+            Self::Or(left, right) => write!(f, "{left} || {right}"),
+            Self::Sequence(left, right) => write!(f, "{left}; {right}"),
+            Self::Background(list) => write!(f, "{list} &"),
+        }
+    }
+}
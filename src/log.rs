@@ -0,0 +1,138 @@
+//! Opt-in debug logging, enabled with `RSHELL_LOG=LEVEL` or `--log-file FILE`.
+//!
+//! Records are written with the [`log!`](crate::log!) macro as
+//! `TIMESTAMP LEVEL TARGET: MESSAGE` lines, to the log file if given or stderr otherwise.
+
+use std::{
+    fmt::{self, Display},
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        })
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            "trace" => Ok(Self::Trace),
+            level => Err(level.to_string()),
+        }
+    }
+}
+
+struct Logger {
+    level: Level,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Starts logging records up to `level` into `file`, or stderr if `None`.
+///
+/// Logging stays off if neither is given. A log file without a level logs up to
+/// [`Level::Debug`].
+///
+/// # Errors
+///
+/// This function will return an error if the log file couldn't be opened.
+pub fn init(level: Option<Level>, file: Option<&Path>) -> io::Result<()> {
+    let level = match (level, file) {
+        (Some(level), _) => level,
+        (None, Some(_)) => Level::Debug,
+        (None, None) => return Ok(()),
+    };
+
+    let output: Box<dyn Write + Send> = match file {
+        Some(file) => Box::new(OpenOptions::new().create(true).append(true).open(file)?),
+        None => Box::new(io::stderr()),
+    };
+
+    let _ = LOGGER.set(Logger {
+        level,
+        output: Mutex::new(output),
+    });
+
+    Ok(())
+}
+
+/// Whether records of `level` are logged, to skip building expensive messages.
+#[must_use]
+pub fn enabled(level: Level) -> bool {
+    LOGGER.get().is_some_and(|logger| level <= logger.level)
+}
+
+/// Writes a record, use the [`log!`](crate::log!) macro instead.
+#[doc(hidden)]
+pub fn write(level: Level, target: &str, args: fmt::Arguments) {
+    let Some(logger) = LOGGER.get().filter(|logger| level <= logger.level) else {
+        return;
+    };
+
+    if let Ok(mut output) = logger.output.lock() {
+        let _ = writeln!(output, "{} {level:<5} {target}: {args}", timestamp());
+        let _ = output.flush();
+    }
+}
+
+/// Returns the current UTC time formatted like `2023-01-31T12:34:56.789Z`.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let seconds = now.as_secs();
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    // civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+        now.subsec_millis()
+    )
+}
@@ -1,80 +1,349 @@
 use rshell::{
-    Command, GREEN_FG_COLOR, PREVIOUS_EXIT_CODE, PROMPT_UNICODE, RED_FG_COLOR, RESET_FG_COLOR,
-    RSHELL_RC, RSHISTORY, SIGINT_EXIT_CODE,
+    bell::{self, Event},
+    completion,
+    editor::{self, Editor, ReadLine},
+    git, history, log,
+    metrics::{self, Record},
+    options::ShellOption,
+    prompt, record,
+    rusage::ExecutionReport,
+    tmux, trap, user, Command, OPTIONS, PREVIOUS_EXIT_CODE, RSHELL_MOTD, RSHELL_RC, RSHISTORY,
+    SIGINT_EXIT_CODE, STATUS,
 };
 
+use clap::{Arg, ArgAction};
 use signal_hook::{consts::SIGINT, iterator::Signals};
 
-use std::{
-    io::Write,
-    path::{Path, PathBuf},
-};
+use std::{path::Path, time::SystemTime};
 
-use tokio::{
-    fs::OpenOptions,
-    io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader},
-};
+use tokio::{io, task::JoinError};
+
+/// How many times in a row reading a line may fail before the shell exits, as its input is
+/// likely gone for good.
+const MAX_READ_ERRORS: usize = 3;
 
-#[tokio::main]
+// a single thread, so that subshells aren't forked while another one is in the middle of
+// something
+#[tokio::main(flavor = "current_thread")]
 async fn main() -> io::Result<()> {
-    // get home directory
-    let home_dir = match std::env::var("HOME") {
-        Ok(dir) => Some(dir),
+    let args = clap::Command::new("rshell")
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg(
+            Arg::new("login")
+                .short('l')
+                .long("login")
+                .help("Act as a login shell")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("log-file")
+                .long("log-file")
+                .value_name("FILE")
+                .help("Log debug records to FILE, see RSHELL_LOG")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("script")
+                .value_name("SCRIPT")
+                .help("Run the commands of SCRIPT instead of reading them")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("args")
+                .value_name("ARGS")
+                .help("The positional parameters of SCRIPT")
+                .num_args(0..)
+                .trailing_var_arg(true)
+                .allow_hyphen_values(true)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            // runs the picker in tmux popups
+            Arg::new("pick")
+                .long("pick")
+                .value_names(["ENTRIES", "OUTPUT"])
+                .num_args(2)
+                .action(ArgAction::Set)
+                .hide(true),
+        )
+        .get_matches();
+
+    if let Some(mut paths) = args.get_many::<String>("pick") {
+        let (Some(entries), Some(output)) = (paths.next(), paths.next()) else {
+            unreachable!("clap requires two values");
+        };
+
+        return editor::pick_to_file(Path::new(entries), Path::new(output));
+    }
+
+    let level = match std::env::var("RSHELL_LOG").map(|level| level.parse()) {
+        Ok(Ok(level)) => Some(level),
+        Ok(Err(level)) => {
+            rshell::error!("RSHELL_LOG: unknown level: {level}");
+            None
+        }
         Err(_) => None,
     };
 
-    let home_dir = home_dir.map(PathBuf::from);
+    if let Err(error) = log::init(level, args.get_one::<String>("log-file").map(Path::new)) {
+        rshell::error!("could not open log file: {error}");
+    }
+
+    user::init_env();
+    rshell::variables::init();
 
-    // open history file to store commands into history
-    let mut history = if let Some(home_dir) = home_dir.clone() {
-        let history = home_dir.join(RSHISTORY);
+    if let Some(path) = args.get_one::<String>("script") {
+        let args = args
+            .get_many::<String>("args")
+            .unwrap_or_default()
+            .cloned()
+            .collect();
+        let code = script(Path::new(path), args).await;
+        trap::exit(code).await;
+    }
+
+    {
+        let mut status = STATUS.lock().await;
+        status.interactive = termion::is_tty(&std::io::stdin());
+        // login shells are started with a `-` before their name
+        status.login = args.get_flag("login")
+            || std::env::args()
+                .next()
+                .is_some_and(|name| name.starts_with('-'));
+    }
 
-        match OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(history)
-            .await
-        {
-            Ok(history) => Some(history),
-            Err(_) => None,
+    // get home directory
+    let home_dir = user::home_dir();
+
+    // load previous commands so they can be navigated with the arrow keys, and open the history
+    // file to store commands into it
+    let history = home_dir.as_ref().map(|home_dir| home_dir.join(RSHISTORY));
+    let mut editor = Editor::new(history.as_deref().map(history::load).unwrap_or_default());
+    if let Some(history) = history {
+        if let Err(error) = history::open(&history) {
+            rshell::error!("could not open {}: {error}", history.display());
         }
-    } else {
-        None
-    };
+    }
 
     init(home_dir.as_deref()).await;
 
+    // pick up the environment of the starting directory
+    rshell::direnv::export().await;
+    rshell::project::enter().await;
+
+    if STATUS.lock().await.interactive {
+        completion::warm_up();
+    }
+
+    motd(home_dir.as_deref()).await;
+
     let mut signals = Signals::new([SIGINT])?;
 
+    // whether the working directory was found to be removed, to warn only once
+    let mut removed_dir = false;
+    let mut read_errors = 0;
+    // the number of the command being run, for `$LINENO`
+    let mut line_number = 0;
+
     'main_loop: loop {
         for signal in signals.pending() {
             if let SIGINT = signal {
+                log!(Debug, "signal", "SIGINT received");
                 *PREVIOUS_EXIT_CODE.lock().await = SIGINT_EXIT_CODE;
                 continue 'main_loop;
             }
         }
 
-        let current_dir = std::env::current_dir()?;
+        rshell::job::notify().await;
 
-        print_prompt(home_dir.as_deref(), &current_dir).await;
-        std::io::stdout().flush()?;
+        let current_dir = match std::env::current_dir() {
+            Ok(current_dir) => {
+                removed_dir = false;
+                current_dir
+            }
+            Err(error) => {
+                let current_dir = user::working_dir();
+                if !removed_dir {
+                    rshell::error!("{}: {error}, cd to leave it", current_dir.display());
+                    removed_dir = true;
+                }
+                current_dir
+            }
+        };
+
+        let prompt = prompt::main(&current_dir).await;
+
+        let Some(line) = read_line(&mut editor, &prompt, false, &mut read_errors).await else {
+            continue;
+        };
+        let command = match line {
+            ReadLine::Line(command) => command,
+            ReadLine::Interrupted => {
+                log!(Debug, "signal", "line interrupted");
+                *PREVIOUS_EXIT_CODE.lock().await = SIGINT_EXIT_CODE;
+                continue;
+            }
+            // EOF reached.
+            ReadLine::Eof => {
+                println!();
+                if !rshell::job::confirm_exit().await {
+                    continue;
+                }
+                trap::exit(0).await;
+            }
+        };
 
-        let command = read_command().await;
+        // a blank line leaves the history, metrics and `$?` alone
+        if command.trim().is_empty() {
+            continue;
+        }
+
+        line_number += 1;
+        rshell::variables::set_line(line_number);
+
+        // a command ending inside quotes, after `\\` or an operator like `&&`, or with
+        // here-documents goes on on the next lines
+        let mut source = command.clone();
+        loop {
+            match is_incomplete(&source).await {
+                Ok(false) => break,
+                Ok(true) => {}
+                Err(error) => {
+                    rshell::error!("internal error: {error}");
+                    *PREVIOUS_EXIT_CODE.lock().await = 1;
+                    continue 'main_loop;
+                }
+            }
 
-        // write command into history
-        if let Some(ref mut history) = history {
-            history.write_all(command.as_bytes()).await?;
+            let prompt = prompt::continuation(&current_dir).await;
+            let Some(line) = read_line(&mut editor, &prompt, true, &mut read_errors).await else {
+                continue 'main_loop;
+            };
+            match line {
+                ReadLine::Line(line) => {
+                    source.push('\n');
+                    source.push_str(&line);
+                }
+                // kept to be picked up again
+                ReadLine::Interrupted => {
+                    editor.add_history(&source);
+                    history::push(&source);
+                    *PREVIOUS_EXIT_CODE.lock().await = SIGINT_EXIT_CODE;
+                    continue 'main_loop;
+                }
+                // left to the parser to report
+                ReadLine::Eof => break,
+            }
         }
 
-        let (code, _) = match Command::run(&command).await {
-            (Ok(code), duration) => (code, duration),
-            (Err(error), duration) => {
+        editor.add_history(&source);
+        history::push(&source);
+
+        log!(Debug, "repl", "read {source:?}");
+        // a title is a single line
+        tmux::set_pane_title(&source.replace('\n', " "));
+
+        STATUS.lock().await.current_command.clone_from(&command);
+        let time = SystemTime::now();
+
+        record::command(&prompt, &source);
+        let capture = record::capture();
+
+        let run = source.clone();
+        // a panic is reported like an error instead of taking the shell down
+        let (code, report) = match tokio::spawn(async move { Command::run(&run).await }).await {
+            Ok((Ok(code), report)) => (code, report),
+            Ok((Err(error), report)) => {
                 rshell::error!("{error}");
-                (error.kind().code(), duration)
+                (error.kind().code(), report)
+            }
+            Err(error) => {
+                rshell::error!("internal error: {error}");
+                (1, ExecutionReport::default())
             }
         };
+        let duration = report.duration;
+
+        if let Some(capture) = capture {
+            capture.finish();
+        }
+
+        if OPTIONS.lock().await.is_set(ShellOption::CmdStats) {
+            eprintln!("{report}");
+        }
+
+        log!(Debug, "repl", "exited with {code} after {duration:?}");
+
+        if duration >= bell::FINISH_AFTER && STATUS.lock().await.interactive {
+            bell::ring(Event::Finished);
+        }
 
         *PREVIOUS_EXIT_CODE.lock().await = code;
+
+        // the command may have changed the working tree
+        git::check_working_tree();
+
+        metrics::record(&Record {
+            time,
+            duration,
+            code,
+            cwd: current_dir,
+            command: source,
+        })
+        .await;
+
+        tmux::set_pane_title("rshell");
+    }
+}
+
+/// Returns whether `source` needs more lines, or the panic of scanning or parsing it.
+async fn is_incomplete(source: &str) -> Result<bool, JoinError> {
+    let source = source.to_string();
+
+    tokio::spawn(async move { Command::is_incomplete(&source).await }).await
+}
+
+/// Reads a line with `prompt`, or one continuing a command, reporting a failure and returning
+/// `None` unless it happened [`MAX_READ_ERRORS`] times in a row, which exits.
+async fn read_line(
+    editor: &mut Editor,
+    prompt: &str,
+    continuation: bool,
+    errors: &mut usize,
+) -> Option<ReadLine> {
+    // read apart from the runtime's only thread, so that the tasks waiting for jobs go on
+    let mut reading = std::mem::take(editor);
+    let prompt = prompt.to_string();
+    let read = tokio::task::spawn_blocking(move || {
+        let line = if continuation {
+            reading.read_continuation(&prompt)
+        } else {
+            reading.read_line(&prompt)
+        };
+        (reading, line)
+    })
+    .await;
+    let line = match read {
+        Ok((reading, line)) => {
+            *editor = reading;
+            line
+        }
+        Err(error) => Err(std::io::Error::other(error)),
+    };
+
+    match line {
+        Ok(line) => {
+            *errors = 0;
+            Some(line)
+        }
+        Err(error) => {
+            rshell::error!("could not read a line: {error}");
+            *errors += 1;
+            if *errors >= MAX_READ_ERRORS {
+                trap::exit(1).await;
+            }
+            None
+        }
     }
 }
 
@@ -82,16 +351,13 @@ async fn init(home_dir: Option<&Path>) {
     if let Some(home_dir) = home_dir {
         let shellrc = home_dir.join(RSHELL_RC);
 
-        let shellrc = match tokio::fs::read(shellrc).await {
-            Ok(rc) => Some(rc),
-            Err(_) => None,
-        };
+        let shellrc = tokio::fs::read_to_string(shellrc).await.ok();
 
         if let Some(shellrc) = shellrc {
-            let mut lines = shellrc.lines();
+            let mut lines = shellrc.lines().enumerate();
 
-            while let Ok(Some(line)) = lines.next_line().await {
-                if let (Err(_), _) = Command::run(&line).await {
+            while let Some((_, command)) = next_command(&mut lines).await {
+                if let (Err(_), _) = Command::run(&command).await {
                     return;
                 }
             }
@@ -99,68 +365,78 @@ async fn init(home_dir: Option<&Path>) {
     }
 }
 
-/// Prints the shell prompt given the previous command's exit code, home directory
-/// and current directory.
-///
-/// # Shell Prompt
-///
-/// Looks like this:
-///     "\[~ if is relative to home directory\]/\[full path\] ❯ (green or red depending on exit code success or failure respectively)"
-///
-/// # Examples
-///
-/// ```no_run
-/// print_prompt(0, "/Users/any", "/Users/any/sandbox") // prints "~/sandbox ❯ " with the ❯ character green
-/// print_prompt(42069, "/Users/any", "/Users/any/sandbox") // prints "~/sandbox ❯ " with the ❯ character red
-/// ```
-async fn print_prompt(home_dir: Option<&Path>, current_dir: &Path) {
-    // print the current directory
-    if let Some(home_dir) = home_dir {
-        print!(
-            "{} ",
-            current_dir
-                .display()
-                .to_string()
-                .replace(&home_dir.display().to_string(), "~")
-        );
-    } else {
-        print!("{} ", current_dir.display());
+/// Runs the script at `path` with the positional parameters `args`, returning the exit code of
+/// its last command, 127 if it can't be read, or 2 on a syntax error.
+async fn script(path: &Path, args: Vec<String>) -> i32 {
+    let source = match tokio::fs::read_to_string(path).await {
+        Ok(source) => source,
+        Err(error) => {
+            rshell::error!("{}: {error}", path.display());
+            return 127;
+        }
+    };
+    rshell::variables::set_name(path.display().to_string());
+    rshell::variables::set_positional(args);
+
+    let mut code = 0;
+    let mut lines = source.lines().enumerate();
+
+    while let Some((number, command)) = next_command(&mut lines).await {
+        rshell::variables::set_line(number);
+
+        code = match Command::run(&command).await {
+            (Ok(code), _) => code,
+            (Err(error), _) => {
+                rshell::error!("{}: {error}", path.display());
+                return 2;
+            }
+        };
+        *PREVIOUS_EXIT_CODE.lock().await = code;
     }
 
-    // print the prompt and reset the color
-    print!(
-        "{}{}{} ",
-        match *PREVIOUS_EXIT_CODE.lock().await {
-            0 => GREEN_FG_COLOR.to_string(),
-            _ => RED_FG_COLOR.to_string(),
-        },
-        PROMPT_UNICODE,
-        RESET_FG_COLOR
-    );
+    code
 }
 
-/// Reads a command from stdin and returns it.
-///
-/// # Panics
-///
-/// Panics if the [`BufReader`] couldn't read from stdin.
-///
-/// # Exits
-///
-/// Exits the program if the character read is an EOF character (CTRL+D).
-async fn read_command() -> String {
-    let mut command = String::new();
-
-    let bytes = BufReader::new(io::stdin())
-        .read_line(&mut command)
-        .await
-        .expect("Failed to read line");
-
-    // EOF reached.
-    if bytes == 0 {
-        println!();
-        std::process::exit(0);
+/// Returns the next command of the numbered `lines` of a file, with the number of its first
+/// line, skipping blank lines. A command may go on on the next lines, like the bodies of
+/// here-documents.
+async fn next_command<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Option<(usize, String)> {
+    let (number, line) = lines.find(|(_, line)| !line.trim().is_empty())?;
+
+    let mut command = line.to_string();
+    while Command::is_incomplete(&command).await {
+        let Some((_, line)) = lines.next() else {
+            break;
+        };
+        command.push('\n');
+        command.push_str(line);
+    }
+
+    Some((number + 1, command))
+}
+
+/// Runs the commands of the startup message file before the first prompt of an interactive
+/// shell, unless the `nomotd` option is set.
+async fn motd(home_dir: Option<&Path>) {
+    if !STATUS.lock().await.interactive || OPTIONS.lock().await.is_set(ShellOption::NoMotd) {
+        return;
     }
 
-    command
+    let Some(home_dir) = home_dir else {
+        return;
+    };
+
+    let Ok(motd) = tokio::fs::read_to_string(home_dir.join(RSHELL_MOTD)).await else {
+        return;
+    };
+
+    let mut lines = motd.lines().enumerate();
+    while let Some((_, command)) = next_command(&mut lines).await {
+        if let (Err(error), _) = Command::run(&command).await {
+            rshell::error!("{RSHELL_MOTD}: {error}");
+            return;
+        }
+    }
 }
@@ -0,0 +1,265 @@
+//! Floating point expressions, as evaluated by the `math` builtin.
+//!
+//! Expressions support `+`, `-`, `*` (or `x`), `/`, `%` and right-associative `^`, parentheses,
+//! decimal, hexadecimal and scientific numbers, the constants `pi`, `tau` and `e`, and
+//! functions like `sqrt(2)` or `max(1, 2)`.
+
+use std::{iter::Peekable, str::Chars};
+
+/// Evaluates `expression`.
+///
+/// # Errors
+///
+/// This function will return an error describing the problem if the expression is invalid.
+pub fn eval(expression: &str) -> Result<f64, String> {
+    let mut parser = Parser {
+        chars: expression.chars().peekable(),
+    };
+
+    let value = parser.expression()?;
+
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected '{c}'")),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    /// Returns the next character that isn't whitespace, without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+
+        loop {
+            if self.eat('*') || self.eat('x') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err(String::from("division by zero"));
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err(String::from("division by zero"));
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            Ok(-self.unary()?)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.primary()?;
+
+        if self.eat('^') {
+            Ok(base.powf(self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let value = self.expression()?;
+
+                if self.eat(')') {
+                    Ok(value)
+                } else {
+                    Err(String::from("missing ')'"))
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() => self.name(),
+            Some(c) => Err(format!("unexpected '{c}'")),
+            None => Err(String::from("unexpected end of expression")),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let mut number = String::new();
+
+        if self.eat('0') {
+            if self.chars.next_if(|&c| c == 'x' || c == 'X').is_some() {
+                while let Some(c) = self.chars.next_if(char::is_ascii_hexdigit) {
+                    number.push(c);
+                }
+
+                #[allow(clippy::cast_precision_loss)]
+                return u64::from_str_radix(&number, 16)
+                    .map(|number| number as f64)
+                    .map_err(|_| String::from("invalid hexadecimal number"));
+            }
+            number.push('0');
+        }
+
+        while let Some(c) = self.chars.next_if(|&c| c.is_ascii_digit() || c == '.') {
+            number.push(c);
+        }
+
+        // an exponent, unless the `e` is the start of a name
+        let mut lookahead = self.chars.clone();
+        if lookahead.next_if(|&c| c == 'e' || c == 'E').is_some() {
+            let sign = lookahead.next_if(|&c| c == '-' || c == '+');
+
+            if lookahead.peek().is_some_and(char::is_ascii_digit) {
+                number.push('e');
+                number.extend(sign);
+                self.chars = lookahead;
+
+                while let Some(c) = self.chars.next_if(char::is_ascii_digit) {
+                    number.push(c);
+                }
+            }
+        }
+
+        number
+            .parse()
+            .map_err(|_| format!("invalid number: {number}"))
+    }
+
+    fn name(&mut self) -> Result<f64, String> {
+        let mut name = String::new();
+        while let Some(c) = self.chars.next_if(|&c| c.is_alphanumeric() || c == '_') {
+            name.push(c);
+        }
+
+        if !self.eat('(') {
+            return match name.as_str() {
+                "pi" => Ok(std::f64::consts::PI),
+                "tau" => Ok(std::f64::consts::TAU),
+                "e" => Ok(std::f64::consts::E),
+                name => Err(format!("unknown constant: {name}")),
+            };
+        }
+
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.expression()?);
+
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(String::from("missing ')'"));
+                }
+            }
+        }
+
+        call(&name, &args)
+    }
+}
+
+fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+    let value = match (name, args) {
+        ("abs", [x]) => x.abs(),
+        ("acos", [x]) => x.acos(),
+        ("asin", [x]) => x.asin(),
+        ("atan", [x]) => x.atan(),
+        ("atan2", [y, x]) => y.atan2(*x),
+        ("ceil", [x]) => x.ceil(),
+        ("cos", [x]) => x.cos(),
+        ("cosh", [x]) => x.cosh(),
+        ("exp", [x]) => x.exp(),
+        ("floor", [x]) => x.floor(),
+        ("ln", [x]) => x.ln(),
+        ("log" | "log10", [x]) => x.log10(),
+        ("log2", [x]) => x.log2(),
+        ("max", [first, rest @ ..]) => rest.iter().fold(*first, |max, x| max.max(*x)),
+        ("min", [first, rest @ ..]) => rest.iter().fold(*first, |min, x| min.min(*x)),
+        ("pow", [x, y]) => x.powf(*y),
+        ("round", [x]) => x.round(),
+        ("sin", [x]) => x.sin(),
+        ("sinh", [x]) => x.sinh(),
+        ("sqrt", [x]) => x.sqrt(),
+        ("tan", [x]) => x.tan(),
+        ("tanh", [x]) => x.tanh(),
+        (
+            "abs" | "acos" | "asin" | "atan" | "atan2" | "ceil" | "cos" | "cosh" | "exp" | "floor"
+            | "ln" | "log" | "log10" | "log2" | "max" | "min" | "pow" | "round" | "sin" | "sinh"
+            | "sqrt" | "tan" | "tanh",
+            _,
+        ) => return Err(format!("wrong number of arguments to {name}")),
+        (name, _) => return Err(format!("unknown function: {name}")),
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eval;
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval("(1 + 2) x 3"), Ok(9.0));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(eval("7 % 4 - 10 / 4"), Ok(0.5));
+    }
+
+    #[test]
+    fn numbers_constants_and_functions() {
+        assert_eq!(eval("0x10 + 1e2"), Ok(116.0));
+        assert_eq!(eval("pi"), Ok(std::f64::consts::PI));
+        assert_eq!(eval("max(1, 3, 2) + sqrt(16)"), Ok(7.0));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval("1 / 0"), Err(String::from("division by zero")));
+        assert_eq!(
+            eval("1 +"),
+            Err(String::from("unexpected end of expression"))
+        );
+        assert_eq!(eval("(1"), Err(String::from("missing ')'")));
+        assert_eq!(eval("1 2"), Err(String::from("unexpected '2'")));
+        assert_eq!(eval("foo"), Err(String::from("unknown constant: foo")));
+    }
+}
@@ -0,0 +1,145 @@
+//! Per-command metrics, recorded to `~/.rshell_metrics` with `set -o metrics` and summarized by
+//! the `report` builtin.
+//!
+//! Each line of the metrics file is a tab-separated record of the start time in seconds since
+//! the unix epoch, the duration in milliseconds, the exit code, the working directory and the
+//! command line.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+use crate::{error, options::ShellOption, user, OPTIONS, RSMETRICS};
+
+/// A command that was run from the prompt.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub time: SystemTime,
+    pub duration: Duration,
+    pub code: i32,
+    pub cwd: PathBuf,
+    pub command: String,
+}
+
+impl Record {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(5, '\t');
+
+        Some(Self {
+            time: UNIX_EPOCH + Duration::from_secs(fields.next()?.parse().ok()?),
+            duration: Duration::from_millis(fields.next()?.parse().ok()?),
+            code: fields.next()?.parse().ok()?,
+            cwd: PathBuf::from(fields.next()?),
+            command: fields.next()?.to_string(),
+        })
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            self.time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            self.duration.as_millis(),
+            self.code,
+            self.cwd.display(),
+            self.command.replace(['\t', '\n'], " "),
+        )
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    user::home_dir().map(|home_dir| home_dir.join(RSMETRICS))
+}
+
+/// Appends `record` to the metrics file.
+///
+/// Does nothing unless the `metrics` option is set. Problems are reported with [`error!`].
+pub async fn record(record: &Record) {
+    if !OPTIONS.lock().await.is_set(ShellOption::Metrics) {
+        return;
+    }
+
+    let Some(path) = path() else {
+        return;
+    };
+
+    let file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .await;
+
+    let result = match file {
+        Ok(mut file) => file.write_all(record.to_line().as_bytes()).await,
+        Err(error) => Err(error),
+    };
+
+    if let Err(error) = result {
+        error!("metrics: could not write to {}: {error}", path.display());
+    }
+}
+
+/// Reads the records started within `window` of now, oldest first.
+///
+/// # Errors
+///
+/// This function will return an error if the metrics file could not be read.
+pub async fn read(window: Duration) -> std::io::Result<Vec<Record>> {
+    let Some(path) = path() else {
+        return Ok(Vec::new());
+    };
+
+    let since = SystemTime::now().checked_sub(window).unwrap_or(UNIX_EPOCH);
+
+    let metrics = match tokio::fs::read_to_string(path).await {
+        Ok(metrics) => metrics,
+        // nothing was recorded yet
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+
+    Ok(metrics
+        .lines()
+        .filter_map(Record::parse)
+        .filter(|record| record.time >= since)
+        .collect())
+}
+
+/// The failures of a command, keyed by its first word in [`failure_rates`].
+#[derive(Debug, Default)]
+pub struct Failures {
+    pub runs: usize,
+    pub failed: usize,
+}
+
+/// Counts the runs and failures of every command, by the command's first word.
+#[must_use]
+pub fn failure_rates(records: &[Record]) -> Vec<(&str, Failures)> {
+    let mut failures = HashMap::<&str, Failures>::new();
+
+    for record in records {
+        let name = record.command.split_whitespace().next().unwrap_or_default();
+        let failures = failures.entry(name).or_default();
+
+        failures.runs += 1;
+        if record.code != 0 {
+            failures.failed += 1;
+        }
+    }
+
+    let mut failures = failures.into_iter().collect::<Vec<_>>();
+    // highest rate first, then most failures, then by name
+    failures.sort_by(|(a_name, a), (b_name, b)| {
+        (b.failed * a.runs)
+            .cmp(&(a.failed * b.runs))
+            .then(b.failed.cmp(&a.failed))
+            .then(a_name.cmp(b_name))
+    });
+    failures
+}
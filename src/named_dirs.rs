@@ -0,0 +1,40 @@
+//! Named directories, set with `hash -d NAME=PATH`, which `~NAME` expands to and which the
+//! prompt shows as `~NAME`, like zsh's.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+static NAMED_DIRS: Mutex<BTreeMap<String, PathBuf>> = Mutex::new(BTreeMap::new());
+
+fn named_dirs() -> MutexGuard<'static, BTreeMap<String, PathBuf>> {
+    NAMED_DIRS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Names `dir` `name`, replacing the directory it named before.
+pub fn set(name: String, dir: PathBuf) {
+    named_dirs().insert(name, dir);
+}
+
+/// Removes the name `name`, returning whether it named a directory.
+#[must_use]
+pub fn remove(name: &str) -> bool {
+    named_dirs().remove(name).is_some()
+}
+
+/// Returns the directory named `name`.
+#[must_use]
+pub fn get(name: &str) -> Option<PathBuf> {
+    named_dirs().get(name).cloned()
+}
+
+/// Returns the names and their directories, sorted by name.
+#[must_use]
+pub fn all() -> Vec<(String, PathBuf)> {
+    named_dirs()
+        .iter()
+        .map(|(name, dir)| (name.clone(), dir.clone()))
+        .collect()
+}
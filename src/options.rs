@@ -0,0 +1,183 @@
+//! Shell options toggled with `set -o NAME` and `set +o NAME`.
+
+use std::{collections::HashSet, fmt::Display, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShellOption {
+    /// Lists the new working directory with `ls` after `cd`.
+    AutoLs,
+    /// Pushes the directory `cd` leaves onto the directory stack, like `pushd`.
+    AutoPushd,
+    /// Rings the bell when a command that ran for a while finishes.
+    BeepFinish,
+    /// Rings the bell when a background job is done.
+    BeepJobs,
+    /// Lists the running jobs when exiting is held back because of them.
+    CheckJobs,
+    /// Prints the wall time, CPU time and peak memory of every line run from the prompt.
+    CmdStats,
+    /// Offers the closest existing directory when `cd` is given a missing one.
+    Correct,
+    /// Applies `direnv export` to the environment whenever the directory changes.
+    Direnv,
+    /// Stops a script, subshell, job or line at the first failing command, and `repeat` at the
+    /// first failing run, `set -e`.
+    ErrExit,
+    /// Completes candidates containing the characters of the word in order, if no other
+    /// candidates match.
+    FuzzyComplete,
+    /// Shows the branch of the git repository and whether it has changes in the prompt.
+    GitPrompt,
+    /// Completes the flags of commands without completions from their `--help` output.
+    HelpComplete,
+    /// Steps through the history entries containing the typed line with the up and down arrows.
+    HistSubstring,
+    /// Writes every command to the history file and syncs it to disk as soon as it is entered.
+    HistSync,
+    /// Draws the completion candidates cycled through with TAB as a menu navigated with the
+    /// arrow keys.
+    MenuSelect,
+    /// Records the duration and exit code of every command to the metrics file.
+    Metrics,
+    /// Never rings the bell.
+    NoBeep,
+    /// Completes candidates regardless of case.
+    NoCaseComplete,
+    /// Skips the startup message of interactive shells.
+    NoMotd,
+    /// Fails commands expanding a variable that isn't set, `set -u`.
+    NoUnset,
+    /// Pipes builtin help and completion listings that don't fit in the terminal through
+    /// `$PAGER`.
+    Pager,
+    /// Makes a pipeline fail with the exit code of its last failing command.
+    PipeFail,
+    /// Sources the trusted `.rshellrc` of a project when entering its directory tree.
+    ProjectRc,
+    /// Completes candidates containing the word, if none start with it.
+    SubstringComplete,
+    /// Flashes the screen instead of ringing the bell.
+    VisualBell,
+    /// Prints every command before running it, `set -x`.
+    XTrace,
+}
+
+impl ShellOption {
+    pub const ALL: [Self; 26] = [
+        Self::AutoLs,
+        Self::AutoPushd,
+        Self::BeepFinish,
+        Self::BeepJobs,
+        Self::CheckJobs,
+        Self::CmdStats,
+        Self::Correct,
+        Self::Direnv,
+        Self::ErrExit,
+        Self::FuzzyComplete,
+        Self::GitPrompt,
+        Self::HelpComplete,
+        Self::HistSubstring,
+        Self::HistSync,
+        Self::MenuSelect,
+        Self::Metrics,
+        Self::NoBeep,
+        Self::NoCaseComplete,
+        Self::NoMotd,
+        Self::NoUnset,
+        Self::Pager,
+        Self::PipeFail,
+        Self::ProjectRc,
+        Self::SubstringComplete,
+        Self::VisualBell,
+        Self::XTrace,
+    ];
+}
+
+impl Display for ShellOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::AutoLs => "autols",
+            Self::AutoPushd => "autopushd",
+            Self::BeepFinish => "beepfinish",
+            Self::BeepJobs => "beepjobs",
+            Self::CheckJobs => "checkjobs",
+            Self::CmdStats => "cmdstats",
+            Self::Correct => "correct",
+            Self::Direnv => "direnv",
+            Self::ErrExit => "errexit",
+            Self::FuzzyComplete => "fuzzycomplete",
+            Self::GitPrompt => "gitprompt",
+            Self::HelpComplete => "helpcomplete",
+            Self::HistSubstring => "histsubstring",
+            Self::HistSync => "histsync",
+            Self::MenuSelect => "menuselect",
+            Self::Metrics => "metrics",
+            Self::NoBeep => "nobeep",
+            Self::NoCaseComplete => "nocasecomplete",
+            Self::NoMotd => "nomotd",
+            Self::NoUnset => "nounset",
+            Self::Pager => "pager",
+            Self::PipeFail => "pipefail",
+            Self::ProjectRc => "projectrc",
+            Self::SubstringComplete => "substringcomplete",
+            Self::VisualBell => "visualbell",
+            Self::XTrace => "xtrace",
+        })
+    }
+}
+
+impl FromStr for ShellOption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "autols" => Ok(Self::AutoLs),
+            "autopushd" => Ok(Self::AutoPushd),
+            "beepfinish" => Ok(Self::BeepFinish),
+            "beepjobs" => Ok(Self::BeepJobs),
+            "checkjobs" => Ok(Self::CheckJobs),
+            "cmdstats" => Ok(Self::CmdStats),
+            "correct" => Ok(Self::Correct),
+            "direnv" => Ok(Self::Direnv),
+            "errexit" => Ok(Self::ErrExit),
+            "fuzzycomplete" => Ok(Self::FuzzyComplete),
+            "gitprompt" => Ok(Self::GitPrompt),
+            "helpcomplete" => Ok(Self::HelpComplete),
+            "histsubstring" => Ok(Self::HistSubstring),
+            "histsync" => Ok(Self::HistSync),
+            "menuselect" => Ok(Self::MenuSelect),
+            "metrics" => Ok(Self::Metrics),
+            "nobeep" => Ok(Self::NoBeep),
+            "nocasecomplete" => Ok(Self::NoCaseComplete),
+            "nomotd" => Ok(Self::NoMotd),
+            "nounset" => Ok(Self::NoUnset),
+            "pager" => Ok(Self::Pager),
+            "pipefail" => Ok(Self::PipeFail),
+            "projectrc" => Ok(Self::ProjectRc),
+            "substringcomplete" => Ok(Self::SubstringComplete),
+            "visualbell" => Ok(Self::VisualBell),
+            "xtrace" => Ok(Self::XTrace),
+            option => Err(option.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Options {
+    enabled: HashSet<ShellOption>,
+}
+
+impl Options {
+    #[must_use]
+    pub fn is_set(&self, option: ShellOption) -> bool {
+        self.enabled.contains(&option)
+    }
+
+    pub fn set(&mut self, option: ShellOption, value: bool) {
+        if value {
+            self.enabled.insert(option);
+        } else {
+            self.enabled.remove(&option);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Paging of output longer than the terminal through `$PAGER`, with `set -o pager`, so that it
+//! doesn't scroll out of sight.
+
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use crate::{options::ShellOption, redirect, OPTIONS};
+
+/// Returns whether `lines` lines of output should be paged: the `pager` option is set, the
+/// standard output of the command being run is a terminal and they don't fit in it.
+#[must_use]
+pub fn is_needed(lines: usize) -> bool {
+    let enabled = OPTIONS
+        .try_lock()
+        .is_ok_and(|options| options.is_set(ShellOption::Pager));
+    let Ok((_, height)) = termion::terminal_size() else {
+        return false;
+    };
+
+    // the prompt takes a line too
+    enabled && redirect::stdout().is_terminal() && lines >= usize::from(height)
+}
+
+/// Prints `text`, through the pager if it is needed.
+///
+/// # Errors
+///
+/// Fails if `text` could not be written.
+pub fn print(text: &str) -> io::Result<()> {
+    if is_needed(text.lines().count()) {
+        page(text)
+    } else {
+        redirect::stdout().write_all(text.as_bytes())
+    }
+}
+
+/// Writes `text` to `$PAGER`, falling back to `less`, or to stdout if the pager can't be run.
+///
+/// Like git, `LESS` is set to `FRX` unless it already is, so that colors are kept and less exits
+/// right away if `text` turns out to fit after all.
+///
+/// # Errors
+///
+/// Fails if `text` could not be written, or the pager could not be waited for.
+pub fn page(text: &str) -> io::Result<()> {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| String::from("less"));
+    // like `less -S`
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        return redirect::stdout().write_all(text.as_bytes());
+    };
+
+    let mut command = Command::new(program);
+    command.args(words).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    let Ok(mut process) = command.spawn() else {
+        return redirect::stdout().write_all(text.as_bytes());
+    };
+
+    if let Some(mut stdin) = process.stdin.take() {
+        match stdin.write_all(text.as_bytes()) {
+            // the pager was quit before reading everything
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+    }
+
+    process.wait().map(|_| ())
+}
@@ -0,0 +1,191 @@
+//! Pipelines like `cmd1 | cmd2 | cmd3`, whose commands run concurrently with the standard output
+//! of each one connected to the standard input of the next.
+//!
+//! Processes are spawned first, then builtins run in the shell one after the other with their
+//! standard streams swapped for the pipes. The output of a builtin is spooled by threads, so that
+//! a builtin never blocks on a pipe that is only read once a later builtin runs.
+
+use std::{
+    fmt::Display,
+    io::{self, Read, Write},
+    os::fd::{OwnedFd, RawFd},
+    process::Child,
+    sync::mpsc,
+};
+
+use crate::{error, options::ShellOption, variables, Command, OPTIONS};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pipeline {
+    pub(crate) commands: Vec<Command>,
+    /// Whether the pipeline starts with `!`, which exits with 1 if it succeeded and 0 otherwise.
+    pub(crate) negated: bool,
+}
+
+/// A command of a pipeline being run.
+enum Stage {
+    /// A builtin waiting for the processes to be spawned, with its pipes.
+    Builtin(Vec<(RawFd, OwnedFd)>),
+    Running(Child),
+    Exited(i32),
+}
+
+impl Pipeline {
+    #[must_use]
+    pub fn new(commands: Vec<Command>, negated: bool) -> Self {
+        Self { commands, negated }
+    }
+
+    /// Returns the commands of the pipeline, joined by `|`.
+    #[must_use]
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Returns whether the pipeline starts with `!`.
+    #[must_use]
+    pub const fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Runs the commands of the pipeline, returning the exit code of the last one, or with
+    /// `set -o pipefail` of the last one that failed. The exit codes of all of them are kept in
+    /// `$PIPESTATUS`.
+    pub(crate) async fn interpret(&self) -> i32 {
+        if let [command] = self.commands.as_slice() {
+            let code = command.interpret().await;
+            variables::set_pipe_status(vec![code]);
+            return code;
+        }
+
+        let mut commands = Vec::with_capacity(self.commands.len());
+        for command in &self.commands {
+            let command = match command.expand().await {
+                Ok(command) => command,
+                Err(error) => {
+                    error!("{error}");
+                    return 1;
+                }
+            };
+            if !command.allowed().await {
+                return 1;
+            }
+            command.trace().await;
+            commands.push(command);
+        }
+
+        let pipes = match pipes(&commands) {
+            Ok(pipes) => pipes,
+            Err(error) => {
+                error!("{error}");
+                return 1;
+            }
+        };
+
+        let mut stages = commands
+            .iter()
+            .zip(pipes)
+            .map(|(command, pipes)| {
+                if command.is_empty() {
+                    Stage::Exited(0)
+                } else if command.is_builtin() {
+                    Stage::Builtin(pipes)
+                } else {
+                    command
+                        .spawn(pipes)
+                        .map_or_else(Stage::Exited, Stage::Running)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (command, stage) in commands.iter().zip(&mut stages) {
+            if let Stage::Builtin(pipes) = stage {
+                *stage = Stage::Exited(command.run_builtin(std::mem::take(pipes)).await);
+            }
+        }
+
+        let pipefail = OPTIONS.lock().await.is_set(ShellOption::PipeFail);
+        let mut code = 0;
+        let mut codes = Vec::with_capacity(stages.len());
+        for (command, stage) in commands.iter().zip(stages) {
+            let exited = match stage {
+                Stage::Running(process) => command.wait(process).await,
+                Stage::Exited(code) => code,
+                // builtins were run above
+                Stage::Builtin(_) => 0,
+            };
+
+            if !pipefail || exited != 0 {
+                code = exited;
+            }
+            codes.push(exited);
+        }
+
+        variables::set_pipe_status(codes);
+        code
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.negated {
+            f.write_str("! ")?;
+        }
+
+        for (index, command) in self.commands.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" | ")?;
+            }
+            write!(f, "{command}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the pipes of every command of a pipeline, by the standard stream they replace.
+fn pipes(commands: &[Command]) -> io::Result<Vec<Vec<(RawFd, OwnedFd)>>> {
+    let mut pipes = commands.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+
+    for index in 1..commands.len() {
+        let (reader, writer) = io::pipe()?;
+        pipes[index - 1].push((libc::STDOUT_FILENO, writer.into()));
+
+        let reader = if commands[index - 1].is_builtin() {
+            spool(reader)?
+        } else {
+            reader.into()
+        };
+        pipes[index].push((libc::STDIN_FILENO, reader));
+    }
+
+    Ok(pipes)
+}
+
+/// Returns a pipe reading what is written to the pipe of `reader`, buffered in between so that
+/// writing never blocks. Once nobody reads the spooled pipe, `reader` is closed, so that writing
+/// fails instead of going on for nothing.
+fn spool(mut reader: io::PipeReader) -> io::Result<OwnedFd> {
+    let (spooled, mut writer) = io::pipe()?;
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+
+        while let Ok(read @ 1..) = reader.read(&mut buffer) {
+            if sender.send(buffer[..read].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    std::thread::spawn(move || {
+        for chunk in receiver {
+            if writer.write_all(&chunk).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(spooled.into())
+}
@@ -0,0 +1,58 @@
+//! The scheduling priorities `nice` and `ionice` run their command with, set in every process it
+//! spawns before `exec`.
+//!
+//! Builtins run by the command keep the shell's own priority, but the processes they spawn get
+//! the adjusted one, so `nice repeat 3 make` runs every `make` with it.
+
+use std::{future::Future, io};
+
+tokio::task_local! {
+    /// Set while `nice` or `ionice` runs its command.
+    static PRIORITY: Priority;
+}
+
+/// How the scheduling priority of a process differs from the shell's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Priority {
+    /// Added to the niceness, from -20 for the most favorable scheduling to 19 for the least.
+    pub(crate) niceness: i32,
+    /// The I/O scheduling class and level, as given to `ioprio_set`.
+    pub(crate) io: Option<i32>,
+}
+
+impl Priority {
+    /// Returns a function setting the priority, to be run in a forked process before `exec`.
+    ///
+    /// A priority the process isn't allowed to have, like a negative niceness without
+    /// privileges, is left as it is, like GNU `nice` does after warning.
+    pub(crate) fn installer(self) -> impl FnMut() -> io::Result<()> + Send + Sync + 'static {
+        move || {
+            // SAFETY: nice and ioprio_set are plain system calls, which are async-signal-safe.
+            unsafe {
+                if self.niceness != 0 {
+                    libc::nice(self.niceness);
+                }
+
+                #[cfg(target_os = "linux")]
+                if let Some(io) = self.io {
+                    /// Sets the priority of a process, rather than of a process group or user.
+                    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+                    libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, io);
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Returns the priority of the processes spawned at this point.
+pub(crate) fn current() -> Priority {
+    PRIORITY.try_with(|priority| *priority).unwrap_or_default()
+}
+
+/// Runs `future` with the processes it spawns getting `priority`.
+pub(crate) async fn scope<F: Future>(priority: Priority, future: F) -> F::Output {
+    PRIORITY.scope(priority, future).await
+}
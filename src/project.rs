@@ -0,0 +1,136 @@
+//! Project rc files, sourced with `set -o projectrc` when entering a directory tree whose root
+//! has a `.rshellrc`, so that repositories can ship shell helpers and environment setup.
+//!
+//! An rc file only runs once trusted. The first time, and whenever its contents change, the
+//! shell asks whether to trust it, and remembers the SHA-256 hash of the trusted contents in the
+//! `trusted` file of the configuration directory, one `HASH  PATH` line per file like the output
+//! of `sha256sum`.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{error, options::ShellOption, user, Command, OPTIONS, RSHELL_RC};
+
+const TRUSTED: &str = "trusted";
+
+/// The root of the project entered last, if any.
+static CURRENT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Sources the rc file of the project containing the working directory, if it is a different
+/// project than before and its rc file is trusted.
+///
+/// Does nothing unless the `projectrc` option is set.
+pub async fn enter() {
+    if !OPTIONS.lock().await.is_set(ShellOption::ProjectRc) {
+        return;
+    }
+
+    let root = std::env::current_dir().ok().and_then(|dir| root(&dir));
+    {
+        let mut current = CURRENT.lock().unwrap_or_else(PoisonError::into_inner);
+        if *current == root {
+            return;
+        }
+        current.clone_from(&root);
+    }
+
+    let Some(root) = root else {
+        return;
+    };
+    let path = root.join(RSHELL_RC);
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            error!("{}: {error}", path.display());
+            return;
+        }
+    };
+
+    let hash = format!("{:x}", Sha256::digest(contents.as_bytes()));
+    if !is_trusted(&path, &hash) {
+        if !user::confirm(&format!(
+            "{}: trust this project's rc file?",
+            path.display()
+        )) {
+            return;
+        }
+
+        if let Err(error) = trust(&path, &hash) {
+            error!("could not trust {}: {error}", path.display());
+        }
+    }
+
+    for line in contents.lines() {
+        if let (Err(error), _) = Command::run(line).await {
+            error!("{}: {error}", path.display());
+            return;
+        }
+    }
+}
+
+/// Returns the closest directory from `dir` up with an rc file, other than the home directory
+/// whose rc file is sourced at startup.
+fn root(dir: &Path) -> Option<PathBuf> {
+    let home_dir = user::home_dir();
+
+    dir.ancestors()
+        .filter(|dir| home_dir.as_deref() != Some(*dir))
+        .find(|dir| dir.join(RSHELL_RC).is_file())
+        .map(Path::to_path_buf)
+}
+
+fn trusted_path() -> Option<PathBuf> {
+    user::config_dir().map(|config_dir| config_dir.join(TRUSTED))
+}
+
+/// Returns whether the rc file at `path` was trusted with the contents hashed to `hash`.
+fn is_trusted(path: &Path, hash: &str) -> bool {
+    let Some(trusted) = trusted_path().and_then(|trusted| std::fs::read_to_string(trusted).ok())
+    else {
+        return false;
+    };
+
+    let line = format!("{hash}  {}", path.display());
+    trusted.lines().any(|trusted| trusted == line)
+}
+
+/// Trusts the rc file at `path` with the contents hashed to `hash`, replacing the hash it was
+/// trusted with before.
+fn trust(path: &Path, hash: &str) -> io::Result<()> {
+    let trusted_path = trusted_path().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not find the configuration directory",
+        )
+    })?;
+
+    let trusted = match std::fs::read_to_string(&trusted_path) {
+        Ok(trusted) => trusted,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+
+    let path = path.display().to_string();
+    let mut contents = String::new();
+    for line in trusted.lines() {
+        if line.split_once("  ").map(|(_, trusted)| trusted) != Some(path.as_str()) {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+    }
+    contents.push_str(hash);
+    contents.push_str("  ");
+    contents.push_str(&path);
+    contents.push('\n');
+
+    if let Some(dir) = trusted_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(trusted_path, contents)
+}
@@ -0,0 +1,139 @@
+//! The prompts of the shell: the one before a command, `$PS2` before the lines continuing a
+//! command and `$PS4` before the commands traced with `set -x`.
+//!
+//! All of them are templates, where `\l` stands for the nesting level, `\w` for the working
+//! directory, `\g` for the git branch, `\j` for the running jobs, `\$` for the `❯` colored by the
+//! exit code of the last command and `\\` for a backslash.
+
+use std::path::Path;
+
+use crate::{
+    git, options::ShellOption, user, variables, GREEN_FG_COLOR, JOBS, NESTING_UNICODE, OPTIONS,
+    PREVIOUS_EXIT_CODE, PROMPT_UNICODE, RED_FG_COLOR, RESET_FG_COLOR, YELLOW_FG_COLOR,
+};
+
+/// The template of the prompt before a command.
+pub const MAIN: &str = "\\l\\w \\g\\j\\$ ";
+
+/// The prompt of the lines continuing a command, unless `$PS2` is set.
+pub const CONTINUATION: &str = "> ";
+
+/// The prompt of the traced commands, unless `$PS4` is set.
+pub const TRACE: &str = "+ ";
+
+/// Builds the shell prompt given the shell's nesting level, the previous command's exit code, the
+/// running jobs, current directory and its git repository.
+///
+/// # Shell Prompt
+///
+/// Looks like this:
+///     "\[↳N in yellow if nested N levels deep\] \[~ or ~NAME if in the home or a named directory\]/\[full path\] \[BRANCH with a * if it has changes, with `set -o gitprompt`\] \[\[N\] if N jobs are running\] ❯ (green or red depending on exit code success or failure respectively)"
+///
+/// # Examples
+///
+/// ```text
+/// main("/Users/any/sandbox") // "~/sandbox ❯ " with the ❯ character green after a success
+/// main("/Users/any/sandbox") // "~/sandbox ❯ " with the ❯ character red after a failure
+/// main("/Users/any/sandbox") // "~/sandbox [1] ❯ " with a job running
+/// main("/Users/any/sandbox") // "↳2 ~/sandbox ❯ " run from another shell
+/// main("/Users/any/sandbox") // "~/sandbox main* ❯ " in a repository with changes
+/// ```
+pub async fn main(current_dir: &Path) -> String {
+    expand(MAIN, current_dir).await
+}
+
+/// Returns the prompt of the lines continuing a command, `$PS2` or [`CONTINUATION`].
+pub async fn continuation(current_dir: &Path) -> String {
+    let template = variables::get("PS2").unwrap_or_else(|| String::from(CONTINUATION));
+    expand(&template, current_dir).await
+}
+
+/// Returns the prompt of the commands traced with `set -x`, `$PS4` or [`TRACE`].
+pub async fn trace() -> String {
+    let template = variables::get("PS4").unwrap_or_else(|| String::from(TRACE));
+    expand(&template, &user::working_dir()).await
+}
+
+/// Replaces the escapes of `template` with what they stand for in `current_dir`. A backslash
+/// before any other character is kept.
+pub async fn expand(template: &str, current_dir: &Path) -> String {
+    let mut prompt = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('l') => prompt.push_str(&level()),
+            Some('w') => prompt.push_str(&user::display_path(current_dir)),
+            Some('g') => prompt.push_str(&branch(current_dir).await),
+            Some('j') => prompt.push_str(&jobs().await),
+            Some('$') => prompt.push_str(&symbol().await),
+            Some('\\') | None => prompt.push('\\'),
+            Some(c) => {
+                prompt.push('\\');
+                prompt.push(c);
+            }
+        }
+    }
+
+    prompt
+}
+
+/// The nesting level, once there is a shell below this one.
+fn level() -> String {
+    match std::env::var("SHLVL").map(|level| level.parse::<u32>()) {
+        Ok(Ok(level)) if level > 1 => {
+            format!("{YELLOW_FG_COLOR}{NESTING_UNICODE}{level}{RESET_FG_COLOR} ")
+        }
+        _ => String::new(),
+    }
+}
+
+/// The branch of the repository, cached until it may have changed.
+async fn branch(current_dir: &Path) -> String {
+    if !OPTIONS.lock().await.is_set(ShellOption::GitPrompt) {
+        return String::new();
+    }
+
+    match git::status(current_dir).await {
+        Some(status) if status.dirty => format!("{}* ", status.branch),
+        Some(status) => format!("{} ", status.branch),
+        None => String::new(),
+    }
+}
+
+/// The number of running jobs.
+async fn jobs() -> String {
+    match JOBS.lock().await.running() {
+        0 => String::new(),
+        running => format!("[{running}] "),
+    }
+}
+
+/// The prompt character, colored by the exit code of the last command, and the color reset.
+async fn symbol() -> String {
+    let color = match *PREVIOUS_EXIT_CODE.lock().await {
+        0 => GREEN_FG_COLOR.to_string(),
+        _ => RED_FG_COLOR.to_string(),
+    };
+
+    format!("{color}{PROMPT_UNICODE}{RESET_FG_COLOR}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn escapes() {
+        let dir = Path::new("/nonexistent/dir");
+
+        assert_eq!(expand("\\w> ", dir).await, "/nonexistent/dir> ");
+        assert_eq!(expand("\\\\w \\x\\", dir).await, "\\w \\x\\");
+        assert_eq!(expand("\\j+ ", dir).await, "+ ");
+    }
+}
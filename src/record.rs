@@ -0,0 +1,284 @@
+//! Session recording with the `record` builtin, in the [asciicast v2] format, so recordings can
+//! be audited or replayed with `asciinema play`.
+//!
+//! Every command is recorded as an input event, and as an output event showing the prompt and
+//! the command. When output is recorded too, the shell's standard output and error go through a
+//! pipe while a command runs, copied to both the terminal and the recording, so programs see a
+//! pipe instead of a terminal.
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::error;
+
+struct Recording {
+    path: PathBuf,
+    file: File,
+    start: Instant,
+    output: bool,
+}
+
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+fn recording() -> MutexGuard<'static, Option<Recording>> {
+    RECORDING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Starts recording to `path`, with the output of commands if `output` is set.
+///
+/// # Errors
+///
+/// This function will return an error if a recording is already running or the file could not
+/// be written.
+pub fn start(path: &Path, output: bool) -> io::Result<()> {
+    let mut recording = recording();
+
+    if let Some(recording) = recording.as_ref() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("already recording to {}", recording.path.display()),
+        ));
+    }
+
+    let mut file = File::create(path)?;
+
+    let (width, height) = termion::terminal_size()
+        .ok()
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .unwrap_or((80, 24));
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    writeln!(
+        file,
+        r#"{{"version": 2, "width": {width}, "height": {height}, "timestamp": {timestamp}}}"#
+    )?;
+
+    *recording = Some(Recording {
+        path: path.to_path_buf(),
+        file,
+        start: Instant::now(),
+        output,
+    });
+
+    Ok(())
+}
+
+/// Stops recording, returning the path of the recording if there was one.
+#[must_use]
+pub fn stop() -> Option<PathBuf> {
+    recording().take().map(|recording| recording.path)
+}
+
+/// Returns the path of the running recording and whether it records output.
+#[must_use]
+pub fn current() -> Option<(PathBuf, bool)> {
+    recording()
+        .as_ref()
+        .map(|recording| (recording.path.clone(), recording.output))
+}
+
+/// Records `command`, read after `prompt`, with every line it goes on on.
+pub fn command(prompt: &str, command: &str) {
+    if let Some(recording) = recording().as_mut() {
+        let result = event(
+            &mut recording.file,
+            recording.start,
+            "i",
+            &format!("{command}\n"),
+        )
+        .and_then(|()| {
+            event(
+                &mut recording.file,
+                recording.start,
+                "o",
+                &format!("{prompt}{}\r\n", command.replace('\n', "\r\n")),
+            )
+        });
+
+        if let Err(error) = result {
+            error!("record: {error}");
+        }
+    }
+}
+
+/// Writes an event of `kind` with `data` to `file`, timed from `start`.
+fn event(file: &mut File, start: Instant, kind: &str, data: &str) -> io::Result<()> {
+    writeln!(
+        file,
+        "[{:.6}, \"{kind}\", {}]",
+        start.elapsed().as_secs_f64(),
+        json_string(data)
+    )
+}
+
+fn json_string(string: &str) -> String {
+    let mut json = String::from('"');
+
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+/// How long the copier waits for output before looking whether the capture finished.
+const POLL_TIMEOUT_MS: i32 = 10;
+
+/// The output of a command being recorded, see [`capture`].
+pub struct Capture {
+    stdout: OwnedFd,
+    stderr: OwnedFd,
+    /// Set once the shell has its standard output and error back.
+    finished: Arc<AtomicBool>,
+    /// Told once the copier copied everything written before the capture finished.
+    drained: mpsc::Receiver<()>,
+}
+
+/// Starts sending the shell's standard output and error to the recording, if it records output.
+///
+/// The output is captured until [`Capture::finish`] is called.
+#[must_use]
+pub fn capture() -> Option<Capture> {
+    let (mut file, start) = match recording().as_ref() {
+        Some(recording) if recording.output => (recording.file.try_clone().ok()?, recording.start),
+        _ => return None,
+    };
+
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two descriptors.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return None;
+    }
+    // SAFETY: pipe2 succeeded, so both descriptors are open and ours.
+    let (reader, writer) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+    let stdout = dup(libc::STDOUT_FILENO)?;
+    let stderr = dup(libc::STDERR_FILENO)?;
+    let mut terminal = File::from(stdout.try_clone().ok()?);
+
+    // SAFETY: both descriptors are open.
+    unsafe {
+        libc::dup2(writer.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(writer.as_raw_fd(), libc::STDERR_FILENO);
+    }
+    drop(writer);
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let (drained, receiver) = mpsc::channel();
+    let copying = Arc::clone(&finished);
+
+    // the copier goes on after the capture finished, as long as a leftover process like a job
+    // or a daemon holds the pipe
+    std::thread::spawn(move || {
+        let mut reader = File::from(reader);
+        let mut buffer = [0; 4096];
+        // bytes of a character split between reads
+        let mut pending = Vec::new();
+        let mut drained = Some(drained);
+
+        loop {
+            // read before waiting, so that output written before the capture finished is seen
+            let finished = copying.load(Ordering::Acquire);
+            let mut poll = libc::pollfd {
+                fd: reader.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: `poll` is a single valid pollfd.
+            match unsafe { libc::poll(&raw mut poll, 1, POLL_TIMEOUT_MS) } {
+                0 => {
+                    if finished {
+                        if let Some(drained) = drained.take() {
+                            let _ = drained.send(());
+                        }
+                    }
+                    continue;
+                }
+                // interrupted by a signal
+                -1 => continue,
+                _ => {}
+            }
+
+            let Ok(read @ 1..) = reader.read(&mut buffer) else {
+                break;
+            };
+            let _ = terminal.write_all(&buffer[..read]);
+
+            pending.extend_from_slice(&buffer[..read]);
+            let valid = match std::str::from_utf8(&pending) {
+                Err(error) if error.error_len().is_none() => error.valid_up_to(),
+                _ => pending.len(),
+            };
+
+            let output = String::from_utf8_lossy(&pending[..valid]).replace('\n', "\r\n");
+            let _ = event(&mut file, start, "o", &output);
+            pending.drain(..valid);
+        }
+    });
+
+    Some(Capture {
+        stdout,
+        stderr,
+        finished,
+        drained: receiver,
+    })
+}
+
+impl Capture {
+    /// Gives the shell its standard output and error back, once what the command wrote is
+    /// copied. What processes it left running write later is still copied, without waiting for
+    /// them.
+    pub fn finish(self) {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+
+        // SAFETY: the saved descriptors are open.
+        unsafe {
+            libc::dup2(self.stdout.as_raw_fd(), libc::STDOUT_FILENO);
+            libc::dup2(self.stderr.as_raw_fd(), libc::STDERR_FILENO);
+        }
+
+        self.finished.store(true, Ordering::Release);
+        // fails right away if the copier already stopped, as nothing holds the pipe anymore
+        let _ = self.drained.recv();
+    }
+}
+
+/// Duplicates `fd`, closed on `exec`.
+fn dup(fd: RawFd) -> Option<OwnedFd> {
+    // SAFETY: fcntl doesn't touch memory, and a valid result is a new descriptor we own.
+    unsafe {
+        match libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) {
+            -1 => None,
+            fd => Some(OwnedFd::from_raw_fd(fd)),
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Redirections of file descriptors, like `< file`, `3< file`, `2>&1` or `3>&-`.
+//!
+//! Descriptors 0 to 2 are only redirected in the shell itself when `exec` asks for it.
+//! Descriptors 3 to 9 are kept in a table instead and only installed in spawned processes, as the
+//! shell's own files (the async runtime, signal pipes, ...) may use these numbers.
+//!
+//! A builtin or compound command runs with its descriptors scoped to the task running it, so
+//! that nothing else the shell does at the same time is redirected with it. It writes to them
+//! through [`stdout`] and [`stderr`], and the processes and subshells it starts get them in
+//! place of the shell's own.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::{File, OpenOptions},
+    future::Future,
+    io::{self, Write},
+    mem::ManuallyDrop,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{Arc, Mutex},
+};
+
+use crate::word::Word;
+
+/// The highest descriptor that can be redirected, as only single digits are recognized.
+pub const MAX_FD: RawFd = 9;
+
+/// Descriptors are duplicated at or above this number while redirecting, so that they can't be
+/// overwritten by a redirection.
+const FIRST_FREE_FD: RawFd = MAX_FD + 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Read,
+    Write,
+    Append,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Redirect {
+    /// `N<FILE`, `N>FILE` and `N>>FILE`, where `N` defaults to 0 for `<` and 1 otherwise.
+    /// `FILE` is expanded when the command runs, with [`Redirect::expand`].
+    File { fd: RawFd, path: Word, mode: Mode },
+    /// `N<&M` and `N>&M`.
+    Dup { fd: RawFd, source: RawFd },
+    /// `N<&-` and `N>&-`.
+    Close { fd: RawFd },
+    /// `N<<DELIMITER` and `N<<-DELIMITER`, reading `body` from a pipe.
+    HereDoc {
+        fd: RawFd,
+        delimiter: String,
+        body: String,
+    },
+}
+
+impl Redirect {
+    /// Builds a redirection from a scanned `operator`, like `2>&`, and the word after it.
+    ///
+    /// Returns `None` if `target` is not a valid descriptor for a `&` operator.
+    #[must_use]
+    pub fn new(operator: &str, target: Word) -> Option<Self> {
+        let (fd, operator) = split_fd(operator);
+
+        let redirect = match operator {
+            "<" => Self::File {
+                fd,
+                path: target,
+                mode: Mode::Read,
+            },
+            ">" => Self::File {
+                fd,
+                path: target,
+                mode: Mode::Write,
+            },
+            ">>" => Self::File {
+                fd,
+                path: target,
+                mode: Mode::Append,
+            },
+            "<&" | ">&" if target.text() == "-" => Self::Close { fd },
+            "<&" | ">&" => Self::Dup {
+                fd,
+                source: target
+                    .text()
+                    .parse()
+                    .ok()
+                    .filter(|source| (0..=MAX_FD).contains(source))?,
+            },
+            _ => return None,
+        };
+
+        Some(redirect)
+    }
+
+    /// Returns the redirection with the path of a file expanded, as it is opened at this point.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the message of a `${NAME:?WORD}` parameter that isn't set, or
+    /// of a path that doesn't expand to exactly one field.
+    pub async fn expand(&self) -> Result<Self, String> {
+        let Self::File { fd, path, mode } = self else {
+            return Ok(self.clone());
+        };
+
+        let [field] = <[String; 1]>::try_from(path.fields().await?)
+            .map_err(|_| format!("{}: ambiguous redirect", path.text()))?;
+
+        Ok(Self::File {
+            fd: *fd,
+            path: Word::from(field),
+            mode: *mode,
+        })
+    }
+
+    /// Builds a here-document from a scanned `operator`, like `<<` or `3<<-`, its delimiter
+    /// and its body, with the tabs already stripped for `<<-`.
+    #[must_use]
+    pub fn here_doc(operator: &str, delimiter: &str, body: String) -> Self {
+        let (fd, _) = split_fd(operator);
+
+        Self::HereDoc {
+            fd,
+            delimiter: delimiter.to_string(),
+            body,
+        }
+    }
+}
+
+/// Splits the descriptor off `operator`, defaulting to 1 for output and 0 for input.
+fn split_fd(operator: &str) -> (RawFd, &str) {
+    let digits = operator.len()
+        - operator
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .len();
+    let (fd, operator) = operator.split_at(digits);
+
+    (
+        fd.parse().unwrap_or(i32::from(operator.starts_with('>'))),
+        operator,
+    )
+}
+
+impl Display for Redirect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File { fd, path, mode } => {
+                let (default, operator) = match mode {
+                    Mode::Read => (libc::STDIN_FILENO, "<"),
+                    Mode::Write => (libc::STDOUT_FILENO, ">"),
+                    Mode::Append => (libc::STDOUT_FILENO, ">>"),
+                };
+                if *fd != default {
+                    write!(f, "{fd}")?;
+                }
+                write!(f, "{operator}{path}")
+            }
+            Self::Dup {
+                fd: libc::STDIN_FILENO,
+                source,
+            } => write!(f, "<&{source}"),
+            Self::Dup { fd, source } => write!(f, "{fd}>&{source}"),
+            Self::Close { fd } => write!(f, "{fd}>&-"),
+            Self::HereDoc { fd, delimiter, .. } => {
+                if *fd != libc::STDIN_FILENO {
+                    write!(f, "{fd}")?;
+                }
+                write!(f, "<<{delimiter}")
+            }
+        }
+    }
+}
+
+/// Descriptors 3 to [`MAX_FD`] opened with `exec`, by the number they are installed as.
+static TABLE: Mutex<BTreeMap<RawFd, OwnedFd>> = Mutex::new(BTreeMap::new());
+
+tokio::task_local! {
+    /// The descriptors of the builtin or compound command being run, in place of the shell's
+    /// own.
+    static SCOPED: Arc<Plan>;
+}
+
+/// The descriptors a process gets, resolved from a list of redirections.
+pub struct Plan {
+    /// Each descriptor is a duplicate of a source, or closed if `None`.
+    fds: BTreeMap<RawFd, Option<RawFd>>,
+    /// Keeps the sources open until the plan is installed.
+    sources: Vec<OwnedFd>,
+    /// The descriptors named by the redirections, rather than inherited.
+    redirected: Vec<RawFd>,
+}
+
+impl Plan {
+    /// Resolves `redirects`, in order, on top of the descriptors of the command being run and
+    /// the ones opened with `exec`. The paths of files are opened as they are, so they should
+    /// have been expanded before.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a file could not be opened or a duplicated
+    /// descriptor is not open.
+    pub fn new(redirects: &[Redirect]) -> io::Result<Self> {
+        Self::piped(Vec::new(), redirects)
+    }
+
+    /// Resolves `redirects` like [`Plan::new`], on top of `pipes` given as the descriptors they
+    /// replace, so that `2>&1` in a pipeline refers to the pipe.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a file could not be opened or a duplicated
+    /// descriptor is not open.
+    pub fn piped(pipes: Vec<(RawFd, OwnedFd)>, redirects: &[Redirect]) -> io::Result<Self> {
+        let mut plan = Self {
+            fds: BTreeMap::new(),
+            sources: Vec::new(),
+            redirected: Vec::new(),
+        };
+
+        match SCOPED.try_with(|scoped| scoped.fds.clone()) {
+            Ok(fds) => {
+                for (fd, source) in fds {
+                    plan.set(fd, source.map(dup_high).transpose()?);
+                }
+            }
+            Err(_) => {
+                for (fd, source) in table().iter() {
+                    plan.set(*fd, Some(dup_high(source.as_raw_fd())?));
+                }
+            }
+        }
+
+        for (fd, pipe) in pipes {
+            plan.set(fd, Some(dup_high(pipe.as_raw_fd())?));
+        }
+
+        for redirect in redirects {
+            match redirect {
+                Redirect::File { fd, .. }
+                | Redirect::Dup { fd, .. }
+                | Redirect::Close { fd }
+                | Redirect::HereDoc { fd, .. } => plan.redirected.push(*fd),
+            }
+
+            match redirect {
+                Redirect::File { fd, path, mode } => {
+                    let path = path.text();
+                    let file = OpenOptions::new()
+                        .read(*mode == Mode::Read)
+                        .write(*mode == Mode::Write)
+                        .append(*mode == Mode::Append)
+                        .create(*mode != Mode::Read)
+                        .truncate(*mode == Mode::Write)
+                        .open(&path)
+                        .map_err(|error| {
+                            io::Error::new(error.kind(), format!("{path}: {error}"))
+                        })?;
+
+                    plan.set(*fd, Some(dup_high(file.as_raw_fd())?));
+                }
+                Redirect::Dup { fd, source } => {
+                    let open = match plan.fds.get(source) {
+                        Some(open) => *open,
+                        // the shell's own standard streams
+                        None => (*source <= 2).then_some(*source),
+                    };
+                    let open = open
+                        .and_then(|open| dup_high(open).ok())
+                        .ok_or_else(|| bad_fd(*source))?;
+
+                    plan.set(*fd, Some(open));
+                }
+                Redirect::Close { fd } => plan.set(*fd, None),
+                Redirect::HereDoc { fd, body, .. } => {
+                    let (reader, mut writer) = io::pipe()?;
+                    // a thread, so that a body larger than the pipe doesn't block
+                    let body = body.clone();
+                    std::thread::spawn(move || writer.write_all(body.as_bytes()));
+
+                    plan.set(*fd, Some(dup_high(reader.as_raw_fd())?));
+                }
+            }
+        }
+
+        Ok(plan)
+    }
+
+    fn set(&mut self, fd: RawFd, source: Option<OwnedFd>) {
+        self.fds.insert(fd, source.as_ref().map(AsRawFd::as_raw_fd));
+        self.sources.extend(source);
+    }
+
+    /// Returns a function installing the plan, to be run in a forked process before `exec`.
+    ///
+    /// The plan must be kept alive until the process is spawned.
+    pub fn installer(&self) -> impl FnMut() -> io::Result<()> + Send + Sync + 'static {
+        let fds = self.fds.clone();
+
+        move || {
+            for (fd, source) in &fds {
+                // SAFETY: dup2 and close are async-signal-safe, and the sources are above the
+                // descriptors being replaced, so they can't be overwritten.
+                let result = unsafe {
+                    match source {
+                        Some(source) => libc::dup2(*source, *fd),
+                        None => libc::close(*fd),
+                    }
+                };
+
+                if result == -1 && source.is_some() {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Runs `future` with the descriptors of the plan in place of the shell's own, for a builtin
+    /// or compound command.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        SCOPED.scope(Arc::new(self), future).await
+    }
+
+    /// Installs the descriptors named by the redirections of the plan in the shell itself, for
+    /// good.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a standard stream couldn't be replaced.
+    pub fn apply_to_shell(self) -> io::Result<()> {
+        self.install(&self.redirected)
+    }
+
+    /// Installs the descriptors of the command being run in the shell itself, for good, in a
+    /// forked subshell, which has nothing else running in it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a standard stream couldn't be replaced.
+    pub(crate) fn inherit(scoped: Option<&Self>) -> io::Result<()> {
+        match scoped {
+            Some(plan) => plan.install(&plan.fds.keys().copied().collect::<Vec<_>>()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the descriptors of the command being run, if they aren't the shell's own.
+    pub(crate) fn scoped() -> Option<Arc<Self>> {
+        SCOPED.try_with(Arc::clone).ok()
+    }
+
+    /// Installs the descriptors `fds` of the plan in the shell itself.
+    fn install(&self, fds: &[RawFd]) -> io::Result<()> {
+        io::stdout().flush()?;
+        io::stderr().flush()?;
+
+        let mut table = table();
+
+        for (&fd, &source) in self.fds.iter().filter(|(fd, _)| fds.contains(fd)) {
+            if fd > 2 {
+                match source {
+                    Some(source) => {
+                        table.insert(fd, dup_high(source)?);
+                    }
+                    None => {
+                        table.remove(&fd);
+                    }
+                }
+                continue;
+            }
+
+            // SAFETY: the descriptors are valid or the calls fail harmlessly.
+            let result = unsafe {
+                match source {
+                    Some(source) => libc::dup2(source, fd),
+                    None => libc::close(fd),
+                }
+            };
+
+            if result == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A standard stream of the command being run, written to the descriptor it is redirected to,
+/// or to the shell's own.
+pub struct Stream {
+    fd: RawFd,
+}
+
+/// Returns the standard output of the command being run.
+#[must_use]
+pub const fn stdout() -> Stream {
+    Stream {
+        fd: libc::STDOUT_FILENO,
+    }
+}
+
+/// Returns the standard error of the command being run.
+#[must_use]
+pub const fn stderr() -> Stream {
+    Stream {
+        fd: libc::STDERR_FILENO,
+    }
+}
+
+/// Returns a duplicate of the standard input of the command being run, to read it without the
+/// buffer of [`io::stdin`], which may hold the shell's own input.
+///
+/// # Errors
+///
+/// This function will return an error if the standard input is closed.
+pub fn stdin() -> io::Result<OwnedFd> {
+    match Target::of(libc::STDIN_FILENO) {
+        Target::Shell(fd) | Target::Redirected(fd) => dup_high(fd),
+        Target::Closed => Err(bad_fd(libc::STDIN_FILENO)),
+    }
+}
+
+/// Returns whether the standard input of the command being run is a terminal.
+#[must_use]
+pub fn stdin_is_terminal() -> bool {
+    Stream {
+        fd: libc::STDIN_FILENO,
+    }
+    .is_terminal()
+}
+
+/// Where a descriptor of the command being run goes.
+enum Target {
+    /// The shell's own descriptor.
+    Shell(RawFd),
+    /// The descriptor it is redirected to.
+    Redirected(RawFd),
+    Closed,
+}
+
+impl Target {
+    fn of(fd: RawFd) -> Self {
+        match SCOPED.try_with(|plan| plan.fds.get(&fd).copied()) {
+            Ok(Some(Some(source))) => Self::Redirected(source),
+            Ok(Some(None)) => Self::Closed,
+            _ => Self::Shell(fd),
+        }
+    }
+}
+
+impl Stream {
+    /// Returns whether the stream is a terminal.
+    #[must_use]
+    pub fn is_terminal(&self) -> bool {
+        match Target::of(self.fd) {
+            // SAFETY: isatty only looks at the descriptor.
+            Target::Shell(fd) | Target::Redirected(fd) => unsafe { libc::isatty(fd) == 1 },
+            Target::Closed => false,
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match Target::of(self.fd) {
+            Target::Shell(libc::STDERR_FILENO) => io::stderr().write(buf),
+            Target::Shell(_) => io::stdout().write(buf),
+            // SAFETY: the descriptor is kept open by the plan while its scope runs, and isn't
+            // closed when the file is dropped.
+            Target::Redirected(fd) => {
+                ManuallyDrop::new(unsafe { File::from_raw_fd(fd) }).write(buf)
+            }
+            Target::Closed => Err(bad_fd(self.fd)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match Target::of(self.fd) {
+            Target::Shell(libc::STDERR_FILENO) => io::stderr().flush(),
+            Target::Shell(_) => io::stdout().flush(),
+            Target::Redirected(_) | Target::Closed => Ok(()),
+        }
+    }
+}
+
+/// Locks the table of the descriptors opened by `exec` in the shell itself.
+pub(crate) fn table() -> std::sync::MutexGuard<'static, BTreeMap<RawFd, OwnedFd>> {
+    TABLE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Duplicates `fd` above [`MAX_FD`], closed on `exec`.
+fn dup_high(fd: RawFd) -> io::Result<OwnedFd> {
+    // SAFETY: fcntl doesn't touch memory, and a valid result is a new descriptor we own.
+    unsafe {
+        match libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, FIRST_FREE_FD) {
+            -1 => Err(io::Error::last_os_error()),
+            fd => Ok(OwnedFd::from_raw_fd(fd)),
+        }
+    }
+}
+
+fn bad_fd(fd: RawFd) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{fd}: bad file descriptor"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str) -> Word {
+        Word::from(text.to_string())
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            Redirect::new(">>", word("f")),
+            Some(Redirect::File {
+                fd: 1,
+                path: word("f"),
+                mode: Mode::Append
+            })
+        );
+        assert_eq!(
+            Redirect::new("3<", word("f")),
+            Some(Redirect::File {
+                fd: 3,
+                path: word("f"),
+                mode: Mode::Read
+            })
+        );
+        assert_eq!(
+            Redirect::new("2>&", word("1")),
+            Some(Redirect::Dup { fd: 2, source: 1 })
+        );
+        assert_eq!(
+            Redirect::new("<&", word("-")),
+            Some(Redirect::Close { fd: 0 })
+        );
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        assert_eq!(Redirect::new(">&", word("x")), None);
+        assert_eq!(Redirect::new(">&", word("10")), None);
+    }
+}
@@ -0,0 +1,155 @@
+//! The resources used by the foreground processes of a line, printed after it with
+//! `set -o cmdstats`.
+//!
+//! With the option set, processes are waited for with `wait4`, which also returns what they
+//! used. Their usage adds up until [`Command::run`](crate::Command::run) puts it in the
+//! [`ExecutionReport`] of the line.
+
+use std::{
+    fmt::Display,
+    ops::Add,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+/// What running a line took.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExecutionReport {
+    /// How long the line ran.
+    pub duration: Duration,
+    /// The resources used by its foreground processes, if they were waited for with `wait4`.
+    pub usage: Option<Usage>,
+}
+
+impl ExecutionReport {
+    /// Returns the report of a line that didn't run, given the usage from before it started.
+    pub(crate) fn unrun(before: Option<Usage>) -> Self {
+        Self {
+            duration: Duration::default(),
+            usage: finish(before),
+        }
+    }
+}
+
+impl Display for ExecutionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.2}s real", self.duration.as_secs_f64())?;
+
+        if let Some(usage) = self.usage {
+            write!(
+                f,
+                ", {:.2}s user, {:.2}s sys, {} max RSS",
+                usage.user.as_secs_f64(),
+                usage.system.as_secs_f64(),
+                kibibytes(usage.max_rss)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The resources used by processes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// The largest resident set size of any of them, in KiB.
+    pub max_rss: u64,
+    /// The CPU time spent in user mode.
+    pub user: Duration,
+    /// The CPU time spent in the kernel.
+    pub system: Duration,
+}
+
+impl Add for Usage {
+    type Output = Self;
+
+    /// Returns the usage of processes along with the ones of `other`.
+    fn add(self, other: Self) -> Self {
+        Self {
+            max_rss: self.max_rss.max(other.max_rss),
+            user: self.user + other.user,
+            system: self.system + other.system,
+        }
+    }
+}
+
+impl From<&libc::rusage> for Usage {
+    fn from(rusage: &libc::rusage) -> Self {
+        let duration = |time: libc::timeval| {
+            Duration::from_secs(u64::try_from(time.tv_sec).unwrap_or_default())
+                + Duration::from_micros(u64::try_from(time.tv_usec).unwrap_or_default())
+        };
+
+        Self {
+            max_rss: u64::try_from(rusage.ru_maxrss).unwrap_or_default(),
+            user: duration(rusage.ru_utime),
+            system: duration(rusage.ru_stime),
+        }
+    }
+}
+
+/// The usage of the processes waited for since the line started running.
+static USAGE: Mutex<Option<Usage>> = Mutex::new(None);
+
+fn usage() -> MutexGuard<'static, Option<Usage>> {
+    USAGE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Adds the usage of a foreground process that exited.
+pub(crate) fn record(process: Usage) {
+    let mut usage = usage();
+    *usage = Some(usage.map_or(process, |usage| usage + process));
+}
+
+/// Starts counting the usage of a line, returning the usage counted so far, for a line run from
+/// another one like a command substitution.
+pub(crate) fn start() -> Option<Usage> {
+    usage().take()
+}
+
+/// Returns the usage of the line started with [`start`], which also counts towards the line it
+/// was run from.
+pub(crate) fn finish(before: Option<Usage>) -> Option<Usage> {
+    let mut usage = usage();
+    let line = usage.take();
+
+    *usage = match (before, line) {
+        (Some(before), Some(line)) => Some(before + line),
+        (before, line) => before.or(line),
+    };
+    line
+}
+
+/// Waits for the process `pid` with `wait4`, returning its raw wait status and its usage.
+pub(crate) fn wait(pid: libc::pid_t) -> std::io::Result<(i32, Usage)> {
+    let mut status = 0;
+    // SAFETY: an all-zero `rusage` is valid, only plain integers being in it.
+    let mut rusage = unsafe { std::mem::zeroed::<libc::rusage>() };
+
+    loop {
+        // SAFETY: `status` and `rusage` are valid for writes.
+        if unsafe { libc::wait4(pid, &raw mut status, 0, &raw mut rusage) } != -1 {
+            return Ok((status, Usage::from(&rusage)));
+        }
+
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+/// Formats `kibibytes` with the largest unit it has at least one of.
+fn kibibytes(kibibytes: u64) -> String {
+    #[allow(clippy::cast_precision_loss)]
+    let mut size = kibibytes as f64;
+
+    for unit in ["KiB", "MiB"] {
+        if size < 1024.0 {
+            return format!("{size:.1} {unit}");
+        }
+        size /= 1024.0;
+    }
+
+    format!("{size:.1} GiB")
+}
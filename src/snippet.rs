@@ -0,0 +1,92 @@
+//! Named command snippets, saved with the `snip` builtin and inserted in the line editor with
+//! ALT+S.
+//!
+//! Snippets are stored in the `snippets` file of the configuration directory, one per line as
+//! the name and the command separated by a tab. Commands may contain placeholders like
+//! `{{host}}`, which the editor selects one after the other to be typed over.
+
+use std::{io, path::PathBuf};
+
+use crate::user;
+
+const SNIPPETS: &str = "snippets";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snippet {
+    pub name: String,
+    pub command: String,
+}
+
+fn path() -> Option<PathBuf> {
+    user::config_dir().map(|config_dir| config_dir.join(SNIPPETS))
+}
+
+/// Reads the saved snippets, sorted by name.
+///
+/// # Errors
+///
+/// This function will return an error if the snippets file exists but could not be read.
+pub fn load() -> io::Result<Vec<Snippet>> {
+    let Some(path) = path() else {
+        return Ok(Vec::new());
+    };
+
+    let snippets = match std::fs::read_to_string(path) {
+        Ok(snippets) => snippets,
+        // nothing was saved yet
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+
+    let mut snippets = snippets
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(name, command)| Snippet {
+            name: name.to_string(),
+            command: command.to_string(),
+        })
+        .collect::<Vec<_>>();
+    snippets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(snippets)
+}
+
+/// Replaces the saved snippets with `snippets`.
+///
+/// # Errors
+///
+/// This function will return an error if the configuration directory could not be found or the
+/// snippets file could not be written.
+pub fn save(snippets: &[Snippet]) -> io::Result<()> {
+    let path = path().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not find the configuration directory",
+        )
+    })?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut contents = String::new();
+    for snippet in snippets {
+        contents.push_str(&snippet.name);
+        contents.push('\t');
+        contents.push_str(&snippet.command.replace('\n', " "));
+        contents.push('\n');
+    }
+
+    std::fs::write(path, contents)
+}
+
+/// Returns the character range of the first placeholder in `line` at or after `from`.
+#[must_use]
+pub fn next_placeholder(line: &[char], from: usize) -> Option<(usize, usize)> {
+    let start = (from..line.len().saturating_sub(1))
+        .find(|&index| line[index] == '{' && line[index + 1] == '{')?;
+    let end = (start + 2..line.len().saturating_sub(1))
+        .find(|&index| line[index] == '}' && line[index + 1] == '}')?;
+
+    Some((start, end + 2))
+}
@@ -0,0 +1,196 @@
+//! Subshells, running `( LIST )` and command substitutions apart from the shell.
+//!
+//! A subshell is a forked child of the shell, so it starts with everything the shell knows, and
+//! nothing it changes, like the working directory, the environment, shell variables, aliases or
+//! options, is seen by the shell or by other subshells. The threads of the shell's async runtime
+//! aren't forked with it, so the child runs its list in a runtime of its own, on a new thread.
+//!
+//! `exit` in a subshell, or in a background job, only stops running its list.
+
+use std::{
+    cell::Cell,
+    future::Future,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    command, errexit, error, job,
+    list::List,
+    options::ShellOption,
+    priority::{self, Priority},
+    redirect::{self, Plan},
+    rusage, ALIASES, JOBS, OPTIONS, STATUS,
+};
+
+/// Whether the process is a subshell forked from the shell.
+static FORKED: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    /// Set while running a subshell or a job, to the code given to `exit` once it ran.
+    static EXIT: Cell<Option<i32>>;
+}
+
+/// What a subshell inherits from the task forking it, besides the state of the process.
+struct Context {
+    priority: Priority,
+    /// Whether the failure of the subshell is tested, so it doesn't stop it with `set -e`.
+    tested: bool,
+    /// The descriptors of the command forking it, installed in the subshell for good.
+    descriptors: Option<Arc<Plan>>,
+}
+
+impl Context {
+    fn current() -> Self {
+        Self {
+            priority: priority::current(),
+            tested: errexit::is_tested(),
+            descriptors: Plan::scoped(),
+        }
+    }
+
+    /// Runs `future` in the context.
+    async fn scope<F: Future>(self, future: F) -> F::Output {
+        let future = priority::scope(self.priority, future);
+
+        if self.tested {
+            errexit::tested(future).await
+        } else {
+            future.await
+        }
+    }
+}
+
+/// Forks a subshell running the future returned by `run`, after `prepare` ran in it, like
+/// replacing its standard output, and returns the process ID of the child.
+///
+/// # Errors
+///
+/// This function will return an error if the shell could not be forked.
+pub(crate) async fn fork<F, R>(run: F, prepare: impl FnOnce() + Send) -> io::Result<u32>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Future<Output = i32>,
+{
+    let context = Context::current();
+
+    // only the forking thread is left in the child, so a lock held elsewhere would stay locked
+    // there for good
+    let status = STATUS.lock().await;
+    let mut jobs = JOBS.lock().await;
+    let options = OPTIONS.lock().await;
+    let aliases = ALIASES.lock().await;
+    let table = redirect::table();
+
+    // buffered output would be written by both processes otherwise
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+
+    // SAFETY: the child only runs `prepare` and a thread of its own, then exits without
+    // returning to the caller.
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => {
+            FORKED.store(true, Ordering::Relaxed);
+            job::forget(&mut jobs);
+            drop((table, aliases, options, jobs, status));
+            if let Err(error) = Plan::inherit(context.descriptors.as_deref()) {
+                error!("{error}");
+            }
+            prepare();
+
+            // CTRL+C stops a subshell in the foreground, instead of being left to the shell
+            let disposition = if job::in_background() {
+                libc::SIG_IGN
+            } else {
+                libc::SIG_DFL
+            };
+            // SAFETY: setting the disposition of a signal touches no memory.
+            unsafe {
+                libc::signal(libc::SIGINT, disposition);
+            }
+
+            let code =
+                std::thread::spawn(move || {
+                    match tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                    {
+                        Ok(runtime) => runtime.block_on(context.scope(run())),
+                        Err(error) => {
+                            error!("{error}");
+                            1
+                        }
+                    }
+                })
+                .join()
+                .unwrap_or(1);
+
+            let _ = io::stdout().flush();
+            let _ = io::stderr().flush();
+            // SAFETY: `_exit` leaves without running the shell's exit handlers.
+            unsafe { libc::_exit(code) }
+        }
+        pid => Ok(pid.unsigned_abs()),
+    }
+}
+
+/// Returns whether the process is a subshell, or the subshell of a job.
+pub(crate) fn is_forked() -> bool {
+    FORKED.load(Ordering::Relaxed)
+}
+
+/// Waits for the subshell `pid` and returns its exit code, recording its usage with
+/// `set -o cmdstats`.
+pub(crate) async fn wait(pid: u32) -> i32 {
+    let measure = !job::in_background() && OPTIONS.lock().await.is_set(ShellOption::CmdStats);
+
+    match command::wait(pid).await {
+        Ok((status, usage)) => {
+            if measure {
+                rusage::record(usage);
+            }
+            command::exit_code(status)
+        }
+        Err(error) => {
+            error!("{error}");
+            1
+        }
+    }
+}
+
+/// Runs `list` in a subshell, returning its exit code or the one given to `exit`.
+pub(crate) async fn run(list: &List) -> i32 {
+    let list = list.clone();
+
+    match fork(move || async move { interpret(&list).await }, || {}).await {
+        Ok(pid) => wait(pid).await,
+        Err(error) => {
+            error!("{error}");
+            1
+        }
+    }
+}
+
+/// Runs `list` until it ends or runs `exit`, returning the exit code, without a subshell.
+pub(crate) async fn interpret(list: &List) -> i32 {
+    EXIT.scope(Cell::new(None), async {
+        let code = list.interpret().await;
+        EXIT.with(Cell::get).unwrap_or(code)
+    })
+    .await
+}
+
+/// Makes the subshell or job being run stop with `code`, returning `false` outside of one.
+pub(crate) fn exit(code: i32) -> bool {
+    EXIT.try_with(|exit| exit.set(Some(code))).is_ok()
+}
+
+/// Returns whether the subshell or job being run ran `exit`, or `set -e` stopped the line being
+/// run, so that nothing else runs.
+pub(crate) fn is_exiting() -> bool {
+    EXIT.try_with(Cell::get).ok().flatten().is_some() || errexit::is_stopped()
+}
@@ -0,0 +1,57 @@
+//! Command substitution, replacing `$(COMMAND)` and `` `COMMAND` `` with the output of `COMMAND`.
+//!
+//! `COMMAND` runs in a subshell whose stdout is a pipe, read by the shell until the subshell and
+//! whatever it started close it.
+
+use std::{
+    io::{self, Read},
+    os::fd::AsRawFd,
+};
+
+use crate::{error, list::List, subshell};
+
+/// Runs `list`, returning what it wrote to stdout without the trailing newlines.
+pub(crate) async fn output(list: &List) -> String {
+    let (mut reader, writer) = match io::pipe() {
+        Ok(pipe) => pipe,
+        Err(error) => {
+            error!("{error}");
+            return String::new();
+        }
+    };
+
+    let list = list.clone();
+    let forked = subshell::fork(
+        move || async move { subshell::interpret(&list).await },
+        || {
+            // SAFETY: the pipe is open, and stdout is replaced by it in the child only.
+            unsafe {
+                libc::dup2(writer.as_raw_fd(), libc::STDOUT_FILENO);
+            }
+        },
+    )
+    .await;
+    // the pipe ends once the subshell, holding the only writer left, exits
+    drop(writer);
+
+    let pid = match forked {
+        Ok(pid) => pid,
+        Err(error) => {
+            error!("{error}");
+            return String::new();
+        }
+    };
+
+    let output = tokio::task::spawn_blocking(move || {
+        let mut output = Vec::new();
+        let _ = reader.read_to_end(&mut output);
+        output
+    })
+    .await
+    .unwrap_or_default();
+    subshell::wait(pid).await;
+
+    String::from_utf8_lossy(&output)
+        .trim_end_matches('\n')
+        .to_string()
+}
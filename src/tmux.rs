@@ -0,0 +1,61 @@
+//! Integration with tmux, used when rshell runs inside of it.
+
+use std::{
+    io::{self, Write},
+    process::Command,
+    sync::OnceLock,
+};
+
+/// Whether rshell runs inside of tmux.
+#[must_use]
+pub fn is_inside() -> bool {
+    std::env::var_os("TMUX").is_some()
+}
+
+/// Whether popups can be opened, which needs tmux 3.2 or later.
+#[must_use]
+pub fn has_popups() -> bool {
+    static HAS_POPUPS: OnceLock<bool> = OnceLock::new();
+
+    is_inside()
+        && *HAS_POPUPS.get_or_init(|| {
+            let Ok(output) = Command::new("tmux").arg("-V").output() else {
+                return false;
+            };
+
+            // "tmux 3.3a", "tmux next-3.4"
+            let version = String::from_utf8_lossy(&output.stdout);
+            let version = version.trim().rsplit([' ', '-']).next().unwrap_or_default();
+
+            let mut numbers = version.split('.').map(|number| {
+                number
+                    .chars()
+                    .take_while(char::is_ascii_digit)
+                    .collect::<String>()
+                    .parse::<u32>()
+                    .unwrap_or(0)
+            });
+
+            (numbers.next().unwrap_or(0), numbers.next().unwrap_or(0)) >= (3, 2)
+        })
+}
+
+/// Runs the shell command `command` in a popup over the current pane, waiting for it to exit.
+///
+/// # Errors
+///
+/// This function will return an error if `tmux` couldn't be run.
+pub fn popup(command: &str) -> io::Result<()> {
+    Command::new("tmux")
+        .args(["display-popup", "-E", "-w", "80%", "-h", "60%", command])
+        .status()
+        .map(|_| ())
+}
+
+/// Sets the title of the current pane to `title`, if inside of tmux and writing to it.
+pub fn set_pane_title(title: &str) {
+    if is_inside() && termion::is_tty(&io::stdout()) {
+        print!("\x1b]2;{title}\x1b\\");
+        let _ = io::stdout().flush();
+    }
+}
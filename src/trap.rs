@@ -0,0 +1,86 @@
+//! Traps, commands set with `trap` to run on a condition. Only `EXIT` is supported, run when the
+//! shell exits.
+//!
+//! The shell always exits through [`exit`], which waits for the jobs of a non-interactive shell,
+//! runs the `EXIT` trap, then `~/.rshell_logout` for an interactive shell, a place for cleanup
+//! like stopping an ssh-agent, and writes the history.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, MutexGuard, PoisonError,
+};
+
+use async_recursion::async_recursion;
+
+use crate::{history, job, user, Command, RSHELL_LOGOUT, STATUS};
+
+/// The conditions a trap can be set on, with their other names.
+pub const CONDITIONS: &[&str] = &["EXIT", "0"];
+
+static EXIT_TRAP: Mutex<Option<String>> = Mutex::new(None);
+
+/// Whether the shell is exiting, for `exit` in the trap or the logout file to exit right away.
+static EXITING: AtomicBool = AtomicBool::new(false);
+
+fn exit_trap() -> MutexGuard<'static, Option<String>> {
+    EXIT_TRAP.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Sets the command run on exit, or removes it without one.
+pub fn set_exit(command: Option<String>) {
+    *exit_trap() = command;
+}
+
+/// Returns the command run on exit.
+#[must_use]
+pub fn get_exit() -> Option<String> {
+    exit_trap().clone()
+}
+
+/// Exits the shell with `code`, after waiting for the jobs of a script, then running the `EXIT`
+/// trap and, in an interactive shell, `~/.rshell_logout`.
+pub async fn exit(code: i32) -> ! {
+    if EXITING.swap(true, Ordering::SeqCst) {
+        history::flush();
+        std::process::exit(code);
+    }
+
+    // an interactive shell leaves its jobs running once `exit` was confirmed
+    let interactive = STATUS.lock().await.interactive;
+    if !interactive {
+        job::wait(&[]).await;
+    }
+
+    let trap = exit_trap().take();
+    if let Some(trap) = trap {
+        run(&trap, "EXIT").await;
+    }
+
+    if interactive {
+        if let Some(home_dir) = user::home_dir() {
+            if let Ok(logout) = tokio::fs::read_to_string(home_dir.join(RSHELL_LOGOUT)).await {
+                for line in logout.lines() {
+                    if !run(line, RSHELL_LOGOUT).await {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    history::flush();
+    std::process::exit(code);
+}
+
+/// Runs `command`, reporting a syntax error as coming from `origin`, and returns whether it
+/// could be parsed.
+#[async_recursion]
+async fn run(command: &str, origin: &str) -> bool {
+    match Command::run(command).await {
+        (Ok(_), _) => true,
+        (Err(error), _) => {
+            crate::error!("{origin}: {error}");
+            false
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Information about the user running the shell, which doesn't rely on `$HOME` being set.
+
+use std::{
+    ffi::{CStr, CString, OsStr},
+    io::{BufRead, Write},
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::named_dirs;
+
+/// An entry of the passwd database.
+struct Passwd {
+    name: String,
+    dir: PathBuf,
+}
+
+/// Returns the home directory: `$HOME` if set, otherwise the one in the passwd database.
+#[must_use]
+pub fn home_dir() -> Option<PathBuf> {
+    match std::env::var_os("HOME") {
+        Some(home) if !home.is_empty() => Some(PathBuf::from(home)),
+        _ => passwd().map(|passwd| passwd.dir),
+    }
+}
+
+/// Replaces a leading `~` of `path` with the home directory, and `~NAME` with the directory named
+/// `NAME` or the home directory of the user `NAME`, up to the first `/`. `path` is kept as it is
+/// if there is no such directory.
+#[must_use]
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let Some(rest) = path.strip_prefix('~') else {
+        return PathBuf::from(path);
+    };
+    let (name, rest) = rest.split_once('/').unwrap_or((rest, ""));
+
+    let home_dir = if name.is_empty() {
+        home_dir()
+    } else {
+        named_dirs::get(name).or_else(|| passwd_of(name).map(|passwd| passwd.dir))
+    };
+
+    match home_dir {
+        Some(home_dir) if rest.is_empty() => home_dir,
+        Some(home_dir) => home_dir.join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Shortens `path` for the prompt, with `~` for the home directory or `~NAME` for a named
+/// directory, whichever is the longest at the start of it.
+#[must_use]
+pub fn display_path(path: &Path) -> String {
+    let named = named_dirs::all()
+        .into_iter()
+        .map(|(name, dir)| (format!("~{name}"), dir));
+    let shortest = home_dir()
+        .map(|home_dir| (String::from("~"), home_dir))
+        .into_iter()
+        .chain(named)
+        .filter_map(|(name, dir)| Some((name, path.strip_prefix(&dir).ok()?, dir)))
+        .max_by_key(|(_, _, dir)| dir.components().count());
+
+    match shortest {
+        Some((name, rest, _)) if rest.as_os_str().is_empty() => name,
+        Some((name, rest, _)) => format!("{name}/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Returns the working directory, or `$PWD` if it was removed, as the shell stays in it until
+/// the directory changes. Without an absolute `$PWD`, falls back to the home directory, then `/`.
+#[must_use]
+pub fn working_dir() -> PathBuf {
+    std::env::current_dir().unwrap_or_else(|_| {
+        std::env::var_os("PWD")
+            .map(PathBuf::from)
+            .filter(|pwd| pwd.is_absolute())
+            .or_else(home_dir)
+            .unwrap_or_else(|| PathBuf::from("/"))
+    })
+}
+
+/// Returns rshell's configuration directory: `$XDG_CONFIG_HOME/rshell`, otherwise
+/// `~/.config/rshell`.
+#[must_use]
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config_dir) if !config_dir.is_empty() => PathBuf::from(config_dir),
+        _ => home_dir()?.join(".config"),
+    };
+
+    Some(config_dir.join("rshell"))
+}
+
+/// Returns the machine's host name.
+#[must_use]
+pub fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+
+    // SAFETY: the buffer is valid for its length, and `gethostname` null-terminates on success
+    // except on truncation, which the last byte guards against.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len() - 1) };
+    if result != 0 {
+        return None;
+    }
+
+    CStr::from_bytes_until_nul(&buffer)
+        .ok()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Asks `question` on the terminal, returning whether the answer is yes. Nothing is asked, and
+/// the answer is no, when there is no terminal.
+#[must_use]
+pub fn confirm(question: &str) -> bool {
+    if !termion::is_tty(&std::io::stdin()) {
+        return false;
+    }
+
+    eprint!("rshell: {question} [y/N] ");
+    let _ = std::io::stderr().flush();
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer).is_ok()
+        && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Sets `$USER` and `$HOSTNAME` if they are missing, so that they can always be expanded, and
+/// `$PWD` to the working directory.
+pub fn init_env() {
+    if std::env::var_os("USER").is_none() {
+        if let Some(passwd) = passwd() {
+            std::env::set_var("USER", passwd.name);
+        }
+    }
+
+    if std::env::var_os("HOSTNAME").is_none() {
+        if let Some(hostname) = hostname() {
+            std::env::set_var("HOSTNAME", hostname);
+        }
+    }
+
+    // one more level of shells than the one that started this one, counting from 1
+    let level = std::env::var("SHLVL")
+        .ok()
+        .and_then(|level| level.trim().parse::<u32>().ok())
+        .unwrap_or_default();
+    std::env::set_var("SHLVL", level.saturating_add(1).to_string());
+
+    // an inherited PWD may be stale, but is kept if it is a symlink to the working directory
+    if let Ok(current_dir) = std::env::current_dir() {
+        if std::env::var_os("PWD")
+            .is_none_or(|pwd| std::fs::canonicalize(pwd).ok().as_ref() != Some(&current_dir))
+        {
+            std::env::set_var("PWD", current_dir);
+        }
+    }
+}
+
+/// Returns the passwd entry of the current user.
+fn passwd() -> Option<Passwd> {
+    // SAFETY: the arguments are passed through from `lookup`.
+    lookup(|passwd, buffer, length, result| unsafe {
+        libc::getpwuid_r(libc::getuid(), passwd, buffer, length, result)
+    })
+}
+
+/// Returns the passwd entry of the user `name`.
+fn passwd_of(name: &str) -> Option<Passwd> {
+    let name = CString::new(name).ok()?;
+
+    // SAFETY: the arguments are passed through from `lookup`, and `name` is null-terminated.
+    lookup(|passwd, buffer, length, result| unsafe {
+        libc::getpwnam_r(name.as_ptr(), passwd, buffer, length, result)
+    })
+}
+
+/// Returns the passwd entry found by `get`, one of the `getpw*_r` functions given the entry, a
+/// buffer and its length, and where to store the result.
+fn lookup(
+    get: impl FnOnce(
+        *mut libc::passwd,
+        *mut libc::c_char,
+        libc::size_t,
+        *mut *mut libc::passwd,
+    ) -> libc::c_int,
+) -> Option<Passwd> {
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+    let mut result = std::ptr::null_mut();
+
+    // all pointers are valid, and the buffer is valid for its length
+    let code = get(
+        passwd.as_mut_ptr(),
+        buffer.as_mut_ptr(),
+        buffer.len(),
+        &raw mut result,
+    );
+
+    if code != 0 || result.is_null() {
+        return None;
+    }
+
+    // SAFETY: the lookup succeeded, so the entry is initialized and its strings point into the
+    // buffer, which is still alive.
+    unsafe {
+        let passwd = passwd.assume_init();
+
+        Some(Passwd {
+            name: CStr::from_ptr(passwd.pw_name)
+                .to_string_lossy()
+                .into_owned(),
+            dir: PathBuf::from(OsStr::from_bytes(CStr::from_ptr(passwd.pw_dir).to_bytes())),
+        })
+    }
+}
@@ -0,0 +1,291 @@
+//! Shell variables, set with `NAME=VALUE` and only seen by the shell itself, and the positional
+//! parameters of a script.
+//!
+//! `$NAME` expands to the shell variable `NAME` if there is one, and to the environment variable
+//! otherwise. `export NAME` moves a shell variable to the environment, where the commands the
+//! shell runs see it.
+//!
+//! `$1` to `$9`, or `${N}` past them, expand to the positional parameters, the arguments of a
+//! script or those given to `set --`. `$#` is how many there are, and `$*` all of them joined
+//! with spaces, like `$@` inside a word that can't be split.
+//!
+//! Special parameters are looked up before any variable: `$$` is the process ID of the shell,
+//! `$!` the one of the job started last, `$0` the name of the shell or script, `$RANDOM` a
+//! random number from 0 to 32767, `$SECONDS` how long the shell has been running, `$LINENO`
+//! the number of the line being run and `$PIPESTATUS` the exit codes of the commands of the last
+//! pipeline, the first one unless an element is picked with `${PIPESTATUS[N]}`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    sync::{Mutex, MutexGuard, OnceLock, PoisonError},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::JOBS;
+
+/// Where a variable is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// A shell variable, only seen by the shell.
+    Shell,
+    /// An environment variable, seen by the commands the shell runs too.
+    Environment,
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Shell => "shell",
+            Self::Environment => "environment",
+        })
+    }
+}
+
+/// The state of the variables.
+#[derive(Debug, Default)]
+struct Variables {
+    values: HashMap<String, String>,
+    positional: Vec<String>,
+    /// `$0`, if not the name the shell was run with.
+    name: Option<String>,
+    /// `$LINENO`.
+    line: usize,
+    /// `$PIPESTATUS`.
+    pipe_status: Vec<i32>,
+    /// The state of the generator of `$RANDOM`, seeded on first use.
+    random: u64,
+}
+
+static VARIABLES: Mutex<Option<Variables>> = Mutex::new(None);
+
+/// When the shell started, for `$SECONDS`.
+static START: OnceLock<Instant> = OnceLock::new();
+
+fn variables() -> MutexGuard<'static, Option<Variables>> {
+    VARIABLES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns whether `name` can be the name of a variable: a letter or `_`, then letters, digits
+/// and `_`s.
+#[must_use]
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|char| char.is_ascii_alphabetic() || char == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+/// Starts the clock of `$SECONDS`, as the shell starts.
+pub fn init() {
+    START.get_or_init(Instant::now);
+}
+
+/// Returns the value of the shell variable `name`, or of the environment variable if there is no
+/// shell variable. `name` may also be the number of a positional parameter or a special
+/// parameter, like `#` or `RANDOM`.
+#[must_use]
+pub fn get(name: &str) -> Option<String> {
+    if let Some(value) = special(name) {
+        return Some(value);
+    }
+    if let Ok(index) = name.parse::<usize>() {
+        return index
+            .checked_sub(1)
+            .and_then(|index| positional().get(index).cloned());
+    }
+
+    variables()
+        .as_ref()
+        .and_then(|variables| variables.values.get(name).cloned())
+        .or_else(|| std::env::var(name).ok())
+}
+
+/// Returns the value of the special parameter `name`, or `None` if it isn't one.
+fn special(name: &str) -> Option<String> {
+    let value = match name {
+        "#" => positional().len().to_string(),
+        "*" | "@" => positional().join(" "),
+        "$" => std::process::id().to_string(),
+        "!" => JOBS
+            .try_lock()
+            .ok()
+            .and_then(|jobs| jobs.last_pid())
+            .map(|pid| pid.to_string())
+            .unwrap_or_default(),
+        "0" => variables()
+            .as_ref()
+            .and_then(|variables| variables.name.clone())
+            .or_else(|| std::env::args().next())
+            .unwrap_or_default(),
+        "RANDOM" => random().to_string(),
+        "SECONDS" => START
+            .get()
+            .map(|start| start.elapsed().as_secs())
+            .unwrap_or_default()
+            .to_string(),
+        "LINENO" => line().to_string(),
+        "PIPESTATUS" => pipe_status().first()?.clone(),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Returns the next number of `$RANDOM`, from 0 to 32767.
+fn random() -> u16 {
+    let mut variables = variables();
+    let state = &mut variables.get_or_insert_with(Variables::default).random;
+
+    if *state == 0 {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.subsec_nanos())
+            .unwrap_or_default();
+        *state = (u64::from(nanos) << 32 | u64::from(std::process::id())) | 1;
+    }
+
+    // xorshift, keeping the top 15 bits
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    u16::try_from(*state >> 49).unwrap_or_default()
+}
+
+/// Sets `$0`, for a script.
+pub fn set_name(name: String) {
+    variables().get_or_insert_with(Variables::default).name = Some(name);
+}
+
+/// Returns `$LINENO`, the number of the line running.
+#[must_use]
+pub fn line() -> usize {
+    variables()
+        .as_ref()
+        .map(|variables| variables.line)
+        .unwrap_or_default()
+}
+
+/// Sets `$LINENO`, as the line `line` starts running.
+pub fn set_line(line: usize) {
+    variables().get_or_insert_with(Variables::default).line = line;
+}
+
+/// Returns `$PIPESTATUS`, the exit codes of the commands of the last pipeline, in order.
+#[must_use]
+pub fn pipe_status() -> Vec<String> {
+    variables()
+        .as_ref()
+        .map(|variables| {
+            variables
+                .pipe_status
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Sets `$PIPESTATUS`, as a pipeline whose commands exited with `codes` ends.
+pub fn set_pipe_status(codes: Vec<i32>) {
+    variables()
+        .get_or_insert_with(Variables::default)
+        .pipe_status = codes;
+}
+
+/// Returns the elements of `name`, which are the exit codes of `$PIPESTATUS`, or the value of
+/// any other variable alone.
+#[must_use]
+pub fn elements(name: &str) -> Vec<String> {
+    if name == "PIPESTATUS" {
+        return pipe_status();
+    }
+    get(name).into_iter().collect()
+}
+
+/// Sets `name` to `value`, in the environment if it is already there, as a shell variable
+/// otherwise.
+pub fn set(name: &str, value: &str) {
+    if std::env::var_os(name).is_some() {
+        std::env::set_var(name, value);
+    } else {
+        variables()
+            .get_or_insert_with(Variables::default)
+            .values
+            .insert(name.to_string(), value.to_string());
+    }
+}
+
+/// Removes `name`, both as a shell variable and from the environment.
+pub fn unset(name: &str) {
+    if let Some(variables) = variables().as_mut() {
+        variables.values.remove(name);
+    }
+    std::env::remove_var(name);
+}
+
+/// Moves the shell variable `name` to the environment, setting it to `value` if given.
+pub fn export(name: &str, value: Option<&str>) {
+    let local = variables()
+        .as_mut()
+        .and_then(|variables| variables.values.remove(name));
+
+    if let Some(value) = value.map(String::from).or(local) {
+        std::env::set_var(name, value);
+    }
+}
+
+/// Returns the names of the shell and environment variables, sorted.
+#[must_use]
+pub fn names() -> Vec<String> {
+    let mut names = std::env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok())
+        .chain(
+            variables()
+                .iter()
+                .flat_map(|variables| variables.values.keys().cloned()),
+        )
+        .collect::<Vec<_>>();
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Returns the value and scope of every variable, a shell variable hiding the environment
+/// variable of the same name.
+#[must_use]
+pub fn all() -> BTreeMap<String, (String, Scope)> {
+    let mut all = std::env::vars_os()
+        .filter_map(|(name, value)| {
+            let value = value.to_string_lossy().into_owned();
+            Some((name.into_string().ok()?, (value, Scope::Environment)))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    if let Some(variables) = variables().as_ref() {
+        for (name, value) in &variables.values {
+            all.insert(name.clone(), (value.clone(), Scope::Shell));
+        }
+    }
+
+    all
+}
+
+/// Returns the positional parameters, `$1` first.
+#[must_use]
+pub fn positional() -> Vec<String> {
+    variables()
+        .as_ref()
+        .map(|variables| variables.positional.clone())
+        .unwrap_or_default()
+}
+
+/// Replaces the positional parameters with `args`.
+pub fn set_positional(args: Vec<String>) {
+    variables()
+        .get_or_insert_with(Variables::default)
+        .positional = args;
+}
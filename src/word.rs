@@ -0,0 +1,629 @@
+//! Words of commands, made of pieces some of which are only expanded when the command runs, so
+//! that they see what the commands before them did, like `$?` in `false; echo $?` or `$PWD` in
+//! `cd /tmp; echo "$PWD"`.
+//!
+//! Parameters are expanded outside of quotes and inside double quotes, but not single quotes.
+//! Outside of quotes, what parameters and command substitutions expand to is split into fields
+//! on the characters of `$IFS`, and removed if it is empty.
+
+use std::fmt::Display;
+
+use async_recursion::async_recursion;
+
+use crate::{
+    editor::quote, glob, list::List, log, options::ShellOption, substitution, variables, OPTIONS,
+    PREVIOUS_EXIT_CODE,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Piece {
+    Text(String),
+    /// `$?`, the exit code of the previous command.
+    Status,
+    /// `$NAME` or `${NAME}`, the value of a shell or environment variable, or a special
+    /// parameter like `$1` or `$#`.
+    Variable(String),
+    /// `$@`, or `$*` outside of quotes, the positional parameters as separate words.
+    Arguments,
+    /// `${NAME...}` with an operator, like `${NAME:-WORD}` or `${#NAME}`.
+    Parameter(Box<Parameter>),
+    /// `$(COMMAND)` or `` `COMMAND` ``, the output of `COMMAND`, run in a subshell.
+    Substitution(List),
+    /// A piece outside of quotes, split into fields on the characters of `$IFS`, like `$NAME` or
+    /// the output of `$(COMMAND)`.
+    Split(Box<Self>),
+}
+
+/// The fields of `$IFS` when it isn't set.
+const DEFAULT_IFS: &str = " \t\n";
+
+impl Piece {
+    /// Returns the piece expanded, without splitting it, and `$@` joined with spaces.
+    #[async_recursion]
+    async fn expand(&self) -> Result<String, String> {
+        Ok(match self {
+            Self::Text(text) => text.clone(),
+            Self::Status => PREVIOUS_EXIT_CODE.lock().await.to_string(),
+            Self::Variable(name) => {
+                let Some(value) = variables::get(name) else {
+                    unbound(name).await?;
+                    return Ok(String::new());
+                };
+                log!(Debug, "expansion", "${name} -> {value}");
+                value
+            }
+            Self::Arguments => variables::positional().join(" "),
+            Self::Parameter(parameter) => parameter.expand().await?,
+            Self::Substitution(list) => {
+                let output = substitution::output(list).await;
+                log!(Debug, "expansion", "$({list}) -> {output:?}");
+                output
+            }
+            Self::Split(piece) => piece.expand().await?,
+        })
+    }
+
+    /// Returns the text of the piece, which is only complete once it was expanded.
+    fn text(&self) -> String {
+        match self {
+            Self::Text(text) => text.clone(),
+            Self::Status => String::from("$?"),
+            Self::Variable(name) => format!("${{{name}}}"),
+            Self::Arguments => String::from("$@"),
+            Self::Parameter(parameter) => format!("${{{}}}", parameter.source),
+            Self::Substitution(list) => format!("$({list})"),
+            Self::Split(piece) => piece.text(),
+        }
+    }
+}
+
+/// The fields a word is split into as it expands.
+#[derive(Default)]
+struct Fields {
+    ended: Vec<String>,
+    field: String,
+    /// Whether the field is kept even if it is empty, like `""` or `"$EMPTY"`.
+    kept: bool,
+}
+
+impl Fields {
+    /// Adds `text` to the field, which is then kept.
+    fn push(&mut self, text: &str) {
+        self.field.push_str(text);
+        self.kept = true;
+    }
+
+    /// Ends the field, keeping it even if it is empty, like between the words of `"$@"`.
+    fn end(&mut self) {
+        self.ended.push(std::mem::take(&mut self.field));
+        self.kept = false;
+    }
+
+    /// Ends the field at a delimiter, keeping it if it isn't empty or was kept, or always at a
+    /// `hard` delimiter, one that isn't whitespace.
+    fn delimit(&mut self, hard: bool) {
+        if hard || self.kept || !self.field.is_empty() {
+            self.end();
+        }
+    }
+
+    /// Adds `text`, split on the characters of `ifs`. Whitespace around a delimiter is part of
+    /// it, and whitespace alone delimits only non-empty fields.
+    fn split(&mut self, text: &str, ifs: &str) {
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if !ifs.contains(c) {
+                self.field.push(c);
+                continue;
+            }
+
+            // the whole delimiter, with a field between each non-whitespace character
+            let mut hard = usize::from(!c.is_whitespace());
+            while let Some(&c) = chars.peek().filter(|&&c| ifs.contains(c)) {
+                hard += usize::from(!c.is_whitespace());
+                chars.next();
+            }
+
+            if hard == 0 {
+                self.delimit(false);
+            }
+            for _ in 0..hard {
+                self.delimit(true);
+            }
+        }
+    }
+
+    /// Returns the fields, with the last one unless it is empty and wasn't kept.
+    fn finish(mut self) -> Vec<String> {
+        if self.kept || !self.field.is_empty() {
+            self.end();
+        }
+        self.ended
+    }
+}
+
+/// A parameter expanded with an operator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Parameter {
+    /// The name of the variable or special parameter, or the prefix of [`Operator::Names`].
+    pub name: String,
+    pub operator: Operator,
+    /// What is between the braces, to show the parameter as it was written.
+    pub source: String,
+}
+
+/// What is done with the value of a parameter. The operators taking a word only apply to an unset
+/// parameter, or also to an empty one with `colon`, the `:` before them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operator {
+    /// `${!NAME}`, the value of the variable named by the value of `NAME`.
+    Indirect,
+    /// `${!PREFIX*}` and `${!PREFIX@}`, the names of the variables starting with `PREFIX`.
+    Names,
+    /// `${#NAME}`, the number of characters of the value.
+    Length,
+    /// `${NAME[N]}`, the element `N` from 0 of `NAME`, which only `PIPESTATUS` has more than one
+    /// of, or all of them joined with spaces for `${NAME[@]}` and `${NAME[*]}`.
+    Index(Option<usize>),
+    /// `${NAME:-WORD}`, `WORD` instead of the value.
+    Default { colon: bool, word: Word },
+    /// `${NAME:=WORD}`, `WORD`, which `NAME` is set to.
+    Assign { colon: bool, word: Word },
+    /// `${NAME:+WORD}`, `WORD` if the parameter is set, nothing otherwise.
+    Alternative { colon: bool, word: Word },
+    /// `${NAME:?WORD}`, an error with the message `WORD`.
+    Error { colon: bool, word: Word },
+    /// `${NAME#PATTERN}`, the value without the shortest prefix matching `PATTERN`, or the
+    /// longest one with `##`.
+    RemovePrefix { longest: bool, pattern: Word },
+    /// `${NAME%PATTERN}`, the value without the shortest suffix matching `PATTERN`, or the
+    /// longest one with `%%`.
+    RemoveSuffix { longest: bool, pattern: Word },
+    /// `${NAME/PATTERN/WORD}`, the value with the first longest match of `PATTERN` replaced by
+    /// `WORD`, or every match with `//`.
+    Replace {
+        all: bool,
+        pattern: Word,
+        replacement: Word,
+    },
+}
+
+impl Operator {
+    /// Returns whether the operator looks at whether the parameter is set, and so takes one that
+    /// isn't with `set -u`.
+    const fn tests_unset(&self) -> bool {
+        matches!(
+            self,
+            Self::Names
+                | Self::Default { .. }
+                | Self::Assign { .. }
+                | Self::Alternative { .. }
+                | Self::Error { .. }
+        )
+    }
+}
+
+impl Parameter {
+    /// Returns the expanded parameter, or the error of `${NAME:?WORD}`.
+    async fn expand(&self) -> Result<String, String> {
+        let name = &self.name;
+        let value = variables::get(name);
+        let is_unset = |colon: bool| value.as_ref().is_none_or(|value| colon && value.is_empty());
+
+        if value.is_none() && !self.operator.tests_unset() {
+            unbound(name).await?;
+        }
+
+        let expanded = match &self.operator {
+            Operator::Indirect => value
+                .and_then(|name| variables::get(&name))
+                .unwrap_or_default(),
+            Operator::Names => variables::names()
+                .into_iter()
+                .filter(|variable| variable.starts_with(name.as_str()))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Operator::Length => value.unwrap_or_default().chars().count().to_string(),
+            Operator::Index(index) => {
+                let elements = variables::elements(name);
+                match index {
+                    Some(index) => elements.get(*index).cloned().unwrap_or_default(),
+                    None => elements.join(" "),
+                }
+            }
+            Operator::Default { colon, word } => {
+                if is_unset(*colon) {
+                    word.expand().await?
+                } else {
+                    value.unwrap_or_default()
+                }
+            }
+            Operator::Assign { colon, word } => {
+                if is_unset(*colon) {
+                    if !variables::is_name(name) {
+                        return Err(format!("${{{name}}}: cannot assign in this way"));
+                    }
+                    let word = word.expand().await?;
+                    variables::set(name, &word);
+                    word
+                } else {
+                    value.unwrap_or_default()
+                }
+            }
+            Operator::Alternative { colon, word } => {
+                if is_unset(*colon) {
+                    String::new()
+                } else {
+                    word.expand().await?
+                }
+            }
+            Operator::Error { colon, word } => {
+                if is_unset(*colon) {
+                    let message = word.expand().await?;
+                    return Err(if message.is_empty() {
+                        format!("{name}: parameter null or not set")
+                    } else {
+                        format!("{name}: {message}")
+                    });
+                }
+                value.unwrap_or_default()
+            }
+            Operator::RemovePrefix { longest, pattern } => remove_prefix(
+                &value.unwrap_or_default(),
+                &pattern.expand().await?,
+                *longest,
+            ),
+            Operator::RemoveSuffix { longest, pattern } => remove_suffix(
+                &value.unwrap_or_default(),
+                &pattern.expand().await?,
+                *longest,
+            ),
+            Operator::Replace {
+                all,
+                pattern,
+                replacement,
+            } => replace(
+                &value.unwrap_or_default(),
+                &pattern.expand().await?,
+                &replacement.expand().await?,
+                *all,
+            ),
+        };
+
+        log!(Debug, "expansion", "${{{}}} -> {expanded}", self.source);
+        Ok(expanded)
+    }
+}
+
+/// Fails with `set -u`, as the parameter `name` isn't set.
+async fn unbound(name: &str) -> Result<(), String> {
+    if OPTIONS.lock().await.is_set(ShellOption::NoUnset) {
+        return Err(format!("{name}: unbound variable"));
+    }
+    Ok(())
+}
+
+/// Returns `value` without its shortest prefix matching `pattern`, or its longest one if
+/// `longest` is set.
+fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let ends = value
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([value.len()])
+        .collect::<Vec<_>>();
+    let mut ends = ends.into_iter();
+
+    let end = if longest {
+        ends.rev()
+            .find(|&end| glob::matches(pattern, &value[..end], false))
+    } else {
+        ends.find(|&end| glob::matches(pattern, &value[..end], false))
+    };
+    value[end.unwrap_or_default()..].to_string()
+}
+
+/// Returns `value` without its shortest suffix matching `pattern`, or its longest one if
+/// `longest` is set.
+fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let starts = value
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([value.len()])
+        .collect::<Vec<_>>();
+    let mut starts = starts.into_iter();
+
+    let start = if longest {
+        starts.find(|&start| glob::matches(pattern, &value[start..], false))
+    } else {
+        starts
+            .rev()
+            .find(|&start| glob::matches(pattern, &value[start..], false))
+    };
+    value[..start.unwrap_or(value.len())].to_string()
+}
+
+/// Replaces the first longest match of `pattern` in `value` by `replacement`, or every match if
+/// `all` is set.
+fn replace(value: &str, pattern: &str, replacement: &str, all: bool) -> String {
+    if pattern.is_empty() {
+        return value.to_string();
+    }
+
+    let bounds = value
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([value.len()])
+        .collect::<Vec<_>>();
+    let mut replaced = String::new();
+    let mut start = 0;
+
+    while start < bounds.len() - 1 {
+        let from = bounds[start];
+        let end = bounds[start + 1..]
+            .iter()
+            .rposition(|&to| glob::matches(pattern, &value[from..to], false));
+
+        if let Some(end) = end {
+            replaced.push_str(replacement);
+            start += end + 1;
+
+            if !all {
+                replaced.push_str(&value[bounds[start]..]);
+                return replaced;
+            }
+        } else {
+            replaced.push_str(&value[from..bounds[start + 1]]);
+            start += 1;
+        }
+    }
+
+    replaced
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Word {
+    pieces: Vec<Piece>,
+}
+
+impl Word {
+    /// Adds `piece` to the end of the word, joining text to the text before it.
+    pub fn push(&mut self, piece: Piece) {
+        match (self.pieces.last_mut(), piece) {
+            (_, Piece::Text(more)) if more.is_empty() => {}
+            (Some(Piece::Text(text)), Piece::Text(more)) => text.push_str(&more),
+            (_, piece) => self.pieces.push(piece),
+        }
+    }
+
+    /// Adds the pieces of `word` to the end of the word.
+    pub fn append(&mut self, word: Self) {
+        for piece in word.pieces {
+            self.push(piece);
+        }
+    }
+
+    /// Splits a `NAME=VALUE` word into the name and the value.
+    #[must_use]
+    pub fn assignment(&self) -> Option<(String, Self)> {
+        let Some(Piece::Text(text)) = self.pieces.first() else {
+            return None;
+        };
+        let (name, value) = text.split_once('=')?;
+
+        let value = std::iter::once(Piece::Text(value.to_string()))
+            .chain(self.pieces[1..].iter().cloned())
+            .collect();
+        Some((name.to_string(), value))
+    }
+
+    /// Returns the pieces of the word, in order.
+    #[must_use]
+    pub fn pieces(&self) -> &[Piece] {
+        &self.pieces
+    }
+
+    /// Returns whether the word is only text, with nothing to expand.
+    #[must_use]
+    pub fn is_text(&self) -> bool {
+        self.pieces
+            .iter()
+            .all(|piece| matches!(piece, Piece::Text(_)))
+    }
+
+    /// Returns the text of the word, which is only complete once it was expanded.
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.pieces.iter().map(Piece::text).collect()
+    }
+
+    /// Returns the word with its pieces expanded, as a single word without splitting it, like
+    /// the value of `NAME=VALUE`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the message of a `${NAME:?WORD}` parameter that isn't set.
+    pub async fn expand(&self) -> Result<String, String> {
+        let mut word = String::new();
+        for piece in &self.pieces {
+            word.push_str(&piece.expand().await?);
+        }
+        Ok(word)
+    }
+
+    /// Returns the fields the word expands to. `"$@"` ends a field at every positional parameter
+    /// but the last, and expands to no field on its own without any. The pieces outside of
+    /// quotes are split on the characters of `$IFS`, space, tab and newline if it isn't set, and
+    /// expand to no field if they are all empty.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the message of a `${NAME:?WORD}` parameter that isn't set.
+    pub async fn fields(&self) -> Result<Vec<String>, String> {
+        if self.pieces == [Piece::Arguments] {
+            return Ok(variables::positional());
+        }
+
+        let ifs = variables::get("IFS").unwrap_or_else(|| String::from(DEFAULT_IFS));
+        let mut fields = Fields {
+            // `''` is an empty field
+            kept: self.pieces.is_empty(),
+            ..Fields::default()
+        };
+
+        for piece in &self.pieces {
+            match piece {
+                Piece::Arguments => {
+                    for (index, arg) in variables::positional().iter().enumerate() {
+                        if index > 0 {
+                            fields.end();
+                        }
+                        fields.push(arg);
+                    }
+                }
+                Piece::Split(piece) if **piece == Piece::Arguments => {
+                    for (index, arg) in variables::positional().iter().enumerate() {
+                        if index > 0 {
+                            fields.delimit(false);
+                        }
+                        fields.split(arg, &ifs);
+                    }
+                }
+                Piece::Split(piece) => fields.split(&piece.expand().await?, &ifs),
+                piece => fields.push(&piece.expand().await?),
+            }
+        }
+
+        Ok(fields.finish())
+    }
+}
+
+impl From<String> for Word {
+    fn from(text: String) -> Self {
+        Self {
+            pieces: vec![Piece::Text(text)],
+        }
+    }
+}
+
+impl FromIterator<Piece> for Word {
+    fn from_iter<T: IntoIterator<Item = Piece>>(pieces: T) -> Self {
+        let mut word = Self::default();
+        for piece in pieces {
+            word.push(piece);
+        }
+        word
+    }
+}
+
+impl Display for Word {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => f.write_str(&quote(text))?,
+                Piece::Status => f.write_str("$?")?,
+                Piece::Variable(name) => write!(f, "\"${{{name}}}\"")?,
+                Piece::Arguments => f.write_str("\"$@\"")?,
+                Piece::Parameter(parameter) => write!(f, "\"${{{}}}\"", parameter.source)?,
+                Piece::Substitution(list) => write!(f, "\"$({list})\"")?,
+                Piece::Split(piece) => match &**piece {
+                    Piece::Text(text) => f.write_str(text)?,
+                    Piece::Arguments => f.write_str("$@")?,
+                    piece => write!(f, "{}", piece.text())?,
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lang::scanner::Scanner;
+
+    use super::*;
+
+    /// Scans `word` as the argument of `echo`.
+    async fn scan(word: &str) -> Word {
+        let tokens = Scanner::new(&format!("echo {word}")).scan_tokens().await;
+        tokens[1]
+            .word
+            .clone()
+            .unwrap_or_else(|| Word::from(tokens[1].lexeme.clone()))
+    }
+
+    async fn expand(word: &str) -> Result<String, String> {
+        scan(word).await.expand().await
+    }
+
+    async fn fields(word: &str) -> Vec<String> {
+        scan(word).await.fields().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn operators() {
+        variables::set("WORD_TEST_PATH", "dir/file.tar.gz");
+
+        assert_eq!(expand("${#WORD_TEST_PATH}").await.as_deref(), Ok("15"));
+        assert_eq!(
+            expand("${WORD_TEST_PATH%.*}").await.as_deref(),
+            Ok("dir/file.tar")
+        );
+        assert_eq!(
+            expand("${WORD_TEST_PATH%%.*}").await.as_deref(),
+            Ok("dir/file")
+        );
+        assert_eq!(
+            expand("${WORD_TEST_PATH#*/}").await.as_deref(),
+            Ok("file.tar.gz")
+        );
+        assert_eq!(
+            expand("${WORD_TEST_PATH//./-}").await.as_deref(),
+            Ok("dir/file-tar-gz")
+        );
+        assert_eq!(
+            expand("${WORD_TEST_PATH/.//}").await.as_deref(),
+            Ok("dir/file/tar.gz")
+        );
+        assert_eq!(
+            expand("${WORD_TEST_UNSET:-default}").await.as_deref(),
+            Ok("default")
+        );
+        assert_eq!(expand("${WORD_TEST_PATH:+set}").await.as_deref(), Ok("set"));
+        assert_eq!(expand("${WORD_TEST_UNSET:+set}").await.as_deref(), Ok(""));
+    }
+
+    #[tokio::test]
+    async fn assign_sets_the_variable() {
+        assert_eq!(
+            expand("${WORD_TEST_ASSIGN:=value}").await.as_deref(),
+            Ok("value")
+        );
+        assert_eq!(variables::get("WORD_TEST_ASSIGN").as_deref(), Some("value"));
+    }
+
+    #[tokio::test]
+    async fn unset_error_is_its_message() {
+        let error = expand("${WORD_TEST_UNSET:?not set}").await.unwrap_err();
+
+        assert!(error.contains("not set"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn unquoted_parameters_are_split() {
+        variables::set("WORD_TEST_SPLIT", " a  b ");
+
+        assert_eq!(fields("$WORD_TEST_SPLIT").await, ["a", "b"]);
+        assert_eq!(fields("x${WORD_TEST_SPLIT}y").await, ["x", "a", "b", "y"]);
+        assert_eq!(fields("\"$WORD_TEST_SPLIT\"").await, [" a  b "]);
+        assert_eq!(fields("'$WORD_TEST_SPLIT'").await, ["$WORD_TEST_SPLIT"]);
+    }
+
+    #[tokio::test]
+    async fn empty_words() {
+        assert_eq!(fields("$WORD_TEST_UNSET").await, Vec::<String>::new());
+        assert_eq!(fields("\"$WORD_TEST_UNSET\"").await, [""]);
+        assert_eq!(fields("''").await, [""]);
+    }
+}